@@ -0,0 +1,98 @@
+/* Notice
+complexity.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! Checks the documented complexity of the main operations by timing each of them on a small and
+//! a large store, and failing if the cost per operation grows by more than `MAX_GROWTH` between
+//! the two. An accidental linear scan in an O(1) operation grows its cost a thousandfold.
+
+extern crate lending_library;
+
+use lending_library::LendingLibrary;
+use std::{hint::black_box,
+          process,
+          time::{Duration, Instant}};
+
+const SMALL: u64 = 1_000;
+const LARGE: u64 = 1_000_000;
+const MAX_GROWTH: f64 = 10.0;
+
+fn filled(n: u64) -> LendingLibrary<u64, u64> {
+    let mut lib = LendingLibrary::with_capacity(n as usize);
+    for i in 0..n {
+        lib.insert(i, i);
+    }
+    lib
+}
+
+/// Returns the best time per call of `f` over several rounds, each of which runs for at least
+/// 20ms.
+fn per_call<F: FnMut()>(mut f: F) -> f64 {
+    let mut best = f64::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        let mut calls = 0u64;
+        while start.elapsed() < Duration::from_millis(20) {
+            for _ in 0..64 {
+                f();
+            }
+            calls += 64;
+        }
+        best = best.min(start.elapsed().as_secs_f64() / calls as f64);
+    }
+    best
+}
+
+type Op = fn(&mut LendingLibrary<u64, u64>, u64);
+
+/// Returns the time per call of `op` on a store of `n` entries, or per entry if `linear` is set.
+fn cost(n: u64, op: Op, linear: bool) -> f64 {
+    let mut lib = filled(n);
+    let t = per_call(|| op(&mut lib, n));
+    if linear {
+        t / n as f64
+    } else {
+        t
+    }
+}
+
+fn main() {
+    let checks: Vec<(&str, Op, bool)> = vec![
+        ("lend and return", |lib, n| {
+            black_box(*lib.lend(black_box(&(n / 2))).unwrap());
+        }, false),
+        ("insert and remove", |lib, n| {
+            lib.insert(black_box(n), n);
+            lib.remove(black_box(&n));
+        }, false),
+        ("contains_key", |lib, n| {
+            black_box(lib.contains_key(black_box(&(n / 2))));
+        }, false),
+        ("len", |lib, _n| {
+            black_box(black_box(&*lib).len());
+        }, false),
+        ("iterate", |lib, _n| {
+            black_box(lib.iter().map(|(_k, v)| *v).sum::<u64>());
+        }, true),
+    ];
+    let mut failed = false;
+    for (name, op, linear) in checks {
+        let growth = cost(LARGE, op, linear) / cost(SMALL, op, linear);
+        let verdict = if growth > MAX_GROWTH {
+            failed = true;
+            "FAILED"
+        } else {
+            "ok"
+        };
+        println!("{:<20} {:>8.2}x  {}", name, growth, verdict);
+    }
+    if failed {
+        eprintln!("cost per operation grew by more than {}x", MAX_GROWTH);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,112 @@
+/* Notice
+lending.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+#[macro_use]
+extern crate criterion;
+extern crate lending_library;
+
+use criterion::{BenchmarkId, Criterion};
+use lending_library::{slab::LendingSlab, LendingLibrary};
+use std::hint::black_box;
+
+const SIZES: &[u64] = &[100, 10_000, 1_000_000];
+
+fn filled(n: u64) -> LendingLibrary<u64, u64> {
+    let mut lib = LendingLibrary::with_capacity(n as usize);
+    for i in 0..n {
+        lib.insert(i, i);
+    }
+    lib
+}
+
+fn lend_checkin(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lend_checkin");
+    for &n in SIZES {
+        let mut lib = filled(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            b.iter(|| {
+                let v = lib.lend(black_box(&(n / 2))).unwrap();
+                black_box(*v);
+            })
+        });
+    }
+    group.finish();
+}
+
+fn slab_lend_checkin(c: &mut Criterion) {
+    let mut group = c.benchmark_group("slab_lend_checkin");
+    for &n in SIZES {
+        let mut lib: LendingSlab<u64> = (0..n).map(|i| (i as usize, i)).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            b.iter(|| {
+                let v = lib.lend(black_box(n as usize / 2)).unwrap();
+                black_box(*v);
+            })
+        });
+    }
+    group.finish();
+}
+
+fn insert_remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_remove");
+    for &n in SIZES {
+        let mut lib = filled(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            b.iter(|| {
+                lib.insert(black_box(n), n);
+                lib.remove(black_box(&n));
+            })
+        });
+    }
+    group.finish();
+}
+
+fn contains_key(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contains_key");
+    for &n in SIZES {
+        let lib = filled(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            b.iter(|| lib.contains_key(black_box(&(n / 2))))
+        });
+    }
+    group.finish();
+}
+
+fn len(c: &mut Criterion) {
+    let mut group = c.benchmark_group("len");
+    for &n in SIZES {
+        let lib = filled(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, _| {
+            b.iter(|| black_box(&lib).len())
+        });
+    }
+    group.finish();
+}
+
+fn iterate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterate");
+    for &n in SIZES {
+        let lib = filled(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, _| {
+            b.iter(|| lib.iter().map(|(_k, v)| *v).sum::<u64>())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    lend_checkin,
+    slab_lend_checkin,
+    insert_remove,
+    contains_key,
+    len,
+    iterate
+);
+criterion_main!(benches);
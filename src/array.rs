@@ -0,0 +1,449 @@
+/* Notice
+array.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A fixed-capacity variant of `LendingLibrary` that stores its entries inline, without
+//! allocating.
+
+use std::{array,
+          fmt::{Debug, Error as FmtError, Formatter},
+          ops::{Deref, DerefMut},
+          process,
+          sync::{atomic::{AtomicUsize, Ordering},
+                 LockResult,
+                 PoisonError},
+          thread};
+
+enum Slot<K, V> {
+    Vacant,
+    Present(K, V),
+    Loaned(K),
+    AwaitingDrop(K),
+}
+
+use self::Slot::{AwaitingDrop, Loaned, Present, Vacant};
+
+/// A data store that lends temporary ownership of stored values, holding up to `N` entries inline.
+///
+/// This has the same loan semantics as [`LendingLibrary`], but never allocates: entries live in
+/// an array inside the store itself and are found by comparing keys, so it is suited to small
+/// stores on targets without a heap. Its name is a `&'static str` for the same reason.
+///
+/// As loans dropped during a panic still return their values, poisoned, a store that is itself
+/// dropped during a panic while loans are outstanding aborts the process rather than leave them
+/// pointing at freed storage.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::array::LendingArray;
+/// let mut lib: LendingArray<u8, i32, 4> = LendingArray::new();
+/// lib.insert(1, 10).unwrap();
+/// {
+///     let mut v = lib.lend(&1).unwrap();
+///     *v += 1;
+///     assert!(lib.contains_key(&1));
+/// }
+/// assert_eq!(lib.get_mut(&1), Some(&mut 11));
+/// ```
+pub struct LendingArray<K, V, const N: usize>
+where
+    K: Eq,
+{
+    store: [Slot<K, V>; N],
+    poisoned: [bool; N],
+    outstanding: AtomicUsize,
+    name: Option<&'static str>,
+}
+
+/// A smart pointer representing the loan of a value from a `LendingArray` instance.
+pub struct Loan<K, V, const N: usize>
+where
+    K: Eq,
+{
+    owner: *mut LendingArray<K, V, N>,
+    index: usize,
+    inner: Option<V>,
+}
+
+fn _panic(name: Option<&str>, msg: &str) -> ! {
+    match name {
+        Some(n) => panic!("Library \"{}\": {}", n, msg),
+        None => panic!("{}", msg),
+    }
+}
+
+impl<K, V, const N: usize> LendingArray<K, V, N>
+where
+    K: Eq,
+{
+    /// Creates a new empty `LendingArray`.
+    /// # Example
+    /// ```
+    /// use lending_library::array::LendingArray;
+    /// let mut lib: LendingArray<i32, i32, 8> = LendingArray::new();
+    /// assert_eq!(lib.capacity(), 8);
+    /// ```
+    pub fn new() -> LendingArray<K, V, N> {
+        LendingArray {
+            store: array::from_fn(|_| Vacant),
+            poisoned: [false; N],
+            outstanding: AtomicUsize::new(0),
+            name: None,
+        }
+    }
+
+    /// Creates a new empty `LendingArray` with a name, which is included in any panic messages it
+    /// raises.
+    pub fn named(name: &'static str) -> LendingArray<K, V, N> {
+        let mut lib = LendingArray::new();
+        lib.name = Some(name);
+        lib
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Returns the number of entries the library can hold, which is always `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of items in the store, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.store
+            .iter()
+            .filter(|s| matches!(**s, Present(..) | Loaned(_)))
+            .count()
+    }
+
+    /// Returns true if the store is empty, including loaned items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn position(&self, key: &K) -> Option<usize> {
+        self.store.iter().position(|s| match *s {
+            Present(ref k, _) | Loaned(ref k) | AwaitingDrop(ref k) => k == key,
+            Vacant => false,
+        })
+    }
+
+    /// Returns true if a record with key `key` exists in the store, and false otherwise.
+    pub fn contains_key(&self, key: &K) -> bool {
+        match self.position(key) {
+            Some(i) => matches!(self.store[i], Present(..) | Loaned(_)),
+            None => false,
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, returning `None` if the key is
+    /// absent or the value is currently loaned.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let i = self.position(key)?;
+        match self.store[i] {
+            Present(_, ref mut v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Inserts a new key/value pair into the store. If a pair with that key already exists, the
+    /// previous value will be returned as `Ok(Some(V))`.
+    /// # Errors
+    /// Hands back the key and value if the key is new and the store is full.
+    /// # Panics
+    /// Will panic if the existing value is currently loaned or awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::array::LendingArray;
+    /// let mut lib: LendingArray<i32, i32, 1> = LendingArray::new();
+    /// assert_eq!(lib.insert(1, 1), Ok(None));
+    /// assert_eq!(lib.insert(1, 2), Ok(Some(1)));
+    /// assert_eq!(lib.insert(2, 2), Err((2, 2)));
+    /// ```
+    pub fn insert(&mut self, key: K, val: V) -> Result<Option<V>, (K, V)> {
+        let i = match self.position(&key) {
+            Some(i) => i,
+            None => match self.store.iter().position(|s| matches!(*s, Vacant)) {
+                Some(i) => i,
+                None => return Err((key, val)),
+            },
+        };
+        match self.store[i] {
+            Loaned(_) => _panic(self.name, "Cannot overwrite loaned value"),
+            AwaitingDrop(_) => _panic(self.name, "Cannot overwrite value awaiting drop"),
+            Vacant | Present(..) => {}
+        }
+        self.poisoned[i] = false;
+        match ::std::mem::replace(&mut self.store[i], Present(key, val)) {
+            Present(_, v) => Ok(Some(v)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Removes a key/value pair from the store. Returning true if the key was present in the store
+    /// and false otherwise. A loaned value is dropped when its loan is returned.
+    pub fn remove(&mut self, key: &K) -> bool {
+        let i = match self.position(key) {
+            Some(i) => i,
+            None => return false,
+        };
+        match ::std::mem::replace(&mut self.store[i], Vacant) {
+            Present(..) => true,
+            Loaned(k) => {
+                self.store[i] = AwaitingDrop(k);
+                true
+            }
+            s => {
+                self.store[i] = s;
+                false
+            }
+        }
+    }
+
+    /// Removes a key/value pair from the store, returning the value if the key was present in the
+    /// store and `None` otherwise.
+    /// # Panics
+    /// Will panic if the value is currently loaned.
+    pub fn take(&mut self, key: &K) -> Option<V> {
+        let i = self.position(key)?;
+        match self.store[i] {
+            Present(..) => match ::std::mem::replace(&mut self.store[i], Vacant) {
+                Present(_, v) => Some(v),
+                _ => unreachable!(),
+            },
+            Loaned(_) => _panic(self.name, "Cannot take loaned value"),
+            Vacant | AwaitingDrop(_) => None,
+        }
+    }
+
+    /// Loans a value from the library, returning `Some(Loan)` if the value is present, and `None`
+    /// if it is not.
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan, or that is
+    /// poisoned (see [`lend_checked()`](#method.lend_checked)).
+    pub fn lend(&mut self, key: &K) -> Option<Loan<K, V, N>> {
+        let i = self.position(key)?;
+        if self.poisoned[i] && matches!(self.store[i], Present(..)) {
+            _panic(self.name, "Lending poisoned value")
+        }
+        self.lend_index(i)
+    }
+
+    /// Loans a value from the library like [`lend()`], but rather than panicking if the value is
+    /// poisoned, returns the loan wrapped in a `PoisonError`.
+    ///
+    /// A value is poisoned when it is returned by a loan dropped during a panic, as the borrower
+    /// may have left it in an inconsistent state. It stays poisoned until [`clear_poison()`] is
+    /// called or a new value is inserted under its key.
+    ///
+    /// [`lend()`]: #method.lend
+    /// [`clear_poison()`]: #method.clear_poison
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan.
+    /// # Example
+    /// ```
+    /// use lending_library::array::LendingArray;
+    /// use std::panic::{self, AssertUnwindSafe};
+    /// let mut lib: LendingArray<i32, Vec<i32>, 2> = LendingArray::new();
+    /// lib.insert(1, vec![]).unwrap();
+    /// let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     let mut v = lib.lend(&1).unwrap();
+    ///     v.push(1);
+    ///     panic!("failed halfway through");
+    /// }));
+    /// assert!(lib.is_poisoned(&1));
+    /// let mut v = lib.lend_checked(&1).unwrap().unwrap_err().into_inner();
+    /// v.clear();
+    /// drop(v);
+    /// lib.clear_poison(&1);
+    /// assert!(lib.lend_checked(&1).unwrap().is_ok());
+    /// ```
+    pub fn lend_checked(&mut self, key: &K) -> Option<LockResult<Loan<K, V, N>>> {
+        let i = self.position(key)?;
+        let poisoned = self.poisoned[i];
+        self.lend_index(i).map(|loan| match poisoned {
+            true => Err(PoisonError::new(loan)),
+            false => Ok(loan),
+        })
+    }
+
+    /// Returns true if the value stored under `key` was returned by a loan dropped during a panic,
+    /// and has not had its poisoning cleared since.
+    pub fn is_poisoned(&self, key: &K) -> bool {
+        match self.position(key) {
+            Some(i) => self.poisoned[i] && matches!(self.store[i], Present(..) | Loaned(_)),
+            None => false,
+        }
+    }
+
+    /// Clears the poisoning of the value stored under `key`, returning true if it was poisoned.
+    pub fn clear_poison(&mut self, key: &K) -> bool {
+        match self.position(key) {
+            Some(i) => {
+                let was = self.is_poisoned(key);
+                self.poisoned[i] = false;
+                was
+            }
+            None => false,
+        }
+    }
+
+    fn lend_index(&mut self, i: usize) -> Option<Loan<K, V, N>> {
+        let ptr: *mut Self = self;
+        match self.store[i] {
+            Present(..) => {}
+            Loaned(_) => _panic(self.name, "Lending already loaned value"),
+            AwaitingDrop(_) => _panic(self.name, "Lending value awaiting drop"),
+            Vacant => unreachable!(),
+        }
+        let val = match ::std::mem::replace(&mut self.store[i], Vacant) {
+            Present(k, v) => {
+                self.store[i] = Loaned(k);
+                v
+            }
+            _ => unreachable!(),
+        };
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            index: i,
+            inner: Some(val),
+        })
+    }
+
+    /// An iterator visiting all key/value pairs in storage order.
+    /// # Panics
+    /// The iterator will panic if it encounters an item that is currently loaned from the store,
+    /// so this should only be used where you are sure you have returned all loaned items.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let name = self.name;
+        self.store.iter().filter_map(move |s| match *s {
+            Present(ref k, ref v) => Some((k, v)),
+            Loaned(_) => _panic(name, "Trying to iterate over a store with loaned items."),
+            Vacant | AwaitingDrop(_) => None,
+        })
+    }
+
+    /// An iterator visiting all keys in storage order, including those whose values are currently
+    /// loaned.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.store.iter().filter_map(|s| match *s {
+            Present(ref k, _) | Loaned(ref k) => Some(k),
+            Vacant | AwaitingDrop(_) => None,
+        })
+    }
+
+    fn checkin(&mut self, index: usize, val: V) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        match ::std::mem::replace(&mut self.store[index], Vacant) {
+            Loaned(k) => self.store[index] = Present(k, val),
+            AwaitingDrop(_) => {}
+            Present(..) => _panic(self.name, "Returning replaced item"),
+            Vacant => _panic(self.name, "Returning item not from store"),
+        }
+    }
+
+    fn checkin_poisoned(&mut self, index: usize, val: V) {
+        match self.store[index] {
+            Loaned(_) => {
+                self.poisoned[index] = true;
+                self.checkin(index, val);
+            }
+            AwaitingDrop(_) => self.checkin(index, val),
+            Vacant | Present(..) => {}
+        }
+    }
+}
+
+impl<K, V, const N: usize> Drop for LendingArray<K, V, N>
+where
+    K: Eq,
+{
+    fn drop(&mut self) {
+        let count = self.outstanding.load(Ordering::SeqCst);
+        if count != 0 {
+            if thread::panicking() {
+                // The outstanding loans will check their values in to this store as the panic
+                // unwinds past them, so it must not be freed first.
+                eprintln!("{} value loans outlived store during a panic.", count);
+                process::abort();
+            }
+            _panic(self.name, &format!("{} value loans outlived store.", count))
+        }
+    }
+}
+
+impl<K, V, const N: usize> Default for LendingArray<K, V, N>
+where
+    K: Eq,
+{
+    fn default() -> Self {
+        LendingArray::new()
+    }
+}
+
+impl<K, V, const N: usize> Debug for Loan<K, V, N>
+where
+    K: Eq,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<K, V, const N: usize> PartialEq for Loan<K, V, N>
+where
+    K: Eq,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<K, V, const N: usize> Drop for Loan<K, V, N>
+where
+    K: Eq,
+{
+    fn drop(&mut self) {
+        if let Some(val) = self.inner.take() {
+            unsafe {
+                match thread::panicking() {
+                    true => (*self.owner).checkin_poisoned(self.index, val),
+                    false => (*self.owner).checkin(self.index, val),
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, const N: usize> Deref for Loan<K, V, N>
+where
+    K: Eq,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<K, V, const N: usize> DerefMut for Loan<K, V, N>
+where
+    K: Eq,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.inner.as_mut().unwrap()
+    }
+}
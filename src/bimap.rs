@@ -0,0 +1,490 @@
+/* Notice
+bimap.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A bidirectional variant of `LendingLibrary`, whose entries can be looked up from either side.
+
+use super::_panic;
+use std::{borrow::Borrow,
+          collections::{hash_map::RandomState, HashMap},
+          fmt::{Debug, Error as FmtError, Formatter},
+          hash::{BuildHasher, Hash},
+          iter::FromIterator,
+          ops::{Deref, DerefMut},
+          sync::atomic::{AtomicUsize, Ordering},
+          thread};
+
+enum Slot<L, R> {
+    Present(L, R),
+    Loaned,
+    AwaitingDrop,
+}
+
+use self::Slot::{AwaitingDrop, Loaned, Present};
+
+struct Entry<L, R> {
+    right: u64,
+    slot: Slot<L, R>,
+}
+
+/// A data store of one-to-one `(L, R)` pairs that lends temporary ownership of its pairs, which
+/// can be found by either side.
+///
+/// An entry has a single loan state shared by both sides, so a pair loaned by its left value
+/// shows as loaned when looked up by its right value and vice versa. As with [`LendingLibrary`],
+/// each side is identified by its hash alone. If a loan changes either side of the pair, the
+/// pair is re-indexed under its new values when the loan is returned. Should the new values
+/// collide with another entry, returning the loan panics and leaves the pair indexed under its
+/// old values, with the rest of the store untouched.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::bimap::LendingBiMap;
+/// let mut handles: LendingBiMap<u32, String> = LendingBiMap::new();
+/// handles.insert(1, String::from("fd:3"));
+/// {
+///     let mut e = handles.lend_by_left(&1).unwrap();
+///     assert!(handles.is_loaned_right("fd:3"));
+///     e.1 = String::from("fd:4");
+/// }
+/// assert_eq!(handles.get_by_right("fd:4"), Some(&1));
+/// assert!(!handles.contains_right("fd:3"));
+/// ```
+pub struct LendingBiMap<L, R, S = RandomState>
+where
+    L: Hash,
+    R: Hash,
+{
+    by_left: HashMap<u64, Entry<L, R>>,
+    by_right: HashMap<u64, u64>,
+    outstanding: AtomicUsize,
+    hasher: S,
+    name: Option<String>,
+}
+
+/// A smart pointer representing the loan of a pair from a `LendingBiMap` instance.
+pub struct Loan<L, R, S = RandomState>
+where
+    L: Hash,
+    R: Hash,
+    S: BuildHasher,
+{
+    owner: *mut LendingBiMap<L, R, S>,
+    hash: u64,
+    inner: Option<(L, R)>,
+}
+
+impl<L, R> LendingBiMap<L, R, RandomState>
+where
+    L: Hash,
+    R: Hash,
+{
+    /// Creates a new empty `LendingBiMap`.
+    /// # Example
+    /// ```
+    /// use lending_library::bimap::LendingBiMap;
+    /// let mut lib: LendingBiMap<i32, &str> = LendingBiMap::new();
+    /// ```
+    pub fn new() -> LendingBiMap<L, R> {
+        LendingBiMap::with_hasher(RandomState::new())
+    }
+
+    /// Creates a new empty `LendingBiMap` with a name, which is included in any panic messages it
+    /// raises.
+    pub fn named<N: Into<String>>(name: N) -> LendingBiMap<L, R> {
+        let mut lib = LendingBiMap::new();
+        lib.name = Some(name.into());
+        lib
+    }
+}
+
+impl<L, R, S> LendingBiMap<L, R, S>
+where
+    L: Hash,
+    R: Hash,
+    S: BuildHasher,
+{
+    /// Creates an empty `LendingBiMap` which will use the given hash builder to hash both sides.
+    pub fn with_hasher(hasher: S) -> LendingBiMap<L, R, S> {
+        LendingBiMap {
+            by_left: HashMap::new(),
+            by_right: HashMap::new(),
+            outstanding: AtomicUsize::new(0),
+            hasher,
+            name: None,
+        }
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the number of pairs in the store, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.by_left
+            .values()
+            .filter(|e| matches!(e.slot, Present(..) | Loaned))
+            .count()
+    }
+
+    /// Returns true if the store is empty, including loaned pairs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn left_of<Q>(&self, right: &Q) -> Option<u64>
+    where
+        Q: ?Sized + Hash,
+    {
+        self.by_right.get(&self.hasher.hash_one(right)).cloned()
+    }
+
+    fn slot(&self, hash: Option<u64>) -> Option<&Slot<L, R>> {
+        hash.and_then(|h| self.by_left.get(&h)).map(|e| &e.slot)
+    }
+
+    /// Returns true if a pair with left value `left` exists in the store, and false otherwise.
+    pub fn contains_left<Q>(&self, left: &Q) -> bool
+    where
+        L: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        let h = self.hasher.hash_one(left);
+        matches!(self.slot(Some(h)), Some(Present(..)) | Some(Loaned))
+    }
+
+    /// Returns true if a pair with right value `right` exists in the store, and false otherwise.
+    pub fn contains_right<Q>(&self, right: &Q) -> bool
+    where
+        R: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        matches!(self.slot(self.left_of(right)), Some(Present(..)) | Some(Loaned))
+    }
+
+    /// Returns true if the pair with left value `left` is currently loaned.
+    pub fn is_loaned_left<Q>(&self, left: &Q) -> bool
+    where
+        L: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        let h = self.hasher.hash_one(left);
+        matches!(self.slot(Some(h)), Some(Loaned))
+    }
+
+    /// Returns true if the pair with right value `right` is currently loaned.
+    pub fn is_loaned_right<Q>(&self, right: &Q) -> bool
+    where
+        R: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        matches!(self.slot(self.left_of(right)), Some(Loaned))
+    }
+
+    /// Returns the right value paired with `left`, returning `None` if there is no such pair or it
+    /// is currently loaned.
+    pub fn get_by_left<Q>(&self, left: &Q) -> Option<&R>
+    where
+        L: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        match self.slot(Some(self.hasher.hash_one(left))) {
+            Some(Present(_, r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    /// Returns the left value paired with `right`, returning `None` if there is no such pair or it
+    /// is currently loaned.
+    pub fn get_by_right<Q>(&self, right: &Q) -> Option<&L>
+    where
+        R: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        match self.slot(self.left_of(right)) {
+            Some(Present(l, _)) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// Inserts a pair into the store, returning any existing pairs that shared either its left or
+    /// its right value.
+    /// # Panics
+    /// Will panic if either of those pairs is currently loaned or awaiting drop.
+    pub fn insert(&mut self, left: L, right: R) -> Vec<(L, R)> {
+        let lh = self.hasher.hash_one(&left);
+        let rh = self.hasher.hash_one(&right);
+        let hashes = [Some(lh), self.by_right.get(&rh).cloned()];
+        for e in hashes.iter().flatten().filter_map(|h| self.by_left.get(h)) {
+            match e.slot {
+                Present(..) => {}
+                Loaned => _panic(&self.name, "Cannot overwrite loaned value"),
+                AwaitingDrop => _panic(&self.name, "Cannot overwrite value awaiting drop"),
+            }
+        }
+        let mut displaced = Vec::new();
+        for h in hashes.iter().flatten() {
+            if let Some(e) = self.by_left.remove(h) {
+                self.by_right.remove(&e.right);
+                if let Present(l, r) = e.slot {
+                    displaced.push((l, r));
+                }
+            }
+        }
+        self.by_left.insert(lh, Entry {
+            right: rh,
+            slot: Present(left, right),
+        });
+        self.by_right.insert(rh, lh);
+        displaced
+    }
+
+    fn remove_hash(&mut self, hash: Option<u64>) -> bool {
+        let h = match hash {
+            Some(h) => h,
+            None => return false,
+        };
+        let loaned = match self.by_left.get_mut(&h) {
+            Some(e) => match e.slot {
+                Present(..) => false,
+                Loaned => {
+                    e.slot = AwaitingDrop;
+                    true
+                }
+                AwaitingDrop => return false,
+            },
+            None => return false,
+        };
+        if !loaned {
+            let e = self.by_left.remove(&h).unwrap();
+            self.by_right.remove(&e.right);
+        }
+        true
+    }
+
+    /// Removes the pair with left value `left` from the store, returning true if it was present
+    /// and false otherwise. A loaned pair is dropped when its loan is returned.
+    pub fn remove_by_left<Q>(&mut self, left: &Q) -> bool
+    where
+        L: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        let h = self.hasher.hash_one(left);
+        self.remove_hash(Some(h))
+    }
+
+    /// Removes the pair with right value `right` from the store, returning true if it was present
+    /// and false otherwise. A loaned pair is dropped when its loan is returned.
+    pub fn remove_by_right<Q>(&mut self, right: &Q) -> bool
+    where
+        R: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        let h = self.left_of(right);
+        self.remove_hash(h)
+    }
+
+    fn lend_hash(&mut self, hash: Option<u64>) -> Option<Loan<L, R, S>> {
+        let ptr: *mut Self = self;
+        let h = hash?;
+        let entry = self.by_left.get_mut(&h)?;
+        let pair = match ::std::mem::replace(&mut entry.slot, Loaned) {
+            Present(l, r) => (l, r),
+            Loaned => _panic(&self.name, "Lending already loaned value"),
+            AwaitingDrop => _panic(&self.name, "Lending value awaiting drop"),
+        };
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            hash: h,
+            inner: Some(pair),
+        })
+    }
+
+    /// Loans the pair with left value `left` from the store, returning `None` if there is no such
+    /// pair.
+    /// # Panics
+    /// Will panic if you try and loan a pair that still has an outstanding loan.
+    pub fn lend_by_left<Q>(&mut self, left: &Q) -> Option<Loan<L, R, S>>
+    where
+        L: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        let h = self.hasher.hash_one(left);
+        self.lend_hash(Some(h))
+    }
+
+    /// Loans the pair with right value `right` from the store, returning `None` if there is no
+    /// such pair.
+    /// # Panics
+    /// Will panic if you try and loan a pair that still has an outstanding loan.
+    pub fn lend_by_right<Q>(&mut self, right: &Q) -> Option<Loan<L, R, S>>
+    where
+        R: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        let h = self.left_of(right);
+        self.lend_hash(h)
+    }
+
+    /// An iterator visiting all pairs in arbitary order.
+    /// # Panics
+    /// The iterator will panic if it encounters a pair that is currently loaned from the store,
+    /// so this should only be used where you are sure you have returned all loaned pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&L, &R)> {
+        let name = &self.name;
+        self.by_left.values().filter_map(move |e| match e.slot {
+            Present(ref l, ref r) => Some((l, r)),
+            Loaned => _panic(name, "Trying to iterate over a store with loaned items."),
+            AwaitingDrop => None,
+        })
+    }
+
+    fn checkin(&mut self, hash: u64, left: L, right: R) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        let right_hash = match self.by_left.get(&hash) {
+            Some(e) => match e.slot {
+                Loaned => e.right,
+                AwaitingDrop => {
+                    let e = self.by_left.remove(&hash).unwrap();
+                    self.by_right.remove(&e.right);
+                    return;
+                }
+                Present(..) => _panic(&self.name, "Returning replaced item"),
+            },
+            None => _panic(&self.name, "Returning item not from store"),
+        };
+        let lh = self.hasher.hash_one(&left);
+        let rh = self.hasher.hash_one(&right);
+        let collides = (lh != hash && self.by_left.contains_key(&lh))
+            || self.by_right.get(&rh).is_some_and(|&l| l != hash);
+        if collides {
+            self.by_left.insert(hash, Entry {
+                right: right_hash,
+                slot: Present(left, right),
+            });
+            _panic(&self.name, "Returned pair collides with an existing entry")
+        }
+        if lh != hash || rh != right_hash {
+            self.by_left.remove(&hash);
+            self.by_right.remove(&right_hash);
+            self.by_right.insert(rh, lh);
+        }
+        self.by_left.insert(lh, Entry {
+            right: rh,
+            slot: Present(left, right),
+        });
+    }
+}
+
+impl<L, R, S> Drop for LendingBiMap<L, R, S>
+where
+    L: Hash,
+    R: Hash,
+{
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let count = self.outstanding.load(Ordering::SeqCst);
+            if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.", count))
+            }
+        }
+    }
+}
+
+impl<L, R, S> Default for LendingBiMap<L, R, S>
+where
+    L: Hash,
+    R: Hash,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        LendingBiMap::with_hasher(S::default())
+    }
+}
+
+impl<L, R, S> FromIterator<(L, R)> for LendingBiMap<L, R, S>
+where
+    L: Hash,
+    R: Hash,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (L, R)>>(iter: I) -> Self {
+        let mut lib = LendingBiMap::default();
+        for (l, r) in iter {
+            lib.insert(l, r);
+        }
+        lib
+    }
+}
+
+impl<L, R, S> Debug for Loan<L, R, S>
+where
+    L: Hash + Debug,
+    R: Hash + Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <(L, R) as Debug>::fmt(self, f)
+    }
+}
+
+impl<L, R, S> PartialEq for Loan<L, R, S>
+where
+    L: Hash + PartialEq,
+    R: Hash + PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<L, R, S> Drop for Loan<L, R, S>
+where
+    L: Hash,
+    R: Hash,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            let (l, r) = self.inner.take().unwrap();
+            unsafe {
+                (*self.owner).checkin(self.hash, l, r);
+            }
+        }
+    }
+}
+
+impl<L, R, S> Deref for Loan<L, R, S>
+where
+    L: Hash,
+    R: Hash,
+    S: BuildHasher,
+{
+    type Target = (L, R);
+
+    fn deref(&self) -> &(L, R) {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<L, R, S> DerefMut for Loan<L, R, S>
+where
+    L: Hash,
+    R: Hash,
+    S: BuildHasher,
+{
+    fn deref_mut(&mut self) -> &mut (L, R) {
+        self.inner.as_mut().unwrap()
+    }
+}
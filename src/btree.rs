@@ -0,0 +1,409 @@
+/* Notice
+btree.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! An ordered, `BTreeMap` backed variant of `LendingLibrary`.
+
+use super::_panic;
+use std::{borrow::Borrow,
+          collections::BTreeMap,
+          fmt::{Debug, Error as FmtError, Formatter},
+          iter::FromIterator,
+          ops::{Deref, DerefMut, RangeBounds},
+          sync::atomic::{AtomicUsize, Ordering},
+          thread};
+
+enum Slot<V> {
+    Present(V),
+    Loaned,
+    AwaitingDrop,
+}
+
+use self::Slot::{AwaitingDrop, Loaned, Present};
+
+/// A data store that lends temporary ownership of stored values, keeping its keys in order.
+///
+/// This has the same loan semantics as [`LendingLibrary`], but is backed by a `BTreeMap` so that
+/// entries can be visited in key order and scanned by range. Loans hold a copy of their key, so
+/// keys must be `Clone`.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::btree::LendingBTree;
+/// let mut lib: LendingBTree<u64, &str> = LendingBTree::new();
+/// lib.insert(30, "c");
+/// lib.insert(10, "a");
+/// lib.insert(20, "b");
+/// {
+///     let mut v = lib.lend(&20).unwrap();
+///     *v = "B";
+/// }
+/// let vals: Vec<_> = lib.range(15..).map(|(_k, v)| *v).collect();
+/// assert_eq!(vals, vec!["B", "c"]);
+/// ```
+pub struct LendingBTree<K, V>
+where
+    K: Ord + Clone,
+{
+    store: BTreeMap<K, Slot<V>>,
+    outstanding: AtomicUsize,
+    name: Option<String>,
+}
+
+/// A smart pointer representing the loan of a value from a `LendingBTree` instance.
+pub struct Loan<K, V>
+where
+    K: Ord + Clone,
+{
+    owner: *mut LendingBTree<K, V>,
+    key: Option<K>,
+    inner: Option<V>,
+}
+
+impl<K, V> LendingBTree<K, V>
+where
+    K: Ord + Clone,
+{
+    /// Creates a new empty `LendingBTree`.
+    /// # Example
+    /// ```
+    /// use lending_library::btree::LendingBTree;
+    /// let mut lib: LendingBTree<i32, i32> = LendingBTree::new();
+    /// ```
+    pub fn new() -> LendingBTree<K, V> {
+        LendingBTree {
+            store: BTreeMap::new(),
+            outstanding: AtomicUsize::new(0),
+            name: None,
+        }
+    }
+
+    /// Creates a new empty `LendingBTree` with a name, which is included in any panic messages it
+    /// raises.
+    /// # Example
+    /// ```
+    /// use lending_library::btree::LendingBTree;
+    /// let lib: LendingBTree<i32, i32> = LendingBTree::named("events");
+    /// assert_eq!(lib.name(), Some("events"));
+    /// ```
+    pub fn named<N: Into<String>>(name: N) -> LendingBTree<K, V> {
+        let mut lib = LendingBTree::new();
+        lib.name = Some(name.into());
+        lib
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the number of items in the store, including those currently loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::btree::LendingBTree;
+    /// let mut lib: LendingBTree<i32, i32> = LendingBTree::new();
+    /// lib.insert(1, 1);
+    /// let _v = lib.lend(&1).unwrap();
+    /// assert_eq!(lib.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.store
+            .values()
+            .filter(|v| !matches!(**v, AwaitingDrop))
+            .count()
+    }
+
+    /// Returns true if the store is empty, including loaned items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if a record with key `key` exists in the store, and false otherwise.
+    /// # Example
+    /// ```
+    /// use lending_library::btree::LendingBTree;
+    /// let mut lib: LendingBTree<String, i32> = LendingBTree::new();
+    /// lib.insert("a".to_string(), 1);
+    /// assert!(lib.contains_key("a"));
+    /// ```
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        matches!(self.store.get(key), Some(Present(_)) | Some(Loaned))
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, returning `None` if the key is
+    /// absent or the value is currently loaned.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        match self.store.get_mut(key) {
+            Some(Present(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Inserts a new key/value pair into the store. If a pair with that key already exists, the
+    /// previous value will be returned.
+    /// # Panics
+    /// Will panic if the existing value is currently loaned or awaiting drop.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        match self.store.insert(key, Present(val)) {
+            Some(Present(v)) => Some(v),
+            Some(Loaned) => _panic(&self.name, "Cannot overwrite loaned value"),
+            Some(AwaitingDrop) => _panic(&self.name, "Cannot overwrite value awaiting drop"),
+            None => None,
+        }
+    }
+
+    /// Removes a key/value pair from the store. Returning true if the key was present in the store
+    /// and false otherwise. A loaned value is dropped when its loan is returned.
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        match self.store.get_mut(key) {
+            Some(v) => match *v {
+                Present(_) => {
+                    self.store.remove(key);
+                    true
+                }
+                Loaned => {
+                    *v = AwaitingDrop;
+                    true
+                }
+                AwaitingDrop => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Removes a key/value pair from the store, returning the value if the key was present in the
+    /// store and `None` otherwise.
+    /// # Panics
+    /// Will panic if the value is currently loaned.
+    pub fn take<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        match self.store.get(key) {
+            Some(Present(_)) => match self.store.remove(key) {
+                Some(Present(v)) => Some(v),
+                _ => unreachable!(),
+            },
+            Some(Loaned) => _panic(&self.name, "Cannot take loaned value"),
+            Some(AwaitingDrop) | None => None,
+        }
+    }
+
+    /// Loans a value from the library, returning `Some(Loan<K, V>)` if the value is present, and
+    /// `None` if it is not.
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan.
+    pub fn lend<Q>(&mut self, key: &Q) -> Option<Loan<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let ptr: *mut Self = self;
+        let (k, slot) = self.store.get_key_value(key)?;
+        match *slot {
+            Present(_) => {}
+            Loaned => _panic(&self.name, "Lending already loaned value"),
+            AwaitingDrop => _panic(&self.name, "Lending value awaiting drop"),
+        }
+        let k = k.clone();
+        let val = match self.store.insert(k.clone(), Loaned) {
+            Some(Present(v)) => v,
+            _ => unreachable!(),
+        };
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            key: Some(k),
+            inner: Some(val),
+        })
+    }
+
+    /// An iterator visiting all key/value pairs in key order.
+    /// # Panics
+    /// The iterator will panic if it encounters an item that is currently loaned from the store,
+    /// so this should only be used where you are sure you have returned all loaned items.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.range::<K, _>(..)
+    }
+
+    /// An iterator visiting all keys in order, including those whose values are currently loaned.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.store.iter().filter_map(|(k, v)| match *v {
+            Present(_) | Loaned => Some(k),
+            AwaitingDrop => None,
+        })
+    }
+
+    /// An iterator visiting the key/value pairs within `range` in key order.
+    /// # Panics
+    /// The iterator will panic if it encounters an item that is currently loaned from the store.
+    /// # Example
+    /// ```
+    /// use lending_library::btree::LendingBTree;
+    /// let lib: LendingBTree<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+    /// let vals: Vec<_> = lib.range(3..6).map(|(_k, v)| *v).collect();
+    /// assert_eq!(vals, vec![30, 40, 50]);
+    /// ```
+    pub fn range<Q, R>(&self, range: R) -> impl Iterator<Item = (&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        R: RangeBounds<Q>,
+    {
+        let name = &self.name;
+        self.store.range(range).filter_map(move |(k, v)| match *v {
+            Present(ref v) => Some((k, v)),
+            Loaned => _panic(name, "Trying to iterate over a store with loaned items."),
+            AwaitingDrop => None,
+        })
+    }
+
+    /// Returns the first key/value pair in key order whose value is present, skipping any that are
+    /// currently loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::btree::LendingBTree;
+    /// let mut lib: LendingBTree<i32, i32> = (0..3).map(|i| (i, i)).collect();
+    /// let _v = lib.lend(&0).unwrap();
+    /// assert_eq!(lib.first_key_value(), Some((&1, &1)));
+    /// ```
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.store.iter().find_map(|(k, v)| match *v {
+            Present(ref v) => Some((k, v)),
+            Loaned | AwaitingDrop => None,
+        })
+    }
+
+    /// Returns the last key/value pair in key order whose value is present, skipping any that are
+    /// currently loaned.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.store.iter().rev().find_map(|(k, v)| match *v {
+            Present(ref v) => Some((k, v)),
+            Loaned | AwaitingDrop => None,
+        })
+    }
+
+    fn checkin(&mut self, key: K, val: V) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        match self.store.get_mut(&key) {
+            Some(v) => match *v {
+                Present(_) => _panic(&self.name, "Returning replaced item"),
+                Loaned => *v = Present(val),
+                AwaitingDrop => {
+                    self.store.remove(&key);
+                }
+            },
+            None => _panic(&self.name, "Returning item not from store"),
+        }
+    }
+}
+
+impl<K, V> Drop for LendingBTree<K, V>
+where
+    K: Ord + Clone,
+{
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let count = self.outstanding.load(Ordering::SeqCst);
+            if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.", count))
+            }
+        }
+    }
+}
+
+impl<K, V> Default for LendingBTree<K, V>
+where
+    K: Ord + Clone,
+{
+    fn default() -> Self {
+        LendingBTree::new()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for LendingBTree<K, V>
+where
+    K: Ord + Clone,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut lib = LendingBTree::new();
+        for (k, v) in iter {
+            lib.insert(k, v);
+        }
+        lib
+    }
+}
+
+impl<K, V> Debug for Loan<K, V>
+where
+    K: Ord + Clone,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<K, V> PartialEq for Loan<K, V>
+where
+    K: Ord + Clone,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<K, V> Drop for Loan<K, V>
+where
+    K: Ord + Clone,
+{
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            unsafe {
+                (*self.owner).checkin(self.key.take().unwrap(), self.inner.take().unwrap());
+            }
+        }
+    }
+}
+
+impl<K, V> Deref for Loan<K, V>
+where
+    K: Ord + Clone,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<K, V> DerefMut for Loan<K, V>
+where
+    K: Ord + Clone,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.inner.as_mut().unwrap()
+    }
+}
@@ -0,0 +1,480 @@
+/* Notice
+checked.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A view of `LendingLibrary` whose operations return an [`Error`] wherever the library itself
+//! would panic, created by [`LendingLibrary::checked()`].
+//!
+//! [`Error`]: struct.Error.html
+//! [`LendingLibrary::checked()`]: ../struct.LendingLibrary.html#method.checked
+
+use super::{InsertError, InsertErrorKind, LendError, LendErrorKind, LendingLibrary, Loan,
+            LoanedError, ReadLoan, RenameError, RenameErrorKind, State};
+use entry::Entry;
+use error::write_name;
+use iter::{Drain, Iter};
+use std::{borrow::Borrow,
+          collections::{hash_map::RandomState, HashSet},
+          error::Error as StdError,
+          fmt::{Debug, Display, Error as FmtError, Formatter},
+          hash::{BuildHasher, Hash},
+          sync::atomic::Ordering};
+
+/// The reason an operation through a [`Checked`] view failed.
+///
+/// [`Checked`]: struct.Checked.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// There is no value stored under the key.
+    NotFound,
+    /// The value is currently loaned.
+    Loaned,
+    /// The value has been removed while loaned, and is yet to be returned.
+    AwaitingDrop,
+    /// The value was returned by a loan dropped during a panic, and its poisoning has not been
+    /// cleared.
+    Poisoned,
+    /// The value would not fit within the library's weight budget.
+    OverWeight,
+    /// The key has been reserved by a `Reservation`.
+    Reserved,
+    /// The key already holds a different value.
+    Occupied,
+    /// The library has this many loans outstanding.
+    OutstandingLoans(usize),
+}
+
+/// The error returned by operations through a [`Checked`] view. It shows the name of the library
+/// it came from, and errors converted from a `LoanedError` keep the loaned keys, in their `Debug`
+/// form.
+///
+/// [`Checked`]: struct.Checked.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    keys: Vec<String>,
+    name: Option<String>,
+}
+
+impl Error {
+    fn new(kind: ErrorKind, name: Option<String>) -> Self {
+        Error {
+            kind,
+            keys: Vec::new(),
+            name,
+        }
+    }
+
+    /// Returns the reason the operation failed.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the `Debug` form of the keys the error concerns, if it was converted from an
+    /// error that listed them.
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write_name(f, self.name.as_deref())?;
+        match self.kind {
+            ErrorKind::NotFound => write!(f, "Key not present in library")?,
+            ErrorKind::Loaned => write!(f, "Value is currently loaned")?,
+            ErrorKind::AwaitingDrop => write!(f, "Value is awaiting drop")?,
+            ErrorKind::Poisoned => write!(f, "Value is poisoned")?,
+            ErrorKind::OverWeight => write!(f, "Value exceeds the weight budget")?,
+            ErrorKind::Reserved => write!(f, "Key is reserved")?,
+            ErrorKind::Occupied => write!(f, "Key already holds a value")?,
+            ErrorKind::OutstandingLoans(n) => write!(f, "{} values are still on loan", n)?,
+        }
+        if !self.keys.is_empty() {
+            write!(f, ": [{}]", self.keys.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for Error {}
+
+impl From<LendError> for Error {
+    fn from(err: LendError) -> Self {
+        let kind = match err.kind() {
+            LendErrorKind::NotFound => ErrorKind::NotFound,
+            LendErrorKind::AlreadyLoaned => ErrorKind::Loaned,
+            LendErrorKind::AwaitingDrop => ErrorKind::AwaitingDrop,
+            LendErrorKind::Poisoned => ErrorKind::Poisoned,
+        };
+        Error::new(kind, err.name)
+    }
+}
+
+impl<K, V> From<InsertError<K, V>> for Error {
+    fn from(err: InsertError<K, V>) -> Self {
+        let kind = match err.kind() {
+            InsertErrorKind::Loaned => ErrorKind::Loaned,
+            InsertErrorKind::AwaitingDrop => ErrorKind::AwaitingDrop,
+            InsertErrorKind::OverWeight => ErrorKind::OverWeight,
+            InsertErrorKind::Reserved => ErrorKind::Reserved,
+        };
+        Error::new(kind, err.name)
+    }
+}
+
+impl<K> From<RenameError<K>> for Error {
+    fn from(err: RenameError<K>) -> Self {
+        let kind = match err.kind() {
+            RenameErrorKind::Loaned => ErrorKind::Loaned,
+            RenameErrorKind::AwaitingDrop => ErrorKind::AwaitingDrop,
+            RenameErrorKind::Occupied => ErrorKind::Occupied,
+            RenameErrorKind::Reserved => ErrorKind::Reserved,
+        };
+        Error::new(kind, err.name)
+    }
+}
+
+impl<'a, K> From<LoanedError<'a, K>> for Error
+where
+    K: Debug,
+{
+    fn from(err: LoanedError<'a, K>) -> Self {
+        Error {
+            kind: ErrorKind::Loaned,
+            keys: err.keys().iter().map(|k| format!("{:?}", k)).collect(),
+            name: err.name.map(str::to_owned),
+        }
+    }
+}
+
+/// A view of a `LendingLibrary` mirroring its operations, but returning an error rather than
+/// panicking when they are misused. Errors that carry more detail, such as the rejected pair from
+/// [`insert()`], convert into [`Error`] so that they can all be propagated with `?`. Indexing has
+/// no checked form of its own; [`get()`] serves in its place.
+///
+/// [`insert()`]: #method.insert
+/// [`get()`]: #method.get
+/// [`Error`]: struct.Error.html
+/// # Example
+/// ```
+/// use lending_library::{checked::ErrorKind, LendingLibrary};
+/// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+/// let mut lib = lib.checked();
+/// lib.insert(1, 1).unwrap();
+/// let v = lib.lend(&1).unwrap();
+/// assert_eq!(lib.take(&1).unwrap_err().kind(), ErrorKind::Loaned);
+/// assert_eq!(lib.lend(&2).unwrap_err().kind(), ErrorKind::NotFound);
+/// drop(v);
+/// assert_eq!(lib.take(&1), Ok(1));
+/// ```
+pub struct Checked<'a, K: 'a, V: 'a, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    pub(super) lib: &'a mut LendingLibrary<K, V, S>,
+}
+
+impl<'a, K, V, S> Checked<'a, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::new(kind, self.lib.name.clone())
+    }
+
+    /// Returns the number of values in the library, as [`LendingLibrary::len()`].
+    ///
+    /// [`LendingLibrary::len()`]: ../struct.LendingLibrary.html#method.len
+    pub fn len(&self) -> usize {
+        self.lib.len()
+    }
+
+    /// Returns true if the library holds no values, as [`LendingLibrary::is_empty()`].
+    ///
+    /// [`LendingLibrary::is_empty()`]: ../struct.LendingLibrary.html#method.is_empty
+    pub fn is_empty(&self) -> bool {
+        self.lib.is_empty()
+    }
+
+    /// Returns true if a value is stored under `key`, as [`LendingLibrary::contains_key()`].
+    ///
+    /// [`LendingLibrary::contains_key()`]: ../struct.LendingLibrary.html#method.contains_key
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lib.contains_key(key)
+    }
+
+    /// Returns a reference to the value stored under `key`.
+    /// # Errors
+    /// Returns `NotFound` if there is no such value, or `Loaned` if it is currently loaned.
+    pub fn get<Q>(&self, key: &Q) -> Result<&V, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.lib.hash_key(key);
+        let kind = match self.lib.store.get(&h) {
+            Some(State::Present(_, v)) if !self.lib.is_expired(h) => return Ok(v),
+            Some(State::Loaned(_)) => ErrorKind::Loaned,
+            Some(State::Present(..)) | Some(State::AwaitingDrop(_)) | None => ErrorKind::NotFound,
+        };
+        Err(self.error(kind))
+    }
+
+    /// Iterates over every key/value pair in the library, as [`LendingLibrary::try_iter()`].
+    ///
+    /// [`LendingLibrary::try_iter()`]: ../struct.LendingLibrary.html#method.try_iter
+    /// # Errors
+    /// Returns a `LoanedError` listing the loaned keys if any values are currently loaned.
+    pub fn iter(&self) -> Result<Iter<'_, K, V>, LoanedError<'_, K>> {
+        self.lib.try_iter()
+    }
+
+    /// Inserts a key/value pair, as [`LendingLibrary::try_insert()`].
+    ///
+    /// [`LendingLibrary::try_insert()`]: ../struct.LendingLibrary.html#method.try_insert
+    /// # Errors
+    /// Returns an `InsertError` holding the rejected key and value if the existing value is
+    /// loaned or awaiting drop, the key is reserved, or the value would not fit within the
+    /// library's weight budget.
+    pub fn insert(&mut self, key: K, val: V) -> Result<Option<V>, InsertError<K, V>> {
+        self.lib.try_insert(key, val)
+    }
+
+    /// Removes the value stored under `key`. If it is currently loaned, it is dropped once
+    /// returned.
+    /// # Errors
+    /// Returns `NotFound` if there is no such value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Result<(), Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self.lib.remove(key) {
+            true => Ok(()),
+            false => Err(self.error(ErrorKind::NotFound)),
+        }
+    }
+
+    /// Removes and returns the value stored under `key`.
+    /// # Errors
+    /// Returns `NotFound` if there is no such value, or `Loaned` if it is currently loaned.
+    pub fn take<Q>(&mut self, key: &Q) -> Result<V, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.lib.hash_key(key);
+        self.lib.settle();
+        match self.lib.store.get(&h) {
+            Some(State::Loaned(_)) => Err(self.error(ErrorKind::Loaned)),
+            _ => self.lib.take(key).ok_or_else(|| self.error(ErrorKind::NotFound)),
+        }
+    }
+
+    /// Removes every value from the library, as [`LendingLibrary::clear()`]. Values currently
+    /// loaned are dropped once returned.
+    ///
+    /// [`LendingLibrary::clear()`]: ../struct.LendingLibrary.html#method.clear
+    pub fn clear(&mut self) {
+        self.lib.clear()
+    }
+
+    /// Clears the library, returning all key/value pairs as an iterator.
+    /// # Errors
+    /// Returns `OutstandingLoans` without removing anything if any values are currently loaned.
+    pub fn drain(&mut self) -> Result<Drain<'_, K, V>, Error> {
+        self.lib.settle();
+        match self.lib.outstanding.load(Ordering::SeqCst) {
+            0 => Ok(self.lib.drain_settled()),
+            n => Err(self.error(ErrorKind::OutstandingLoans(n))),
+        }
+    }
+
+    /// Loans the value stored under `key`, as [`LendingLibrary::try_lend()`].
+    ///
+    /// [`LendingLibrary::try_lend()`]: ../struct.LendingLibrary.html#method.try_lend
+    /// # Errors
+    /// Returns `NotFound` if there is no such value, `Loaned` or `AwaitingDrop` if it cannot be
+    /// lent, or `Poisoned` if it is poisoned.
+    #[track_caller]
+    pub fn lend<Q>(&mut self, key: &Q) -> Result<Loan<K, V, S>, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lib.try_lend(key).map_err(Error::from)
+    }
+
+    /// Loans the value stored under `key` for reading, as [`LendingLibrary::lend_shared()`].
+    ///
+    /// [`LendingLibrary::lend_shared()`]: ../struct.LendingLibrary.html#method.lend_shared
+    /// # Errors
+    /// Returns `NotFound` if there is no such value, `Loaned` if it is exclusively loaned,
+    /// `AwaitingDrop` if it has been removed while loaned, or `Poisoned` if it is poisoned.
+    pub fn lend_shared<Q>(&mut self, key: &Q) -> Result<ReadLoan<K, V, S>, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.lib.hash_key(key);
+        self.lib.settle();
+        let kind = match self.lib.store.get(&h) {
+            Some(State::Present(..)) if self.lib.poisoned.contains(&h) => ErrorKind::Poisoned,
+            Some(State::Loaned(_)) if !self.lib.shared.contains_key(&h) => ErrorKind::Loaned,
+            Some(State::AwaitingDrop(_)) => ErrorKind::AwaitingDrop,
+            _ => return self.lib.lend_shared(key).ok_or_else(|| self.error(ErrorKind::NotFound)),
+        };
+        Err(self.error(kind))
+    }
+
+    /// Returns a mutable reference to the value stored under `key`.
+    /// # Errors
+    /// Returns `NotFound` if there is no such value, or `Loaned` if it is currently loaned.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Result<&mut V, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.lib.hash_key(key);
+        self.lib.settle();
+        let kind = match self.lib.store.get(&h) {
+            Some(State::Present(..)) => return Ok(self.lib.get_mut(key).unwrap()),
+            Some(State::Loaned(_)) => ErrorKind::Loaned,
+            Some(State::AwaitingDrop(_)) | None => ErrorKind::NotFound,
+        };
+        Err(self.error(kind))
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, inserting the result of `f`
+    /// first if the key is absent, as [`LendingLibrary::get_or_insert_with()`].
+    ///
+    /// [`LendingLibrary::get_or_insert_with()`]: ../struct.LendingLibrary.html#method.get_or_insert_with
+    /// # Errors
+    /// Fails as [`entry()`](#method.entry) does, without calling `f`.
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> Result<&mut V, Error>
+    where
+        F: FnOnce() -> V,
+    {
+        Ok(self.entry(key)?.or_insert_with(f))
+    }
+
+    /// Gets the entry for `key` in the library, as [`LendingLibrary::entry()`]. An entry returned
+    /// here holds a value that can be read, modified, overwritten and lent, or is free to insert
+    /// into, so none of its operations will panic.
+    ///
+    /// [`LendingLibrary::entry()`]: ../struct.LendingLibrary.html#method.entry
+    /// # Errors
+    /// Returns `Loaned` or `AwaitingDrop` if the value under `key` is currently loaned or awaiting
+    /// drop, `Poisoned` if it is poisoned, or `Reserved` if the key is absent and reserved.
+    pub fn entry(&mut self, key: K) -> Result<Entry<'_, K, V, S>, Error> {
+        let h = self.lib.hash_key(&key);
+        self.lib.settle();
+        let kind = match self.lib.store.get(&h) {
+            Some(State::Loaned(_)) => ErrorKind::Loaned,
+            Some(State::AwaitingDrop(_)) => ErrorKind::AwaitingDrop,
+            Some(State::Present(..)) if self.lib.poisoned.contains(&h) => ErrorKind::Poisoned,
+            None if self.lib.is_reserved(&key) => ErrorKind::Reserved,
+            Some(State::Present(..)) | None => return Ok(self.lib.entry(key)),
+        };
+        Err(self.error(kind))
+    }
+
+    /// Moves all present entries from `other` into this library, as
+    /// [`LendingLibrary::append()`].
+    ///
+    /// [`LendingLibrary::append()`]: ../struct.LendingLibrary.html#method.append
+    /// # Errors
+    /// Returns `Reserved`, `Loaned` or `AwaitingDrop`, without moving anything, if any entry would
+    /// land on a reserved key or overwrite a value that is currently loaned or awaiting drop.
+    pub fn append(&mut self, other: &mut LendingLibrary<K, V, S>) -> Result<(), Error> {
+        self.lib.settle();
+        other.settle();
+        for s in other.store.values() {
+            if let State::Present(ref k, _) = *s {
+                let h = self.lib.hash_key(k);
+                let kind = match self.lib.store.get(&h) {
+                    Some(State::Loaned(_)) => ErrorKind::Loaned,
+                    Some(State::AwaitingDrop(_)) => ErrorKind::AwaitingDrop,
+                    None if self.lib.is_reserved(k) => ErrorKind::Reserved,
+                    Some(State::Present(..)) | None => continue,
+                };
+                return Err(self.error(kind));
+            }
+        }
+        self.lib.append(other);
+        Ok(())
+    }
+
+    /// Moves the value stored under `old` to the key `new`, as [`LendingLibrary::rename_key()`].
+    ///
+    /// [`LendingLibrary::rename_key()`]: ../struct.LendingLibrary.html#method.rename_key
+    /// # Errors
+    /// Returns a `RenameError` holding `new` if either key is loaned or awaiting drop, or if `new`
+    /// is reserved or already holds another value.
+    pub fn rename_key<Q>(&mut self, old: &Q, new: K) -> Result<bool, RenameError<K>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lib.rename_key(old, new)
+    }
+
+    /// Loans several values from the library together, as [`LendingLibrary::lend_many()`].
+    ///
+    /// [`LendingLibrary::lend_many()`]: ../struct.LendingLibrary.html#method.lend_many
+    /// # Errors
+    /// Returns `NotFound` if any key is absent, `Loaned` if any value is currently loaned or the
+    /// same key is given more than once, `AwaitingDrop` if any value is awaiting drop, or
+    /// `Poisoned` if any value is poisoned. Nothing is lent unless every value can be.
+    #[track_caller]
+    pub fn lend_many<'b, I, Q>(&mut self, keys: I) -> Result<Vec<Loan<K, V, S>>, Error>
+    where
+        I: IntoIterator<Item = &'b Q>,
+        K: Borrow<Q>,
+        Q: 'b + ?Sized + Hash + Eq,
+    {
+        self.lib.settle();
+        let mut hashes = Vec::new();
+        let mut seen = HashSet::new();
+        for key in keys {
+            let h = self.lib.hash_key(key);
+            let kind = match self.lib.store.get(&h) {
+                _ if !seen.insert(h) => ErrorKind::Loaned,
+                Some(State::Present(..)) if self.lib.poisoned.contains(&h) => ErrorKind::Poisoned,
+                Some(State::Present(..)) => {
+                    hashes.push(h);
+                    continue;
+                }
+                Some(State::Loaned(_)) => ErrorKind::Loaned,
+                Some(State::AwaitingDrop(_)) => ErrorKind::AwaitingDrop,
+                None => ErrorKind::NotFound,
+            };
+            return Err(self.error(kind));
+        }
+        Ok(hashes
+            .into_iter()
+            .map(|h| self.lib.lend_hashed_unchecked(h).unwrap())
+            .collect())
+    }
+}
+
+impl<'a, K, V, S> Debug for Checked<'a, K, V, S>
+where
+    K: Hash + Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.debug_tuple("Checked").field(&*self.lib).finish()
+    }
+}
@@ -0,0 +1,302 @@
+/* Notice
+entry.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! Entry types for `LendingLibrary`
+
+use super::{_panic, LendingLibrary, Loan, State};
+use std::{collections::hash_map::RandomState,
+          hash::{BuildHasher, Hash}};
+
+/// A view into a single entry of a `LendingLibrary`, which may be either vacant or occupied.
+///
+/// This is constructed by the [`entry`] method on `LendingLibrary`.
+///
+/// [`entry`]: ../struct.LendingLibrary.html#method.entry
+pub enum Entry<'a, K: 'a, V: 'a, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    /// An entry whose key is in the library, whether its value is present or currently loaned.
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    /// An entry whose key is not in the library, including keys whose value is awaiting drop.
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// A view into an occupied entry of a `LendingLibrary`. It is part of the [`Entry`] enum.
+///
+/// [`Entry`]: enum.Entry.html
+pub struct OccupiedEntry<'a, K: 'a, V: 'a, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    pub(super) lib: &'a mut LendingLibrary<K, V, S>,
+    pub(super) hash: u64,
+}
+
+/// A view into a vacant entry of a `LendingLibrary`. It is part of the [`Entry`] enum.
+///
+/// [`Entry`]: enum.Entry.html
+pub struct VacantEntry<'a, K: 'a, V: 'a, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    pub(super) lib: &'a mut LendingLibrary<K, V, S>,
+    pub(super) hash: u64,
+    pub(super) key: K,
+}
+
+/// A builder for raw entries, which locate an entry in a `LendingLibrary` by a pre-computed hash.
+///
+/// This is constructed by the [`raw_entry_mut`] method on `LendingLibrary`.
+///
+/// [`raw_entry_mut`]: ../struct.LendingLibrary.html#method.raw_entry_mut
+pub struct RawEntryBuilderMut<'a, K: 'a, V: 'a, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    pub(super) lib: &'a mut LendingLibrary<K, V, S>,
+}
+
+/// A view into a single entry of a `LendingLibrary`, located by its hash, which may be either
+/// vacant or occupied.
+///
+/// This is constructed by [`RawEntryBuilderMut::from_hash`].
+///
+/// [`RawEntryBuilderMut::from_hash`]: struct.RawEntryBuilderMut.html#method.from_hash
+pub enum RawEntryMut<'a, K: 'a, V: 'a, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    /// An entry whose key is in the library, whether its value is present or currently loaned.
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    /// An entry whose key is not in the library, including keys whose value is awaiting drop.
+    Vacant(RawVacantEntryMut<'a, K, V, S>),
+}
+
+/// A view into a vacant entry of a `LendingLibrary`, located by its hash. It is part of the
+/// [`RawEntryMut`] enum.
+///
+/// [`RawEntryMut`]: enum.RawEntryMut.html
+pub struct RawVacantEntryMut<'a, K: 'a, V: 'a, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    pub(super) lib: &'a mut LendingLibrary<K, V, S>,
+    pub(super) hash: u64,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref e) => e.key(),
+            Entry::Vacant(ref e) => e.key(),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting `default` if empty, and returns a mutable
+    /// reference to the value in the entry.
+    /// # Panics
+    /// Will panic if the value is currently loaned, or if the entry is awaiting drop.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensures a value is in the entry by inserting the result of `default` if empty, and returns
+    /// a mutable reference to the value in the entry.
+    /// # Panics
+    /// Will panic if the value is currently loaned, or if the entry is awaiting drop.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => {
+                if e.is_loaned() {
+                    _panic(&e.lib.name, "Cannot access loaned value")
+                }
+                e.into_mut().unwrap()
+            }
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any potential inserts.
+    /// # Panics
+    /// Will panic if the value is currently loaned.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut e) => {
+                match e.get_mut() {
+                    Some(v) => f(v),
+                    None => _panic(&e.lib.name, "Cannot modify loaned value"),
+                }
+                Entry::Occupied(e)
+            }
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S>
+where
+    K: Hash,
+{
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        match self.lib.store.get(&self.hash) {
+            Some(State::Present(k, _)) | Some(State::Loaned(k)) => k,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns true if the value in this entry is currently loaned.
+    pub fn is_loaned(&self) -> bool {
+        matches!(self.lib.store.get(&self.hash), Some(State::Loaned(_)))
+    }
+
+    /// Returns a reference to the value in the entry, or `None` if it is currently loaned.
+    pub fn get(&self) -> Option<&V> {
+        match self.lib.store.get(&self.hash) {
+            Some(State::Present(_, v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the value in the entry, or `None` if it is currently loaned.
+    pub fn get_mut(&mut self) -> Option<&mut V> {
+        match self.lib.store.get_mut(&self.hash) {
+            Some(State::Present(_, v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Converts the entry into a mutable reference to its value with the lifetime of the library,
+    /// or `None` if it is currently loaned.
+    pub fn into_mut(self) -> Option<&'a mut V> {
+        match self.lib.store.get_mut(&self.hash) {
+            Some(State::Present(_, v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Sets the value of the entry, returning the entry's old value.
+    /// # Panics
+    /// Will panic if the value is currently loaned.
+    pub fn insert(&mut self, val: V) -> V {
+        let old = match self.get_mut() {
+            Some(v) => ::std::mem::replace(v, val),
+            None => _panic(&self.lib.name, "Cannot overwrite loaned value"),
+        };
+        self.lib.replaced(self.hash);
+        old
+    }
+
+    /// Loans the value in the entry from the library.
+    /// # Panics
+    /// Will panic if the value is currently loaned.
+    #[track_caller]
+    pub fn lend(self) -> Loan<K, V, S> {
+        self.lib.lend_hashed(self.hash).unwrap()
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns a reference to the key that would be used when inserting through this entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes ownership of the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Returns true if the entry is vacant because its previous value was removed while loaned,
+    /// and is yet to be returned. Such entries cannot be inserted into.
+    pub fn is_awaiting_drop(&self) -> bool {
+        matches!(self.lib.store.get(&self.hash), Some(State::AwaitingDrop(_)))
+    }
+
+    /// Inserts a value into the entry, returning a mutable reference to it.
+    /// # Panics
+    /// Will panic if the entry is awaiting drop.
+    pub fn insert(self, val: V) -> &'a mut V {
+        self.lib.insert_hashed(self.hash, self.key, val);
+        match self.lib.store.get_mut(&self.hash) {
+            Some(State::Present(_, v)) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Inserts a value into the entry and immediately loans it from the library.
+    /// # Panics
+    /// Will panic if the entry is awaiting drop.
+    #[track_caller]
+    pub fn insert_and_lend(self, val: V) -> Loan<K, V, S> {
+        self.lib.insert_hashed(self.hash, self.key, val);
+        self.lib.lend_hashed(self.hash).unwrap()
+    }
+}
+
+impl<'a, K, V, S> RawEntryBuilderMut<'a, K, V, S>
+where
+    K: Hash,
+{
+    /// Locates the entry for a key with the given hash, which must have been computed with the
+    /// library's hasher, as by `lib.hasher().hash_one(&key)`. Keys are not compared, so a key
+    /// whose hash collides with another key's was stored in a later slot and must be looked up
+    /// by key instead.
+    pub fn from_hash(self, hash: u64) -> RawEntryMut<'a, K, V, S> {
+        match self.lib.store.get(&hash) {
+            Some(State::Present(..)) | Some(State::Loaned(_)) => {
+                RawEntryMut::Occupied(OccupiedEntry { lib: self.lib, hash })
+            }
+            Some(State::AwaitingDrop(_)) | None => {
+                RawEntryMut::Vacant(RawVacantEntryMut { lib: self.lib, hash })
+            }
+        }
+    }
+}
+
+impl<'a, K, V, S> RawVacantEntryMut<'a, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns true if the entry is vacant because its previous value was removed while loaned,
+    /// and is yet to be returned. Such entries cannot be inserted into.
+    pub fn is_awaiting_drop(&self) -> bool {
+        matches!(self.lib.store.get(&self.hash), Some(State::AwaitingDrop(_)))
+    }
+
+    /// Inserts a key and value into the entry, returning a mutable reference to the value. The key
+    /// must hash to the hash this entry was located by, which is only checked in debug builds.
+    /// # Panics
+    /// Will panic if the entry is awaiting drop.
+    pub fn insert(self, key: K, val: V) -> &'a mut V {
+        debug_assert_eq!(self.lib.hash_key(&key), self.hash, "Key does not match entry hash");
+        VacantEntry { lib: self.lib, hash: self.hash, key }.insert(val)
+    }
+
+    /// Inserts a key and value into the entry and immediately loans the value from the library.
+    /// The key must hash to the hash this entry was located by, which is only checked in debug
+    /// builds.
+    /// # Panics
+    /// Will panic if the entry is awaiting drop.
+    pub fn insert_and_lend(self, key: K, val: V) -> Loan<K, V, S> {
+        debug_assert_eq!(self.lib.hash_key(&key), self.hash, "Key does not match entry hash");
+        VacantEntry { lib: self.lib, hash: self.hash, key }.insert_and_lend(val)
+    }
+}
@@ -0,0 +1,215 @@
+/* Notice
+error.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+use std::{error::Error,
+          fmt::{Debug, Display, Error as FmtError, Formatter}};
+
+/// Prefixes an error message with the name of the library it came from, as for its panics.
+pub(super) fn write_name(f: &mut Formatter, name: Option<&str>) -> Result<(), FmtError> {
+    match name {
+        Some(n) => write!(f, "Library \"{}\": ", n),
+        None => Ok(()),
+    }
+}
+
+/// The reason an insertion into a `LendingLibrary` was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertErrorKind {
+    /// The existing value for the key is currently loaned.
+    Loaned,
+    /// The existing value for the key has been removed while loaned, and is yet to be returned.
+    AwaitingDrop,
+    /// The value would not fit within the library's weight budget, even after evicting every value
+    /// that is not on loan.
+    OverWeight,
+    /// The key has been reserved by a `Reservation`.
+    Reserved,
+}
+
+/// The error returned by [`LendingLibrary::try_insert()`] when the existing value for a key cannot
+/// be overwritten. It hands back the rejected key and value.
+///
+/// [`LendingLibrary::try_insert()`]: struct.LendingLibrary.html#method.try_insert
+#[derive(Debug)]
+pub struct InsertError<K, V> {
+    pub(super) key: K,
+    pub(super) val: V,
+    pub(super) kind: InsertErrorKind,
+    pub(super) name: Option<String>,
+}
+
+impl<K, V> InsertError<K, V> {
+    /// Returns the reason the insertion was rejected.
+    pub fn kind(&self) -> InsertErrorKind {
+        self.kind
+    }
+
+    /// Returns a reference to the rejected key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns a reference to the rejected value.
+    pub fn value(&self) -> &V {
+        &self.val
+    }
+
+    /// Consumes the error, returning the rejected key and value.
+    pub fn into_inner(self) -> (K, V) {
+        (self.key, self.val)
+    }
+}
+
+impl<K, V> Display for InsertError<K, V> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write_name(f, self.name.as_deref())?;
+        match self.kind {
+            InsertErrorKind::Loaned => write!(f, "Cannot overwrite loaned value"),
+            InsertErrorKind::AwaitingDrop => write!(f, "Cannot overwrite value awaiting drop"),
+            InsertErrorKind::OverWeight => write!(f, "Value exceeds the weight budget"),
+            InsertErrorKind::Reserved => write!(f, "Cannot insert into reserved key"),
+        }
+    }
+}
+
+impl<K, V> Error for InsertError<K, V>
+where
+    K: Debug,
+    V: Debug,
+{
+}
+
+/// The reason a rename by `LendingLibrary::rename_key()` was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenameErrorKind {
+    /// The value under one of the keys is currently loaned.
+    Loaned,
+    /// The value under one of the keys has been removed while loaned, and is yet to be returned.
+    AwaitingDrop,
+    /// A value is already stored under the new key.
+    Occupied,
+    /// The new key has been reserved by a `Reservation`.
+    Reserved,
+}
+
+/// The error returned by [`LendingLibrary::rename_key()`] when a value cannot be moved to its new
+/// key. It hands back the rejected new key, and the library is left unchanged.
+///
+/// [`LendingLibrary::rename_key()`]: struct.LendingLibrary.html#method.rename_key
+#[derive(Debug)]
+pub struct RenameError<K> {
+    pub(super) key: K,
+    pub(super) kind: RenameErrorKind,
+    pub(super) name: Option<String>,
+}
+
+impl<K> RenameError<K> {
+    /// Returns the reason the rename was rejected.
+    pub fn kind(&self) -> RenameErrorKind {
+        self.kind
+    }
+
+    /// Returns a reference to the rejected new key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Consumes the error, returning the rejected new key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+}
+
+impl<K> Display for RenameError<K> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write_name(f, self.name.as_deref())?;
+        match self.kind {
+            RenameErrorKind::Loaned => write!(f, "Cannot rename loaned value"),
+            RenameErrorKind::AwaitingDrop => write!(f, "Cannot rename value awaiting drop"),
+            RenameErrorKind::Occupied => write!(f, "Cannot rename onto an occupied key"),
+            RenameErrorKind::Reserved => write!(f, "Cannot rename onto a reserved key"),
+        }
+    }
+}
+
+impl<K> Error for RenameError<K> where K: Debug {}
+
+/// The reason a value could not be lent by [`LendingLibrary::try_lend()`].
+///
+/// [`LendingLibrary::try_lend()`]: struct.LendingLibrary.html#method.try_lend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LendErrorKind {
+    /// There is no value stored under the key.
+    NotFound,
+    /// The value is currently loaned.
+    AlreadyLoaned,
+    /// The value has been removed while loaned, and is yet to be returned.
+    AwaitingDrop,
+    /// The value was returned by a loan dropped during a panic, and its poisoning has not been
+    /// cleared.
+    Poisoned,
+}
+
+/// The error returned by [`LendingLibrary::try_lend()`] when a value cannot be lent.
+///
+/// [`LendingLibrary::try_lend()`]: struct.LendingLibrary.html#method.try_lend
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LendError {
+    pub(super) kind: LendErrorKind,
+    pub(super) name: Option<String>,
+}
+
+impl LendError {
+    /// Returns the reason the value could not be lent.
+    pub fn kind(&self) -> LendErrorKind {
+        self.kind
+    }
+}
+
+impl Display for LendError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write_name(f, self.name.as_deref())?;
+        match self.kind {
+            LendErrorKind::NotFound => write!(f, "No value stored under key"),
+            LendErrorKind::AlreadyLoaned => write!(f, "Lending already loaned value"),
+            LendErrorKind::AwaitingDrop => write!(f, "Lending value awaiting drop"),
+            LendErrorKind::Poisoned => write!(f, "Lending poisoned value"),
+        }
+    }
+}
+
+impl Error for LendError {}
+
+/// The error returned when an operation that needs every value to be present in a
+/// `LendingLibrary` finds some of them on loan.
+#[derive(Debug)]
+pub struct LoanedError<'a, K: 'a> {
+    pub(super) keys: Vec<&'a K>,
+    pub(super) name: Option<&'a str>,
+}
+
+impl<'a, K> LoanedError<'a, K> {
+    /// Returns the keys of the values that are currently loaned, including any that have been
+    /// removed from the library but not yet returned.
+    pub fn keys(&self) -> &[&'a K] {
+        &self.keys
+    }
+}
+
+impl<'a, K> Display for LoanedError<'a, K>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write_name(f, self.name)?;
+        write!(f, "{} values are still on loan: {:?}", self.keys.len(), self.keys)
+    }
+}
+
+impl<'a, K> Error for LoanedError<'a, K> where K: Debug {}
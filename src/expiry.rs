@@ -0,0 +1,72 @@
+/* Notice
+expiry.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+use std::{collections::{BTreeSet, HashMap},
+          time::Instant};
+
+/// Tracks when the entries of a library, identified by key hash, expire.
+#[derive(Clone, Default)]
+pub(super) struct Deadlines {
+    at: HashMap<u64, Instant>,
+    order: BTreeSet<(Instant, u64)>,
+}
+
+impl Deadlines {
+    /// Sets the entry `hash` to expire at `deadline`.
+    pub(super) fn set(&mut self, hash: u64, deadline: Instant) {
+        if let Some(old) = self.at.insert(hash, deadline) {
+            self.order.remove(&(old, hash));
+        }
+        self.order.insert((deadline, hash));
+    }
+
+    /// Returns the deadline of the entry `hash`, if it has one.
+    pub(super) fn get(&self, hash: u64) -> Option<Instant> {
+        self.at.get(&hash).cloned()
+    }
+
+    /// Stops tracking `hash`.
+    pub(super) fn forget(&mut self, hash: u64) {
+        if let Some(old) = self.at.remove(&hash) {
+            self.order.remove(&(old, hash));
+        }
+    }
+
+    /// Returns true if the entry `hash` has a deadline at or before `now`.
+    pub(super) fn is_expired(&self, hash: u64, now: Instant) -> bool {
+        self.at.get(&hash).is_some_and(|d| *d <= now)
+    }
+
+    /// Returns the entries whose deadline is at or before `now` and that have yet to be popped by
+    /// [`pop_expired()`].
+    ///
+    /// [`pop_expired()`]: #method.pop_expired
+    pub(super) fn expired(&self, now: Instant) -> impl Iterator<Item = u64> + '_ {
+        self.order.range(..=(now, u64::MAX)).map(|&(_, h)| h)
+    }
+
+    /// Removes and returns the next entry whose deadline is at or before `now`. The entry's
+    /// deadline is kept, so that it can still be checked by [`is_expired()`] until forgotten.
+    ///
+    /// [`is_expired()`]: #method.is_expired
+    pub(super) fn pop_expired(&mut self, now: Instant) -> Option<u64> {
+        match self.order.iter().next() {
+            Some(&(d, h)) if d <= now => {
+                self.order.remove(&(d, h));
+                Some(h)
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.at.clear();
+        self.order.clear();
+    }
+}
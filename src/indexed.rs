@@ -0,0 +1,387 @@
+/* Notice
+indexed.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! An insertion ordered, `IndexMap` backed variant of `LendingLibrary`.
+//!
+//! This module is only available with the `indexmap` feature enabled.
+
+use super::_panic;
+use indexmap::IndexMap;
+use std::{borrow::Borrow,
+          collections::HashSet,
+          fmt::{Debug, Error as FmtError, Formatter},
+          hash::Hash,
+          iter::FromIterator,
+          ops::{Deref, DerefMut},
+          sync::atomic::{AtomicUsize, Ordering},
+          thread};
+
+enum Slot<V> {
+    Present(V),
+    Loaned,
+}
+
+use self::Slot::{Loaned, Present};
+
+/// A data store that lends temporary ownership of stored values, remembering the order in which
+/// keys were inserted.
+///
+/// This has the same loan semantics as [`LendingLibrary`], but is backed by an `IndexMap` so that
+/// entries are visited in insertion order and can be addressed by position. Loaned entries keep
+/// their position. Entries removed while loaned give up their position immediately, and are
+/// dropped once their loan is returned. Loans hold a copy of their key, so keys must be `Clone`.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::indexed::LendingIndexMap;
+/// let mut lib: LendingIndexMap<&str, i32> = LendingIndexMap::new();
+/// lib.insert("c", 3);
+/// lib.insert("a", 1);
+/// lib.insert("b", 2);
+/// {
+///     let mut v = lib.lend(&"a").unwrap();
+///     *v += 10;
+/// }
+/// let keys: Vec<_> = lib.iter().map(|(k, v)| (*k, *v)).collect();
+/// assert_eq!(keys, vec![("c", 3), ("a", 11), ("b", 2)]);
+/// ```
+pub struct LendingIndexMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    store: IndexMap<K, Slot<V>>,
+    awaiting_drop: HashSet<K>,
+    outstanding: AtomicUsize,
+    name: Option<String>,
+}
+
+/// A smart pointer representing the loan of a value from a `LendingIndexMap` instance.
+pub struct Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    owner: *mut LendingIndexMap<K, V>,
+    key: Option<K>,
+    inner: Option<V>,
+}
+
+impl<K, V> LendingIndexMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Creates a new empty `LendingIndexMap`.
+    /// # Example
+    /// ```
+    /// use lending_library::indexed::LendingIndexMap;
+    /// let mut lib: LendingIndexMap<i32, i32> = LendingIndexMap::new();
+    /// ```
+    pub fn new() -> LendingIndexMap<K, V> {
+        LendingIndexMap::with_capacity(0)
+    }
+
+    /// Creates an empty `LendingIndexMap` with at least the specified capacity.
+    pub fn with_capacity(capacity: usize) -> LendingIndexMap<K, V> {
+        LendingIndexMap {
+            store: IndexMap::with_capacity(capacity),
+            awaiting_drop: HashSet::new(),
+            outstanding: AtomicUsize::new(0),
+            name: None,
+        }
+    }
+
+    /// Creates a new empty `LendingIndexMap` with a name, which is included in any panic messages
+    /// it raises.
+    pub fn named<N: Into<String>>(name: N) -> LendingIndexMap<K, V> {
+        let mut lib = LendingIndexMap::new();
+        lib.name = Some(name.into());
+        lib
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the number of items in the store, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Returns true if the store is empty, including loaned items.
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Returns true if a record with key `key` exists in the store, and false otherwise.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.store.contains_key(key)
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, returning `None` if the key is
+    /// absent or the value is currently loaned.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self.store.get_mut(key) {
+            Some(Present(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the position of `key` in insertion order, including keys whose values are
+    /// currently loaned.
+    pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.store.get_index_of(key)
+    }
+
+    /// Returns the key/value pair at position `index` in insertion order, returning `None` if the
+    /// index is out of bounds or the value is currently loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::indexed::LendingIndexMap;
+    /// let mut lib: LendingIndexMap<&str, i32> = LendingIndexMap::new();
+    /// lib.insert("b", 2);
+    /// lib.insert("a", 1);
+    /// assert_eq!(lib.get_index(1), Some((&"a", &1)));
+    /// let _v = lib.lend(&"a").unwrap();
+    /// assert_eq!(lib.get_index(1), None);
+    /// ```
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        match self.store.get_index(index) {
+            Some((k, Present(v))) => Some((k, v)),
+            _ => None,
+        }
+    }
+
+    /// Inserts a new key/value pair into the store. If a pair with that key already exists, its
+    /// value is replaced in place and the previous value is returned.
+    /// # Panics
+    /// Will panic if the existing value is currently loaned or awaiting drop.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if self.awaiting_drop.contains(&key) {
+            _panic(&self.name, "Cannot overwrite value awaiting drop")
+        }
+        match self.store.get_mut(&key) {
+            Some(slot) => match *slot {
+                Present(ref mut v) => Some(::std::mem::replace(v, val)),
+                Loaned => _panic(&self.name, "Cannot overwrite loaned value"),
+            },
+            None => {
+                self.store.insert(key, Present(val));
+                None
+            }
+        }
+    }
+
+    /// Removes a key/value pair from the store, shifting all following entries down to preserve
+    /// insertion order. Returns true if the key was present in the store and false otherwise. A
+    /// loaned value is dropped when its loan is returned.
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self.store.shift_remove_entry(key) {
+            Some(e) => {
+                self.removed(e);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a key/value pair from the store by swapping it with the last entry, which is O(1)
+    /// but perturbs insertion order. Returns true if the key was present in the store and false
+    /// otherwise. A loaned value is dropped when its loan is returned.
+    /// # Example
+    /// ```
+    /// use lending_library::indexed::LendingIndexMap;
+    /// let mut lib: LendingIndexMap<i32, i32> = (0..4).map(|i| (i, i)).collect();
+    /// assert!(lib.swap_remove(&1));
+    /// let keys: Vec<_> = lib.keys().cloned().collect();
+    /// assert_eq!(keys, vec![0, 3, 2]);
+    /// ```
+    pub fn swap_remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self.store.swap_remove_entry(key) {
+            Some(e) => {
+                self.removed(e);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn removed(&mut self, (k, slot): (K, Slot<V>)) {
+        if let Loaned = slot {
+            self.awaiting_drop.insert(k);
+        }
+    }
+
+    /// Loans a value from the library, returning `Some(Loan<K, V>)` if the value is present, and
+    /// `None` if it is not.
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan.
+    pub fn lend<Q>(&mut self, key: &Q) -> Option<Loan<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let ptr: *mut Self = self;
+        let (_i, k, slot) = self.store.get_full_mut(key)?;
+        let val = match ::std::mem::replace(slot, Loaned) {
+            Present(v) => v,
+            Loaned => _panic(&self.name, "Lending already loaned value"),
+        };
+        let k = k.clone();
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            key: Some(k),
+            inner: Some(val),
+        })
+    }
+
+    /// An iterator visiting all key/value pairs in insertion order.
+    /// # Panics
+    /// The iterator will panic if it encounters an item that is currently loaned from the store,
+    /// so this should only be used where you are sure you have returned all loaned items.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let name = &self.name;
+        self.store.iter().map(move |(k, v)| match *v {
+            Present(ref v) => (k, v),
+            Loaned => _panic(name, "Trying to iterate over a store with loaned items."),
+        })
+    }
+
+    /// An iterator visiting all keys in insertion order, including those whose values are
+    /// currently loaned.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.store.keys()
+    }
+
+    fn checkin(&mut self, key: K, val: V) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        if self.awaiting_drop.remove(&key) {
+            return;
+        }
+        match self.store.get_mut(&key) {
+            Some(slot) => match *slot {
+                Present(_) => _panic(&self.name, "Returning replaced item"),
+                Loaned => *slot = Present(val),
+            },
+            None => _panic(&self.name, "Returning item not from store"),
+        }
+    }
+}
+
+impl<K, V> Drop for LendingIndexMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let count = self.outstanding.load(Ordering::SeqCst);
+            if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.", count))
+            }
+        }
+    }
+}
+
+impl<K, V> Default for LendingIndexMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        LendingIndexMap::new()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for LendingIndexMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut lib = LendingIndexMap::new();
+        for (k, v) in iter {
+            lib.insert(k, v);
+        }
+        lib
+    }
+}
+
+impl<K, V> Debug for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<K, V> PartialEq for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<K, V> Drop for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            unsafe {
+                (*self.owner).checkin(self.key.take().unwrap(), self.inner.take().unwrap());
+            }
+        }
+    }
+}
+
+impl<K, V> Deref for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<K, V> DerefMut for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.inner.as_mut().unwrap()
+    }
+}
@@ -9,12 +9,15 @@ Its licensing is governed by the LICENSE file at the root of the project.
 
 //! Various iterator structs for `LendingLibrary`
 
-use super::{LendingLibrary, State};
-use std::hash::Hash;
+use super::{_panic, LendingLibrary, State};
+use std::{collections::hash_map,
+          hash::Hash,
+          mem,
+          sync::atomic::Ordering};
 
 /// An iterator over the key/value pairs of a `LendingLibrary`
 pub struct Iter<'a, K: 'a, V: 'a> {
-    iter: Box<Iterator<Item = (&'a K, &'a V)> + 'a>,
+    pub(super) iter: Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a>,
 }
 
 impl<'a, K, V> Iterator for Iter<'a, K, V> {
@@ -26,7 +29,7 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
 
 /// A mutable iterator over the key/value pairs of a `LendingLibrary`
 pub struct IterMut<'a, K: 'a, V: 'a> {
-    iter: Box<Iterator<Item = (&'a K, &'a mut V)> + 'a>,
+    iter: Box<dyn Iterator<Item = (&'a K, &'a mut V)> + 'a>,
 }
 
 impl<'a, K, V> Iterator for IterMut<'a, K, V> {
@@ -36,7 +39,83 @@ impl<'a, K, V> Iterator for IterMut<'a, K, V> {
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a LendingLibrary<K, V>
+/// An iterator over the keys of a `LendingLibrary`
+pub struct Keys<'a, K: 'a> {
+    pub(super) iter: Box<dyn Iterator<Item = &'a K> + 'a>,
+}
+
+impl<'a, K> Iterator for Keys<'a, K> {
+    type Item = &'a K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// An iterator over the present values of a `LendingLibrary`
+pub struct Values<'a, V: 'a> {
+    pub(super) iter: Box<dyn Iterator<Item = &'a V> + 'a>,
+}
+
+impl<'a, V> Iterator for Values<'a, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// A mutable iterator over the present values of a `LendingLibrary`
+pub struct ValuesMut<'a, V: 'a> {
+    pub(super) iter: Box<dyn Iterator<Item = &'a mut V> + 'a>,
+}
+
+impl<'a, V> Iterator for ValuesMut<'a, V> {
+    type Item = &'a mut V;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// A draining iterator over the key/value pairs of a `LendingLibrary`
+pub struct Drain<'a, K: 'a, V: 'a> {
+    pub(super) iter: Box<dyn Iterator<Item = (K, V)> + 'a>,
+}
+
+impl<'a, K, V> Iterator for Drain<'a, K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// An iterator which removes and yields the key/value pairs of a `LendingLibrary` that match a
+/// predicate
+pub struct ExtractIf<'a, K: 'a, V: 'a> {
+    pub(super) iter: Box<dyn Iterator<Item = (K, V)> + 'a>,
+}
+
+impl<'a, K, V> Iterator for ExtractIf<'a, K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// An owning iterator over the key/value pairs of a `LendingLibrary`
+pub struct IntoIter<K, V> {
+    iter: hash_map::IntoIter<u64, State<K, V>>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_h, v)| match v {
+            State::Present(k, v) => (k, v),
+            State::Loaned(_) | State::AwaitingDrop(_) => unreachable!(),
+        })
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a LendingLibrary<K, V, S>
 where
     K: Hash,
 {
@@ -44,26 +123,51 @@ where
     type IntoIter = Iter<'a, K, V>;
     fn into_iter(self) -> Self::IntoIter {
         Iter {
-            iter: Box::new(self.store.iter().map(|(_h, v)| match *v {
-                State::Present(ref k, ref v) => (k, v),
-                _ => panic!("Trying to iterate over a store with loaned items."),
+            iter: Box::new(self.store.iter().filter_map(move |(h, v)| match *v {
+                State::Present(..) if self.is_expired(*h) => None,
+                State::Present(ref k, ref v) => Some((k, v)),
+                _ => _panic(&self.name, "Trying to iterate over a store with loaned items."),
             })),
         }
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a mut LendingLibrary<K, V>
+impl<'a, K, V, S> IntoIterator for &'a mut LendingLibrary<K, V, S>
 where
     K: Hash,
 {
     type Item = (&'a K, &'a mut V);
     type IntoIter = IterMut<'a, K, V>;
     fn into_iter(self) -> Self::IntoIter {
+        self.settle();
+        let name = &self.name;
         IterMut {
-            iter: Box::new(self.store.iter_mut().map(|(_h, v)| match *v {
+            iter: Box::new(self.store.values_mut().map(move |v| match *v {
                 State::Present(ref k, ref mut v) => (k, v),
-                _ => panic!("Trying to iterate over a store with loaned items."),
+                _ => _panic(name, "Trying to iterate over a store with loaned items."),
             })),
         }
     }
 }
+
+/// Consumes the library, yielding all of its key/value pairs.
+///
+/// # Panics
+/// Will panic if the library has any outstanding loans, as they would otherwise outlive the store.
+impl<K, V, S> IntoIterator for LendingLibrary<K, V, S>
+where
+    K: Hash,
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.settle();
+        let count = self.outstanding.load(Ordering::SeqCst);
+        if count != 0 {
+            _panic(&self.name, &format!("{} value loans outlived store.", count))
+        }
+        IntoIter {
+            iter: mem::take(&mut self.store).into_iter(),
+        }
+    }
+}
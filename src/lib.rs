@@ -10,6 +10,18 @@ Its licensing is governed by the LICENSE file at the root of the project.
 
 //! A data store that lends temporary ownership of stored values.
 //!
+//! # Naming
+//! Methods follow a common scheme:
+//!
+//! - `lend*` methods hand out a [`Loan`], taking the value out of the store until the loan is
+//!   dropped. `get*` methods hand out plain references and leave the value in place.
+//! - `remove*` methods discard values and report whether anything was there, while `take` gives
+//!   the removed value back to the caller.
+//! - Operations that cannot proceed because a value is on loan panic, naming the library if it has
+//!   a name. Where a `try_*` variant exists it returns an error describing the conflict instead.
+//!
+//! [`Loan`]: struct.Loan.html
+//!
 //! # Example
 //! ```
 //! use lending_library::*;
@@ -81,17 +93,71 @@ Its licensing is governed by the LICENSE file at the root of the project.
 //! }
 //! ```
 
+#[cfg(feature = "indexmap")]
+extern crate indexmap;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+pub mod array;
+pub mod bimap;
+pub mod btree;
+pub mod checked;
+pub mod entry;
+mod error;
+mod expiry;
+#[cfg(feature = "indexmap")]
+pub mod indexed;
 pub mod iter;
 mod loan;
+mod lru;
+pub mod multimap;
+pub mod query;
+pub mod set;
+pub mod slab;
+#[macro_use]
+pub mod slotmap;
 #[cfg(test)]
 mod tests;
+pub mod vec;
+mod weight;
 
-pub use loan::Loan;
+pub use error::{InsertError, InsertErrorKind, LendError, LendErrorKind, LoanedError, RenameError,
+                RenameErrorKind};
+pub use loan::{Loan, LoanWatch, MappedLoan, OutstandingLoan, RawToken, ReadLoan, Reservation,
+               ScopedLoan, SendLoan};
+#[cfg(feature = "serde")]
+pub use loan::WithKey;
 
-use std::{collections::{hash_map::DefaultHasher, HashMap},
-          hash::{Hash, Hasher},
-          sync::atomic::{AtomicUsize, Ordering},
-          thread};
+use expiry::Deadlines;
+use checked::Checked;
+use entry::{Entry, OccupiedEntry, RawEntryBuilderMut, VacantEntry};
+use iter::{Drain, ExtractIf, Iter, Keys, Values, ValuesMut};
+use loan::{Issue, Returned, Returns, Shared, Validator};
+use lru::Recency;
+use query::Query;
+use weight::{Weigher, Weights};
+#[cfg(feature = "debug")]
+use std::panic::Location;
+use std::{borrow::{Borrow, Cow},
+          cell::Cell,
+          collections::{hash_map::RandomState, HashMap, HashSet},
+          convert::TryFrom,
+          fmt::{Debug, Error as FmtError, Formatter},
+          iter::FromIterator,
+          marker::PhantomData,
+          ops::Index,
+          ptr::NonNull,
+          hash::{BuildHasher, Hash},
+          sync::{atomic::{AtomicUsize, Ordering},
+                 Arc,
+                 mpsc::{self, Sender},
+                 LockResult,
+                 PoisonError,
+                 Weak},
+          thread,
+          time::{Duration, Instant}};
 
 enum State<K, V> {
     Present(K, V),
@@ -110,23 +176,95 @@ use self::State::{AwaitingDrop, Loaned, Present};
 /// The main panic condition is that a `Loan` object derived from the `lend` method on a store may
 /// never outlive the store it originated from. If this condition happens the store will generate a
 /// panic as it goes out of scope, noting the number of outstanding `Loan` objects.
-pub struct LendingLibrary<K, V>
+///
+/// # Complexity
+/// The store is backed by a `HashMap`, and its operations carry the same expected costs, where `n`
+/// is the number of entries:
+///
+/// | Operation | Cost |
+/// |-----------|------|
+/// | `insert`, `try_insert`, `insert_if_vacant`, `remove`, `take`, `contains_key`, `get_mut`, `get_key_value` | O(1) amortized |
+/// | `entry`, `raw_entry_mut`, `get_or_insert_with`, `get_or_default`, `reserve_key`, `insert_reserved`, `is_reserved` | O(1) amortized |
+/// | `lend`, `try_lend`, `lend_send`, `lend_shared`, `lend_scoped`, `lend_checked`, `lend_tagged`, `lend_if`, `lend_or_insert_with` | O(1) amortized |
+/// | the return of a `Loan`, `SendLoan` or `ReadLoan` on drop | O(1) amortized |
+/// | `swap_keys`, `rename_key`, `is_poisoned`, `clear_poison`, `watch` | O(1) amortized |
+/// | `len`, `is_empty` | O(1) |
+/// | `insert_many`, `lend_many`, `remove_keys`, `missing_from` | O(k) for `k` keys |
+/// | `append` | O(m) for `m` entries appended |
+/// | `purge_expired` | O(e log n) for `e` expired entries |
+/// | `drain_dirty_keys` | O(d) for `d` dirty entries |
+/// | `outstanding_report` | O(l) for `l` outstanding loans |
+/// | `longest_held` | O(l log l) for `l` outstanding loans |
+/// | `clear`, `drain`, `retain`, `retain_keys`, `extract_if`, `split_off`, `into_vec` | O(n) |
+/// | `iter`, `iter_mut`, `try_iter`, `query`, `keys`, `key_set`, `values`, `values_mut` | O(n) |
+/// | `set_time_to_live`, `set_weigher`, `set_max_weight`, `set_max_entries` | O(n) |
+/// | `into_sorted_vec` | O(n log n) |
+///
+/// When an entry limit or a time to live is set, the store additionally keeps its entries ordered
+/// by last use or by deadline, and every operation that touches an entry pays a further O(log n).
+/// `len` also pays for each entry that has passed its deadline since the library was last
+/// modified, as such entries are still stored until then.
+///
+/// The benchmarks in `benches/` measure the main operations at several store sizes, and can be
+/// run with `cargo bench`. Of these, `cargo bench --bench complexity` fails if the cost of lending,
+/// inserting, looking up or counting grows with the size of the store, or if iterating grows
+/// faster than linearly, and is run by CI.
+///
+/// # Hashing
+/// Keys are hashed with SipHash by default, as for `HashMap`. A different hashing algorithm can be
+/// selected by supplying a `BuildHasher` to [`with_hasher()`] or [`with_capacity_and_hasher()`].
+///
+/// Lookups accept any borrowed form of the key type, such as `&str` for `String` keys, provided
+/// that `Hash` and `Eq` on the borrowed form match those on the key type itself.
+///
+/// Keys are compared on lookup, so keys whose hashes collide are kept apart. A colliding key is
+/// stored in the next free slot after its hash, and lookups probe as many slots as the longest
+/// run of collisions seen so far. With a hasher that rarely collides this is a single slot.
+///
+/// [`with_hasher()`]: #method.with_hasher
+/// [`with_capacity_and_hasher()`]: #method.with_capacity_and_hasher
+pub struct LendingLibrary<K, V, S = RandomState>
 where
     K: Hash,
 {
     store: HashMap<u64, State<K, V>>,
+    awaiting: usize,
+    probes: AtomicUsize,
+    shared: HashMap<u64, Shared<V>>,
+    poisoned: HashSet<u64>,
+    dirty: HashSet<u64>,
+    reserved: HashSet<u64>,
+    generation: u64,
+    issued: HashMap<u64, Issue>,
+    watchers: HashMap<u64, Vec<Sender<()>>>,
+    returns: Option<Returns<V>>,
+    alive: Arc<()>,
     outstanding: AtomicUsize,
+    escrowed: AtomicUsize,
+    name: Option<String>,
+    normalizer: Option<Arc<dyn Fn(V) -> V + Send + Sync>>,
+    max_entries: Option<usize>,
+    recency: Recency,
+    evictor: Option<Arc<dyn Fn(K, V) + Send + Sync>>,
+    validator: Option<Validator<K, V>>,
+    max_weight: Option<usize>,
+    weigher: Option<Weigher<K, V>>,
+    weights: Weights,
+    ttl: Option<Duration>,
+    deadlines: Deadlines,
+    hasher: S,
 }
 
-fn _hash<K:Hash>(val: &K) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    (*val).hash(&mut hasher);
-    hasher.finish()
+fn _panic(name: &Option<String>, msg: &str) -> ! {
+    match *name {
+        Some(ref n) => panic!("Library \"{}\": {}", n, msg),
+        None => panic!("{}", msg),
+    }
 }
 
-impl<K, V> LendingLibrary<K, V>
+impl<K, V> LendingLibrary<K, V, RandomState>
 where
-    K: Hash,
+    K: Hash + Eq,
 {
     /// Creates a new empty `LendingLibrary`.
     /// # Example
@@ -135,10 +273,22 @@ where
     /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
     /// ```
     pub fn new() -> LendingLibrary<K, V> {
-        LendingLibrary {
-            store: HashMap::new(),
-            outstanding: AtomicUsize::new(0),
-        }
+        LendingLibrary::with_capacity(0)
+    }
+
+    /// Creates a new empty `LendingLibrary` with a name that is included in any panic messages it
+    /// generates, making it possible to tell stores apart when several are in use. The name is also
+    /// shown by every error it returns.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let lib: LendingLibrary<i32, i32> = LendingLibrary::named("sessions");
+    /// assert_eq!(lib.name(), Some("sessions"));
+    /// ```
+    pub fn named<N: Into<String>>(name: N) -> LendingLibrary<K, V> {
+        let mut lib = LendingLibrary::new();
+        lib.name = Some(name.into());
+        lib
     }
 
     /// Creates an empty `LendingLibrary` with at least the specified capacity.
@@ -149,12 +299,386 @@ where
     /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::with_capacity(100);
     /// ```
     pub fn with_capacity(capacity: usize) -> LendingLibrary<K, V> {
+        LendingLibrary::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> LendingLibrary<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Creates an empty `LendingLibrary` which will use the given hash builder to hash keys.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::collections::hash_map::RandomState;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::with_hasher(RandomState::new());
+    /// lib.insert(1, 1);
+    /// ```
+    pub fn with_hasher(hasher: S) -> LendingLibrary<K, V, S> {
+        LendingLibrary::with_capacity_and_hasher(0, hasher)
+    }
+
+    /// Creates an empty `LendingLibrary` with at least the specified capacity, which will use the
+    /// given hash builder to hash keys.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::collections::hash_map::RandomState;
+    /// let lib: LendingLibrary<i32, i32> =
+    ///     LendingLibrary::with_capacity_and_hasher(100, RandomState::new());
+    /// assert!(lib.capacity() >= 100);
+    /// ```
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> LendingLibrary<K, V, S> {
         LendingLibrary {
             store: HashMap::with_capacity(capacity),
+            awaiting: 0,
+            probes: AtomicUsize::new(0),
+            shared: HashMap::new(),
+            poisoned: HashSet::new(),
+            dirty: HashSet::new(),
+            reserved: HashSet::new(),
+            generation: 0,
+            issued: HashMap::new(),
+            watchers: HashMap::new(),
+            returns: None,
+            alive: Arc::new(()),
             outstanding: AtomicUsize::new(0),
+            escrowed: AtomicUsize::new(0),
+            name: None,
+            normalizer: None,
+            max_entries: None,
+            recency: Recency::default(),
+            evictor: None,
+            validator: None,
+            max_weight: None,
+            weigher: None,
+            weights: Weights::default(),
+            ttl: None,
+            deadlines: Deadlines::default(),
+            hasher,
+        }
+    }
+
+    /// Returns a reference to the library's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Returns the slot in the store holding `key`, or the first free slot it would be inserted
+    /// into. Keys whose hashes collide are placed in the slots following their hash, so lookups
+    /// probe as many slots as the longest run of collisions so far, comparing keys as they go.
+    fn hash_key<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let base = self.hasher.hash_one(key);
+        let probes = self.probes.load(Ordering::Relaxed);
+        let mut free = None;
+        for i in 0..=probes {
+            let h = base.wrapping_add(i as u64);
+            match self.store.get(&h) {
+                Some(Present(k, _)) | Some(Loaned(k)) | Some(AwaitingDrop(k))
+                    if k.borrow() == key =>
+                {
+                    return h
+                }
+                Some(_) => {}
+                None => {
+                    free.get_or_insert(h);
+                }
+            }
+        }
+        free.unwrap_or_else(|| {
+            self.probes.fetch_max(probes + 1, Ordering::Relaxed);
+            base.wrapping_add(probes as u64 + 1)
+        })
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// assert_eq!(lib.name(), None);
+    /// ```
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Sets a normalizer that is applied to every loaned value as it is returned to the library,
+    /// before it is stored back. This can be used to keep values in a canonical form without
+    /// relying on every borrower to tidy up after themselves.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, Vec<i32>> = LendingLibrary::new();
+    /// lib.set_normalizer(|mut v: Vec<i32>| {
+    ///     v.sort();
+    ///     v
+    /// });
+    /// lib.insert(1, vec![]);
+    /// {
+    ///     let mut v = lib.lend(&1).unwrap();
+    ///     v.push(3);
+    ///     v.push(1);
+    /// }
+    /// assert_eq!(*lib.lend(&1).unwrap(), vec![1, 3]);
+    /// ```
+    pub fn set_normalizer<F>(&mut self, f: F)
+    where
+        F: Fn(V) -> V + Send + Sync + 'static,
+    {
+        self.normalizer = Some(Arc::new(f));
+    }
+
+    /// Removes any normalizer set by [`set_normalizer()`].
+    ///
+    /// [`set_normalizer()`]: #method.set_normalizer
+    pub fn clear_normalizer(&mut self) {
+        self.normalizer = None;
+    }
+
+    /// Returns the entry limit set by [`set_max_entries()`], if any.
+    ///
+    /// [`set_max_entries()`]: #method.set_max_entries
+    pub fn max_entries(&self) -> Option<usize> {
+        self.max_entries
+    }
+
+    /// Limits the library to `max` entries, turning it into a least-recently-used cache, or lifts
+    /// the limit if `max` is `None`.
+    ///
+    /// Inserting, lending, returning or mutably borrowing a value counts as a use. Once the limit
+    /// is exceeded, the least recently used values are evicted, passing them to any hook set by
+    /// [`set_eviction_hook()`]. Loaned values are never evicted, so the library may stay over its
+    /// limit until enough of them are returned. Values removed while loaned count towards the
+    /// limit until their loans are returned.
+    ///
+    /// [`set_eviction_hook()`]: #method.set_eviction_hook
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.set_max_entries(Some(2));
+    /// lib.insert(1, 1);
+    /// lib.insert(2, 2);
+    /// {
+    ///     let _v = lib.lend(&1).unwrap();
+    ///     lib.insert(3, 3);
+    /// }
+    /// assert!(lib.contains_key(&1));
+    /// assert!(!lib.contains_key(&2));
+    /// assert!(lib.contains_key(&3));
+    /// ```
+    pub fn set_max_entries(&mut self, max: Option<usize>) {
+        let was_limited = self.is_limited();
+        self.max_entries = max;
+        self.limits_changed(was_limited);
+    }
+
+    /// Returns the weight budget set by [`set_max_weight()`], if any.
+    ///
+    /// [`set_max_weight()`]: #method.set_max_weight
+    pub fn max_weight(&self) -> Option<usize> {
+        self.max_weight
+    }
+
+    /// Returns the total weight of the values in the library, as measured by the function set by
+    /// [`set_weigher()`]. This is only tracked while a budget is set by [`set_max_weight()`].
+    ///
+    /// [`set_weigher()`]: #method.set_weigher
+    /// [`set_max_weight()`]: #method.set_max_weight
+    pub fn total_weight(&self) -> usize {
+        self.weights.total()
+    }
+
+    /// Limits the total weight of the values in the library to `max`, or lifts the limit if `max`
+    /// is `None`. Values are weighed by the function set by [`set_weigher()`], or count as one
+    /// each if none is set.
+    ///
+    /// Values are weighed when inserted and when their loans are returned. Once the budget is
+    /// exceeded, the least recently used values are evicted as for [`set_max_entries()`], and
+    /// loaned values still count towards it; values already on loan when a budget is first set
+    /// start counting once they are returned. [`try_insert()`] rejects values that cannot fit even
+    /// after evicting everything that is not on loan.
+    ///
+    /// [`set_weigher()`]: #method.set_weigher
+    /// [`set_max_entries()`]: #method.set_max_entries
+    /// [`try_insert()`]: #method.try_insert
+    /// # Example
+    /// ```
+    /// use lending_library::{InsertErrorKind, LendingLibrary};
+    /// let mut lib: LendingLibrary<i32, Vec<u8>> = LendingLibrary::new();
+    /// lib.set_weigher(|_k, v| v.len());
+    /// lib.set_max_weight(Some(10));
+    /// lib.insert(1, vec![0; 4]);
+    /// lib.insert(2, vec![0; 4]);
+    /// lib.insert(3, vec![0; 4]);
+    /// assert!(!lib.contains_key(&1));
+    /// assert_eq!(lib.total_weight(), 8);
+    /// let _v = lib.lend(&2).unwrap();
+    /// let err = lib.try_insert(4, vec![0; 8]).unwrap_err();
+    /// assert_eq!(err.kind(), InsertErrorKind::OverWeight);
+    /// ```
+    pub fn set_max_weight(&mut self, max: Option<usize>) {
+        let was_limited = self.is_limited();
+        self.max_weight = max;
+        self.reweigh();
+        self.limits_changed(was_limited);
+    }
+
+    /// Sets the function used to weigh values against the budget set by [`set_max_weight()`],
+    /// such as an estimate of their memory footprint.
+    ///
+    /// [`set_max_weight()`]: #method.set_max_weight
+    pub fn set_weigher<F>(&mut self, f: F)
+    where
+        F: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        self.weigher = Some(Arc::new(f));
+        self.reweigh();
+        self.evict_excess(None);
+    }
+
+    /// Removes any function set by [`set_weigher()`], so that each value counts as one.
+    ///
+    /// [`set_weigher()`]: #method.set_weigher
+    pub fn clear_weigher(&mut self) {
+        self.weigher = None;
+        self.reweigh();
+        self.evict_excess(None);
+    }
+
+    /// Sets a function that is handed each key/value pair evicted to keep the library within the
+    /// limits set by [`set_max_entries()`] and [`set_max_weight()`]. Without a hook, evicted pairs
+    /// are dropped.
+    ///
+    /// [`set_max_entries()`]: #method.set_max_entries
+    /// [`set_max_weight()`]: #method.set_max_weight
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::sync::{Arc, Mutex};
+    /// let evicted = Arc::new(Mutex::new(Vec::new()));
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// let sink = evicted.clone();
+    /// lib.set_eviction_hook(move |k, v| sink.lock().unwrap().push((k, v)));
+    /// lib.set_max_entries(Some(1));
+    /// lib.insert(1, 10);
+    /// lib.insert(2, 20);
+    /// assert_eq!(*evicted.lock().unwrap(), vec![(1, 10)]);
+    /// ```
+    pub fn set_eviction_hook<F>(&mut self, f: F)
+    where
+        F: Fn(K, V) + Send + Sync + 'static,
+    {
+        self.evictor = Some(Arc::new(f));
+    }
+
+    /// Removes any hook set by [`set_eviction_hook()`].
+    ///
+    /// [`set_eviction_hook()`]: #method.set_eviction_hook
+    pub fn clear_eviction_hook(&mut self) {
+        self.evictor = None;
+    }
+
+    /// Sets a function that checks each value returned from a loan through which it was mutably
+    /// accessed. If it returns an error, the value is handed to `on_reject` along with the error
+    /// instead of being stored; `on_reject` returns the value to store in its place, or `None` to
+    /// remove the entry.
+    ///
+    /// Values are checked after the normalizer set by [`set_normalizer()`] has been applied.
+    ///
+    /// [`set_normalizer()`]: #method.set_normalizer
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<&str, Vec<u32>> = LendingLibrary::new();
+    /// lib.set_validator(
+    ///     |_, v: &Vec<u32>| if v.len() <= 2 { Ok(()) } else { Err(v.len()) },
+    ///     |k, _, len| {
+    ///         println!("{} grew to {} items, discarding", k, len);
+    ///         None
+    ///     },
+    /// );
+    /// lib.insert("a", vec![1]);
+    /// lib.lend(&"a").unwrap().push(2);
+    /// assert!(lib.contains_key(&"a"));
+    /// lib.lend(&"a").unwrap().push(3);
+    /// assert!(!lib.contains_key(&"a"));
+    /// ```
+    pub fn set_validator<F, R, E>(&mut self, validator: F, on_reject: R)
+    where
+        F: Fn(&K, &V) -> Result<(), E> + Send + Sync + 'static,
+        R: Fn(&K, V, E) -> Option<V> + Send + Sync + 'static,
+    {
+        self.validator = Some(Arc::new(move |k: &K, v: V| match validator(k, &v) {
+            Ok(()) => Some(v),
+            Err(e) => on_reject(k, v, e),
+        }));
+    }
+
+    /// Removes any validator set by [`set_validator()`].
+    ///
+    /// [`set_validator()`]: #method.set_validator
+    pub fn clear_validator(&mut self) {
+        self.validator = None;
+    }
+
+    /// Returns the time to live set by [`set_time_to_live()`], if any.
+    ///
+    /// [`set_time_to_live()`]: #method.set_time_to_live
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Sets how long values live after being inserted, or lets them live indefinitely if `ttl` is
+    /// `None`. Values already in the library are given the new time to live from now.
+    ///
+    /// Expired values are treated as absent by lookups, lending, iterators and [`len()`], and are
+    /// purged as the library is modified. Call [`purge_expired()`] to remove them all at once. A
+    /// loaned value is not affected by expiry until its loan is returned, at which point it is
+    /// dropped if it has expired.
+    ///
+    /// [`len()`]: #method.len
+    /// [`purge_expired()`]: #method.purge_expired
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::{thread, time::Duration};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.set_time_to_live(Some(Duration::from_millis(10)));
+    /// lib.insert(1, 1);
+    /// assert!(lib.contains_key(&1));
+    /// thread::sleep(Duration::from_millis(20));
+    /// assert!(!lib.contains_key(&1));
+    /// assert!(lib.lend(&1).is_none());
+    /// ```
+    pub fn set_time_to_live(&mut self, ttl: Option<Duration>) {
+        self.ttl = ttl;
+        self.deadlines.clear();
+        if let Some(ttl) = ttl {
+            let deadline = Instant::now() + ttl;
+            for h in self.store.keys() {
+                self.deadlines.set(*h, deadline);
+            }
         }
     }
 
+    /// Removes every value that has outlived the time to live set by [`set_time_to_live()`],
+    /// returning how many were removed. Loaned values are left until their loans are returned.
+    ///
+    /// [`set_time_to_live()`]: #method.set_time_to_live
+    pub fn purge_expired(&mut self) -> usize {
+        self.collect_returns();
+        self.expire()
+    }
+
     /// Returns the number of elements the library can store without reallocating.
     /// The same bounds as [`HashMap::capacity()`] apply.
     ///
@@ -195,6 +719,38 @@ where
         self.store.shrink_to_fit()
     }
 
+    /// Reduces the stores capacity with a lower limit. The capacity will remain at least as large
+    /// as both `min_capacity` and the minimum currently required.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::with_capacity(100);
+    /// lib.insert(1, 1);
+    /// lib.shrink_to(10);
+    /// assert!(lib.capacity() >= 10 && lib.capacity() < 100);
+    /// lib.shrink_to(0);
+    /// assert!(lib.capacity() >= 1);
+    /// ```
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        self.store.shrink_to(min_capacity)
+    }
+
+    /// Returns a [`Checked`] view of the library, whose operations return an error wherever the
+    /// library's own would panic.
+    ///
+    /// [`Checked`]: checked/struct.Checked.html
+    /// # Example
+    /// ```
+    /// use lending_library::{checked::ErrorKind, LendingLibrary};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let _v = lib.lend(&1).unwrap();
+    /// assert_eq!(lib.checked().drain().err().unwrap().kind(), ErrorKind::OutstandingLoans(1));
+    /// ```
+    pub fn checked(&mut self) -> Checked<'_, K, V, S> {
+        Checked { lib: self }
+    }
+
     /// An iterator visiting all key/value pairs in arbitary order.
     /// The item type is `(&'a K, &'a V)`
     /// # Panics
@@ -210,215 +766,2431 @@ where
     /// The iterator will panic if it encounters an item that is currently loaned from the store,
     /// so this should only be used where you are sure you have returned all loaned items.
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.settle();
         self.into_iter()
     }
 
-    /// Returns the number of items in the store.
+    /// An iterator visiting all key/value pairs in arbitary order, like [`iter()`], but which checks
+    /// up front that nothing is loaned rather than panicking part way through iteration.
+    /// The item type is `(&'a K, &'a V)`
+    ///
+    /// [`iter()`]: #method.iter
+    /// # Errors
+    /// Returns a `LoanedError` listing the loaned keys if any values are currently loaned.
     /// # Example
     /// ```
     /// use lending_library::LendingLibrary;
-    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
-    /// lib.insert(1, 1);
-    /// lib.insert(2, 1);
-    /// assert_eq!(lib.len(), 2);
+    /// let mut lib: LendingLibrary<i32, i32> = (0..10).map(|i| (i, i)).collect();
+    /// let total: i32 = lib.try_iter().unwrap().filter(|&(k, _)| k % 2 == 0).map(|(_, v)| v).sum();
+    /// assert_eq!(total, 20);
+    /// let _v = lib.lend(&3).unwrap();
+    /// assert_eq!(lib.try_iter().err().unwrap().keys(), &[&3]);
     /// ```
-    pub fn len(&self) -> usize {
-        self.store
-            .iter()
-            .map(|(_k, v)| match v {
-                Present(..) | Loaned(_) => 1,
-                AwaitingDrop(_) => 0,
+    pub fn try_iter(&self) -> Result<Iter<'_, K, V>, LoanedError<'_, K>> {
+        let loaned: Vec<&K> = self.store
+            .values()
+            .filter_map(|v| match *v {
+                Loaned(ref k) => Some(k),
+                Present(..) | AwaitingDrop(_) => None,
             })
-            .sum()
+            .collect();
+        if !loaned.is_empty() {
+            return Err(LoanedError {
+                keys: loaned,
+                name: self.name.as_deref(),
+            });
+        }
+        Ok(Iter {
+            iter: Box::new(self.store.iter().filter_map(move |(h, v)| match *v {
+                Present(ref k, ref v) if !self.is_expired(*h) => Some((k, v)),
+                Present(..) | Loaned(_) | AwaitingDrop(_) => None,
+            })),
+        })
     }
 
-    /// Returns true if the store is empty and false otherwise.
+    /// Starts a [`Query`] over the present entries of the library. The query is run in a single
+    /// pass, and fails without a partial result if any value is on loan.
+    ///
+    /// [`Query`]: query/struct.Query.html
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..10).map(|i| (i, i)).collect();
+    /// let mut evens: Vec<i32> = lib.query()
+    ///     .filter(|k, _v| k % 2 == 0)
+    ///     .map(|(_k, v)| v * 10)
+    ///     .collect()
+    ///     .unwrap();
+    /// evens.sort();
+    /// assert_eq!(evens, vec![0, 20, 40, 60, 80]);
+    /// let _v = lib.lend(&3).unwrap();
+    /// assert_eq!(lib.query().count().unwrap_err().keys(), &[&3]);
+    /// ```
+    pub fn query(&self) -> Query<'_, K, V, (&K, &V), S> {
+        Query {
+            lib: self,
+            filter: Box::new(|_, _| true),
+            map: Box::new(|k, v| (k, v)),
+        }
+    }
+
+    /// An iterator visiting all keys in arbitary order, including those whose values are currently
+    /// loaned. The item type is `&'a K`
     /// # Example
     /// ```
     /// use lending_library::LendingLibrary;
     /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
-    /// assert!(lib.is_empty());
     /// lib.insert(1, 1);
     /// lib.insert(2, 1);
-    /// assert!(!lib.is_empty());
+    /// let _v = lib.lend(&1).unwrap();
+    /// let mut keys: Vec<_> = lib.keys().cloned().collect();
+    /// keys.sort();
+    /// assert_eq!(keys, vec![1, 2]);
     /// ```
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    pub fn keys(&self) -> Keys<'_, K> {
+        Keys {
+            iter: Box::new(self.store.iter().filter_map(move |(h, v)| match *v {
+                Present(ref k, _) if !self.is_expired(*h) => Some(k),
+                Loaned(ref k) => Some(k),
+                Present(..) | AwaitingDrop(_) => None,
+            })),
+        }
     }
 
-    /// Removes all items from the store.
+    /// Returns the set of keys in the library, including those whose values are currently loaned,
+    /// for comparing against external key lists.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::collections::HashSet;
+    /// let lib: LendingLibrary<i32, i32> = (0..4).map(|i| (i, i)).collect();
+    /// let external: HashSet<i32> = [2, 3, 4].iter().cloned().collect();
+    /// let mut stale: Vec<_> = lib.key_set().into_iter().filter(|k| !external.contains(k)).collect();
+    /// stale.sort();
+    /// assert_eq!(stale, vec![&0, &1]);
+    /// ```
+    pub fn key_set(&self) -> HashSet<&K>
+    where
+        K: Eq,
+    {
+        self.keys().collect()
+    }
+
+    /// Returns the keys of every value that has been mutably accessed through a loan since the
+    /// last call, and clears the record. Values changed directly, such as through [`get_mut()`],
+    /// are not tracked.
+    ///
+    /// [`get_mut()`]: #method.get_mut
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..3).map(|i| (i, i)).collect();
+    /// *lib.lend(&1).unwrap() += 1;
+    /// let _ = *lib.lend(&2).unwrap();
+    /// assert_eq!(lib.drain_dirty_keys(), vec![&1]);
+    /// assert!(lib.drain_dirty_keys().is_empty());
+    /// ```
+    pub fn drain_dirty_keys(&mut self) -> Vec<&K> {
+        self.settle();
+        let dirty = ::std::mem::take(&mut self.dirty);
+        let store = &self.store;
+        dirty
+            .into_iter()
+            .filter_map(|h| match store.get(&h) {
+                Some(Present(k, _)) | Some(Loaned(k)) => Some(k),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Lists every outstanding loan, with the tag it was lent with by [`lend_tagged()`], for
+    /// tracking down loans that are never returned. A value lent to several readers at once is
+    /// listed once.
+    ///
+    /// With the `debug` feature enabled, each entry also records the location of the call that
+    /// lent the value, and these locations are added to the panic raised when loans outlive the
+    /// store.
+    ///
+    /// [`lend_tagged()`]: #method.lend_tagged
     /// # Example
     /// ```
     /// use lending_library::LendingLibrary;
     /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
     /// lib.insert(1, 1);
-    /// lib.insert(2, 1);
-    /// {
-    ///     let v = lib.lend(&2).unwrap();
-    ///     assert_eq!(*v, 1);
-    /// }
-    /// lib.clear();
-    /// assert_eq!(lib.lend(&1), None);
+    /// let v = lib.lend_tagged(&1, "renderer").unwrap();
+    /// let report = lib.outstanding_report();
+    /// assert_eq!(report.len(), 1);
+    /// assert_eq!((report[0].key(), report[0].tag()), (&1, Some("renderer")));
     /// ```
-    pub fn clear(&mut self) {
-        let new_store = self.store
-            .drain()
-            .filter(|&(_k, ref v)| match v {
-                Present(..) => false,
-                Loaned(_) | AwaitingDrop(_) => true,
-            })
-            .map(|(h, v)| match v {
-                Loaned(k) | AwaitingDrop(k) => (h, AwaitingDrop(k)),
-                Present(..) => unreachable!(),
+    pub fn outstanding_report(&self) -> Vec<OutstandingLoan<'_, K>> {
+        self.issued
+            .iter()
+            .filter_map(|(h, i)| match self.store.get(h) {
+                Some(Loaned(k)) | Some(AwaitingDrop(k)) => Some(OutstandingLoan {
+                    key: k,
+                    tag: i.tag.as_deref(),
+                    generation: i.generation,
+                    issued: i.at,
+                    #[cfg(feature = "debug")]
+                    location: i.location,
+                }),
+                _ => None,
             })
-            .collect();
-        self.store = new_store;
+            .collect()
     }
 
-    /// Returns true if a record with key `key` exists in the store, and false otherwise.
+    /// Lists up to `n` outstanding loans, starting with the one that has been held the longest,
+    /// to help find borrowers that hold on to values for too long.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..3).map(|i| (i, i)).collect();
+    /// let a = lib.lend_tagged(&0, "slow").unwrap();
+    /// let b = lib.lend(&1).unwrap();
+    /// let c = lib.lend(&2).unwrap();
+    /// let held = lib.longest_held(2);
+    /// assert_eq!(held.len(), 2);
+    /// assert_eq!(held[0].tag(), Some("slow"));
+    /// assert!(held[0].held_for() >= held[1].held_for());
+    /// ```
+    pub fn longest_held(&self, n: usize) -> Vec<OutstandingLoan<'_, K>> {
+        let mut held = self.outstanding_report();
+        held.sort_by_key(|l| l.generation);
+        held.truncate(n);
+        held
+    }
+
+    /// An iterator visiting the values of all present entries in arbitary order, skipping any that
+    /// are currently loaned. The item type is `&'a V`
     /// # Example
     /// ```
     /// use lending_library::LendingLibrary;
     /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
-    /// assert!(!lib.contains_key(&1));
     /// lib.insert(1, 1);
-    /// assert!(lib.contains_key(&1));
+    /// lib.insert(2, 2);
+    /// let _v = lib.lend(&1).unwrap();
+    /// assert_eq!(lib.values().collect::<Vec<_>>(), vec![&2]);
     /// ```
-    pub fn contains_key(&self, key: &K) -> bool {
-        let h = _hash(key);
-        match self.store.get(&h) {
-            Some(v) => match v {
-                Present(..) | Loaned(_) => true,
-                AwaitingDrop(_) => false,
-            },
-            None => false,
+    pub fn values(&self) -> Values<'_, V> {
+        Values {
+            iter: Box::new(self.store.iter().filter_map(move |(h, v)| match *v {
+                Present(_, ref v) if !self.is_expired(*h) => Some(v),
+                Present(..) | Loaned(_) | AwaitingDrop(_) => None,
+            })),
         }
     }
 
-    /// Inserts a new key/value pair into the store. If a pair with that key already exists, the
-    /// previous values will be returned as `Some(V)`, otherwise the method returns `None`.
-    /// # Panics
-    /// The method will panic if you attempt to overwrite a key/value pair that is currently loaned.
+    /// An iterator visiting mutable references to the values of all present entries in arbitary
+    /// order, skipping any that are currently loaned. The item type is `&'a mut V`
     /// # Example
     /// ```
     /// use lending_library::LendingLibrary;
     /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
     /// lib.insert(1, 1);
-    /// lib.insert(2, 1);
+    /// lib.insert(2, 2);
+    /// for v in lib.values_mut() {
+    ///     *v *= 10;
+    /// }
+    /// assert_eq!(*lib.lend(&2).unwrap(), 20);
     /// ```
-    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
-        let h = _hash(&key);
-        match self.store.insert(h, Present(key, val)) {
-            Some(v) => match v {
-                Present(_, v) => Some(v),
-                Loaned(_) => panic!("Cannot overwrite loaned value"),
-                AwaitingDrop(_) => panic!("Cannot overwrite value awaiting drop"),
-            },
-            None => None,
+    pub fn values_mut(&mut self) -> ValuesMut<'_, V> {
+        self.settle();
+        ValuesMut {
+            iter: Box::new(self.store.values_mut().filter_map(|v| match *v {
+                Present(_, ref mut v) => Some(v),
+                Loaned(_) | AwaitingDrop(_) => None,
+            })),
         }
     }
 
-    /// Removes a key/value pair from the store. Returning true if the key was present in the store
-    /// and false otherwise.
+    /// Returns the number of loaned values that are currently held in raw escrow via
+    /// [`Loan::into_raw()`].
+    ///
+    /// [`Loan::into_raw()`]: struct.Loan.html#method.into_raw
     /// # Example
     /// ```
-    /// use lending_library::LendingLibrary;
+    /// use lending_library::{LendingLibrary, Loan};
     /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
-    /// assert!(!lib.remove(&1));
     /// lib.insert(1, 1);
-    /// assert!(lib.contains_key(&1));
-    /// assert!(lib.remove(&1));
-    /// assert!(!lib.contains_key(&1));
-    /// assert!(!lib.remove(&1));
+    /// let (ptr, token) = Loan::into_raw(lib.lend(&1).unwrap());
+    /// assert_eq!(lib.raw_escrows(), 1);
+    /// drop(unsafe { Loan::from_raw(token, ptr) });
+    /// assert_eq!(lib.raw_escrows(), 0);
     /// ```
-    pub fn remove(&mut self, key: &K) -> bool {
-        let h = _hash(key);
-        match self.store.remove(&h){
-            Some(v) => match v {
-                Present(..) => true,
-                Loaned(k) => {
-                    self.store.insert(h,AwaitingDrop(k));
-                    true
-                }
+    pub fn raw_escrows(&self) -> usize {
+        self.escrowed.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of items in the store, including those that are currently loaned.
+    ///
+    /// This keeps a running count, so takes constant time other than for entries that have passed
+    /// their deadline since the library was last modified, which are still stored but not counted.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// lib.insert(2, 1);
+    /// assert_eq!(lib.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        let expired = match self.ttl {
+            Some(_) => self.deadlines
+                .expired(Instant::now())
+                .filter(|h| matches!(self.store.get(h), Some(Present(..))))
+                .count(),
+            None => 0,
+        };
+        self.store.len() - self.awaiting - expired
+    }
+
+    /// Returns true if the store is empty and false otherwise.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// assert!(lib.is_empty());
+    /// lib.insert(1, 1);
+    /// lib.insert(2, 1);
+    /// assert!(!lib.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all items from the store.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// lib.insert(2, 1);
+    /// {
+    ///     let v = lib.lend(&2).unwrap();
+    ///     assert_eq!(*v, 1);
+    /// }
+    /// lib.clear();
+    /// assert_eq!(lib.lend(&1), None);
+    /// ```
+    pub fn clear(&mut self) {
+        self.settle();
+        let mut removed = Vec::new();
+        let new_store = self.store
+            .drain()
+            .filter_map(|(h, v)| match v {
+                Present(..) => {
+                    removed.push(h);
+                    None
+                }
+                Loaned(k) | AwaitingDrop(k) => Some((h, AwaitingDrop(k))),
+            })
+            .collect();
+        self.store = new_store;
+        self.awaiting = self.store.len();
+        for h in removed {
+            self.forget(h);
+        }
+        self.recency.clear();
+        self.deadlines.clear();
+    }
+
+    /// Clears the store, returning all key/value pairs as an iterator.
+    /// # Panics
+    /// Will panic before removing anything if the store has any outstanding loans, as their values
+    /// could not be returned once the store has been emptied. The panic message lists the keys of
+    /// the loaned values.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// lib.insert(2, 1);
+    /// let mut pairs: Vec<_> = lib.drain().collect();
+    /// pairs.sort();
+    /// assert_eq!(pairs, vec![(1, 1), (2, 1)]);
+    /// assert!(lib.is_empty());
+    /// ```
+    pub fn drain(&mut self) -> Drain<'_, K, V>
+    where
+        K: Debug,
+    {
+        self.settle();
+        if self.outstanding.load(Ordering::SeqCst) != 0 {
+            _panic(
+                &self.name,
+                &format!("Cannot drain a store with loaned items: {:?}", self.loaned_keys()),
+            )
+        }
+        self.drain_settled()
+    }
+
+    fn drain_settled(&mut self) -> Drain<'_, K, V> {
+        self.recency.clear();
+        self.deadlines.clear();
+        self.weights.clear();
+        self.poisoned.clear();
+        self.dirty.clear();
+        Drain {
+            iter: Box::new(self.store.drain().map(|(_h, v)| match v {
+                Present(k, v) => (k, v),
+                Loaned(_) | AwaitingDrop(_) => unreachable!(),
+            })),
+        }
+    }
+
+    /// Consumes the library, returning all of its key/value pairs in arbitary order.
+    /// # Panics
+    /// Will panic if the library has any outstanding loans, as they would otherwise outlive the
+    /// store.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let lib: LendingLibrary<i32, i32> = LendingLibrary::from(vec![(1, 1), (2, 2)]);
+    /// assert_eq!(lib.into_vec().len(), 2);
+    /// ```
+    pub fn into_vec(self) -> Vec<(K, V)> {
+        self.into_iter().collect()
+    }
+
+    /// Consumes the library, returning all of its key/value pairs sorted by key, for reproducible
+    /// output.
+    /// # Panics
+    /// Will panic if the library has any outstanding loans, as they would otherwise outlive the
+    /// store.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let lib: LendingLibrary<i32, &str> = LendingLibrary::from(vec![(2, "b"), (1, "a")]);
+    /// assert_eq!(lib.into_sorted_vec(), vec![(1, "a"), (2, "b")]);
+    /// ```
+    pub fn into_sorted_vec(self) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        let mut v = self.into_vec();
+        v.sort_by(|a, b| a.0.cmp(&b.0));
+        v
+    }
+
+    /// Retains only the present entries for which `f` returns true, removing the rest. Entries that
+    /// are currently loaned are skipped and always retained.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// for i in 0..6 {
+    ///     lib.insert(i, i);
+    /// }
+    /// {
+    ///     let _v = lib.lend(&1).unwrap();
+    ///     lib.retain(|_k, v| *v % 2 == 0);
+    /// }
+    /// assert_eq!(lib.len(), 4);
+    /// assert!(lib.contains_key(&1));
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.settle();
+        let mut removed = Vec::new();
+        self.store.retain(|h, v| match *v {
+            Present(ref k, ref mut v) => {
+                let keep = f(k, v);
+                if !keep {
+                    removed.push(*h);
+                }
+                keep
+            }
+            Loaned(_) | AwaitingDrop(_) => true,
+        });
+        for h in removed {
+            self.forget(h);
+        }
+    }
+
+    /// Creates an iterator which removes and yields the present entries for which `f` returns
+    /// true, leaving the rest in place. Entries that are currently loaned are skipped and always
+    /// retained. If the iterator is dropped before being fully consumed, the entries it has not
+    /// yet visited are left in the library.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..6).map(|i| (i, i)).collect();
+    /// {
+    ///     let _v = lib.lend(&2).unwrap();
+    ///     let mut evens: Vec<_> = lib.extract_if(|_k, v| *v % 2 == 0).collect();
+    ///     evens.sort();
+    ///     assert_eq!(evens, vec![(0, 0), (4, 4)]);
+    /// }
+    /// assert_eq!(lib.len(), 4);
+    /// ```
+    pub fn extract_if<'a, F>(&'a mut self, mut f: F) -> ExtractIf<'a, K, V>
+    where
+        F: FnMut(&K, &mut V) -> bool + 'a,
+    {
+        self.settle();
+        // The same bookkeeping as `forget()`, split by field so it can run while `store` is
+        // borrowed by the iterator.
+        let recency = &mut self.recency;
+        let deadlines = &mut self.deadlines;
+        let weights = &mut self.weights;
+        let poisoned = &mut self.poisoned;
+        let dirty = &mut self.dirty;
+        ExtractIf {
+            iter: Box::new(
+                self.store
+                    .extract_if(move |_h, v| match *v {
+                        Present(ref k, ref mut v) => f(k, v),
+                        Loaned(_) | AwaitingDrop(_) => false,
+                    })
+                    .map(move |(h, v)| {
+                        recency.forget(h);
+                        deadlines.forget(h);
+                        weights.forget(h);
+                        poisoned.remove(&h);
+                        dirty.remove(&h);
+                        match v {
+                            Present(k, v) => (k, v),
+                            Loaned(_) | AwaitingDrop(_) => unreachable!(),
+                        }
+                    }),
+            ),
+        }
+    }
+
+    /// Retains only the present entries whose keys are in `keys`, removing the rest. As with
+    /// [`retain()`], entries that are currently loaned are skipped and always retained.
+    ///
+    /// [`retain()`]: #method.retain
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::collections::HashSet;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..6).map(|i| (i, i)).collect();
+    /// let keep: HashSet<i32> = [1, 2, 7].iter().cloned().collect();
+    /// lib.retain_keys(&keep);
+    /// assert_eq!(lib.len(), 2);
+    /// assert!(lib.contains_key(&1) && lib.contains_key(&2));
+    /// ```
+    pub fn retain_keys<T>(&mut self, keys: &HashSet<K, T>)
+    where
+        K: Eq,
+        T: BuildHasher,
+    {
+        self.retain(|k, _v| keys.contains(k))
+    }
+
+    /// Removes every key yielded by `keys` from the store, returning the number of keys that were
+    /// present. Loaned keys are handled as by [`remove()`].
+    ///
+    /// [`remove()`]: #method.remove
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..6).map(|i| (i, i)).collect();
+    /// assert_eq!(lib.remove_keys(&[1, 2, 7]), 2);
+    /// assert_eq!(lib.len(), 4);
+    /// ```
+    pub fn remove_keys<'a, I, Q>(&mut self, keys: I) -> usize
+    where
+        I: IntoIterator<Item = &'a Q>,
+        K: Borrow<Q>,
+        Q: 'a + ?Sized + Hash + Eq,
+    {
+        keys.into_iter().filter(|k| self.remove(k)).count()
+    }
+
+    /// Returns the keys yielded by `keys` which are not in the store, in the order they were
+    /// yielded. Loaned keys count as being in the store.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let lib: LendingLibrary<i32, i32> = (0..6).map(|i| (i, i)).collect();
+    /// assert_eq!(lib.missing_from(vec![4, 5, 6, 7]), vec![6, 7]);
+    /// ```
+    pub fn missing_from<I>(&self, keys: I) -> Vec<K>
+    where
+        I: IntoIterator<Item = K>,
+    {
+        keys.into_iter().filter(|k| !self.contains_key(k)).collect()
+    }
+
+    /// Moves all present entries from `other` into this library, leaving any loaned entries behind
+    /// in `other`. Where a key exists in both libraries, the value from `other` replaces the
+    /// existing value.
+    /// # Panics
+    /// Will panic, before moving anything, if any entry would overwrite a value that is currently
+    /// loaned or awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut a: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// let mut b: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// a.insert(1, 1);
+    /// a.insert(2, 1);
+    /// b.insert(2, 2);
+    /// b.insert(3, 2);
+    /// a.append(&mut b);
+    /// assert_eq!(a.len(), 3);
+    /// assert!(b.is_empty());
+    /// assert_eq!(*a.lend(&2).unwrap(), 2);
+    /// ```
+    pub fn append(&mut self, other: &mut Self) {
+        self.settle();
+        other.settle();
+        let moving: Vec<u64> = other.store
+            .iter()
+            .filter_map(|(h, v)| match *v {
+                Present(ref k, _) => {
+                    let hk = self.hash_key(k);
+                    match self.store.get(&hk) {
+                        None if self.is_reserved(k) => {
+                            _panic(&self.name, "Cannot insert into reserved key")
+                        }
+                        Some(Loaned(_)) => _panic(&self.name, "Cannot overwrite loaned value"),
+                        Some(AwaitingDrop(_)) => {
+                            _panic(&self.name, "Cannot overwrite value awaiting drop")
+                        }
+                        Some(Present(..)) | None => Some(*h),
+                    }
+                }
+                Loaned(_) | AwaitingDrop(_) => None,
+            })
+            .collect();
+        self.reserve(moving.len());
+        for h in moving {
+            if let Some(Present(k, v)) = other.store.remove(&h) {
+                other.forget(h);
+                self.insert(k, v);
+            }
+        }
+    }
+
+    /// Moves all present entries whose keys match `pred` into a new library, which is returned.
+    /// # Errors
+    /// If any currently loaned entries match `pred`, nothing is moved and a `LoanedError` listing
+    /// their keys is returned instead.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..10).map(|i| (i, i)).collect();
+    /// let evens = lib.split_off(|k| k % 2 == 0).unwrap();
+    /// assert_eq!(lib.len(), 5);
+    /// assert_eq!(evens.len(), 5);
+    /// ```
+    pub fn split_off<F>(&mut self, mut pred: F) -> Result<Self, LoanedError<'_, K>>
+    where
+        F: FnMut(&K) -> bool,
+        S: Clone,
+    {
+        self.settle();
+        let mut moving = Vec::new();
+        let mut loaned = Vec::new();
+        for (h, v) in &self.store {
+            match *v {
+                Present(ref k, _) if pred(k) => moving.push(*h),
+                Loaned(ref k) if pred(k) => loaned.push(*h),
+                Present(..) | Loaned(_) | AwaitingDrop(_) => {}
+            }
+        }
+        if !loaned.is_empty() {
+            let store = &self.store;
+            return Err(LoanedError {
+                keys: loaned
+                    .iter()
+                    .map(|h| match store.get(h) {
+                        Some(Loaned(k)) => k,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+                name: self.name.as_deref(),
+            });
+        }
+        let mut other = LendingLibrary::with_capacity_and_hasher(moving.len(), self.hasher.clone());
+        other.name = self.name.clone();
+        other.normalizer = self.normalizer.clone();
+        for h in moving {
+            if let Some(Present(k, v)) = self.store.remove(&h) {
+                self.forget(h);
+                other.insert(k, v);
+            }
+        }
+        Ok(other)
+    }
+
+    /// Returns true if a record with key `key` exists in the store, and false otherwise.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// assert!(!lib.contains_key(&1));
+    /// lib.insert(1, 1);
+    /// assert!(lib.contains_key(&1));
+    ///
+    /// let mut names: LendingLibrary<String, i32> = LendingLibrary::new();
+    /// names.insert("a".to_string(), 1);
+    /// assert!(names.contains_key("a"));
+    /// ```
+    #[doc(alias = "has", alias = "contains")]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        match self.store.get(&h) {
+            Some(v) => match v {
+                Present(..) => !self.is_expired(h),
+                Loaned(_) => true,
+                AwaitingDrop(_) => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Swaps the values stored under keys `a` and `b`, without cloning either. Returns `Ok(false)`
+    /// and leaves the library unchanged if either key is absent.
+    /// # Errors
+    /// Returns a `LoanedError` listing the keys concerned if either value is currently loaned or
+    /// awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, &str> = LendingLibrary::new();
+    /// lib.insert(1, "a");
+    /// lib.insert(2, "b");
+    /// assert!(lib.swap_keys(&1, &2).unwrap());
+    /// assert_eq!(lib[&1], "b");
+    /// assert!(!lib.swap_keys(&1, &3).unwrap());
+    /// let _v = lib.lend(&2).unwrap();
+    /// assert_eq!(lib.swap_keys(&1, &2).unwrap_err().keys(), &[&2]);
+    /// ```
+    pub fn swap_keys<Q>(&mut self, a: &Q, b: &Q) -> Result<bool, LoanedError<'_, K>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let hashes = [self.hash_key(a), self.hash_key(b)];
+        self.settle();
+        if self.any_loaned(&hashes) {
+            return Err(self.loaned_error(&hashes));
+        }
+        let [ha, hb] = hashes;
+        if ha == hb {
+            return Ok(self.store.contains_key(&ha));
+        }
+        match (self.store.remove(&ha), self.store.remove(&hb)) {
+            (Some(Present(ka, va)), Some(Present(kb, vb))) => {
+                self.store.insert(ha, Present(ka, vb));
+                self.store.insert(hb, Present(kb, va));
+                self.swap_state(ha, hb);
+                self.weigh(ha);
+                self.weigh(hb);
+                Ok(true)
+            }
+            (sa, sb) => {
+                if let Some(sa) = sa {
+                    self.store.insert(ha, sa);
+                }
+                if let Some(sb) = sb {
+                    self.store.insert(hb, sb);
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    /// Moves the value stored under `old` to the key `new`, without cloning it, along with its
+    /// deadline, poisoning and dirty flag. Returns `Ok(false)` and leaves the library unchanged if
+    /// `old` is absent.
+    /// # Errors
+    /// Returns a `RenameError` holding `new`, and leaves the library unchanged, if the value under
+    /// either key is currently loaned or awaiting drop, or if `new` is reserved or already holds a
+    /// value other than the one being moved.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, RenameErrorKind};
+    /// let mut lib: LendingLibrary<i32, &str> = LendingLibrary::new();
+    /// lib.insert(1, "a");
+    /// lib.insert(3, "c");
+    /// assert!(lib.rename_key(&1, 2).unwrap());
+    /// assert!(!lib.contains_key(&1));
+    /// assert_eq!(lib[&2], "a");
+    /// assert!(!lib.rename_key(&1, 4).unwrap());
+    /// let err = lib.rename_key(&2, 3).unwrap_err();
+    /// assert_eq!((err.kind(), err.into_key()), (RenameErrorKind::Occupied, 3));
+    /// ```
+    pub fn rename_key<Q>(&mut self, old: &Q, new: K) -> Result<bool, RenameError<K>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let ho = self.hash_key(old);
+        let hn = self.hash_key::<K>(&new);
+        self.settle();
+        let kind = match (self.store.get(&ho), self.store.get(&hn)) {
+            (Some(Loaned(_)), _) | (_, Some(Loaned(_))) => Some(RenameErrorKind::Loaned),
+            (Some(AwaitingDrop(_)), _) | (_, Some(AwaitingDrop(_))) => {
+                Some(RenameErrorKind::AwaitingDrop)
+            }
+            (None, _) => None,
+            (Some(_), Some(_)) if ho != hn => Some(RenameErrorKind::Occupied),
+            (Some(_), None) if self.is_reserved::<K>(&new) => Some(RenameErrorKind::Reserved),
+            (Some(_), _) => None,
+        };
+        if let Some(kind) = kind {
+            return Err(RenameError {
+                key: new,
+                kind,
+                name: self.name.clone(),
+            });
+        }
+        match self.store.remove(&ho) {
+            Some(Present(_, v)) => {
+                self.store.insert(hn, Present(new, v));
+                if ho != hn {
+                    if let Some(d) = self.deadlines.get(ho) {
+                        self.deadlines.set(hn, d);
+                    }
+                    if self.poisoned.contains(&ho) {
+                        self.poisoned.insert(hn);
+                    }
+                    if self.dirty.contains(&ho) {
+                        self.dirty.insert(hn);
+                    }
+                    self.forget(ho);
+                }
+                self.touch(hn);
+                self.weigh(hn);
+                Ok(true)
+            }
+            Some(_) => unreachable!(),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, returning `None` if the key is
+    /// absent or the value is currently loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// if let Some(v) = lib.get_mut(&1) {
+    ///     *v += 5;
+    /// }
+    /// {
+    ///     let v = lib.lend(&1).unwrap();
+    ///     assert_eq!(*v, 6);
+    ///     assert_eq!(lib.get_mut(&1), None);
+    /// }
+    /// assert_eq!(lib.get_mut(&2), None);
+    /// ```
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        if let Some(Present(..)) = self.store.get(&h) {
+            self.touch(h);
+        }
+        match self.store.get_mut(&h) {
+            Some(Present(_, v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the stored key and a reference to its value, returning `None` if the key is absent
+    /// or the value is currently loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<String, i32> = LendingLibrary::new();
+    /// lib.insert("a".to_string(), 1);
+    /// assert_eq!(lib.get_key_value("a"), Some((&"a".to_string(), &1)));
+    /// {
+    ///     let _v = lib.lend("a").unwrap();
+    ///     assert_eq!(lib.get_key_value("a"), None);
+    /// }
+    /// assert_eq!(lib.get_key_value("b"), None);
+    /// ```
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        match self.store.get(&h) {
+            Some(Present(k, v)) if !self.is_expired(h) => Some((k, v)),
+            _ => None,
+        }
+    }
+
+    /// Inserts a new key/value pair into the store. If a pair with that key already exists, the
+    /// previous values will be returned as `Some(V)`, otherwise the method returns `None`.
+    /// # Panics
+    /// The method will panic if you attempt to overwrite a key/value pair that is currently loaned,
+    /// or to insert into a key reserved by [`reserve_key()`].
+    ///
+    /// [`reserve_key()`]: #method.reserve_key
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// lib.insert(2, 1);
+    /// ```
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let h = self.hash_key(&key);
+        self.insert_hashed(h, key, val)
+    }
+
+    /// Inserts a new key/value pair into the store like [`insert()`], but rather than panicking if
+    /// the existing value is loaned or awaiting drop, returns an `InsertError` holding the rejected
+    /// key and value.
+    ///
+    /// [`insert()`]: #method.insert
+    /// # Example
+    /// ```
+    /// use lending_library::{InsertErrorKind, LendingLibrary};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// assert_eq!(lib.try_insert(1, 1).unwrap(), None);
+    /// assert_eq!(lib.try_insert(1, 2).unwrap(), Some(1));
+    /// let _v = lib.lend(&1).unwrap();
+    /// let err = lib.try_insert(1, 3).unwrap_err();
+    /// assert_eq!(err.kind(), InsertErrorKind::Loaned);
+    /// assert_eq!(err.into_inner(), (1, 3));
+    /// ```
+    pub fn try_insert(&mut self, key: K, val: V) -> Result<Option<V>, InsertError<K, V>> {
+        let h = self.hash_key(&key);
+        self.settle();
+        let kind = match self.store.get(&h) {
+            None if self.is_reserved(&key) => InsertErrorKind::Reserved,
+            Some(Loaned(_)) => InsertErrorKind::Loaned,
+            Some(AwaitingDrop(_)) => InsertErrorKind::AwaitingDrop,
+            Some(Present(..)) | None => match self.max_weight {
+                Some(max) if self.weights.loaned() + self.weight_of(&key, &val) > max => {
+                    InsertErrorKind::OverWeight
+                }
+                _ => return Ok(self.insert_hashed(h, key, val)),
+            },
+        };
+        Err(InsertError {
+            key,
+            val,
+            kind,
+            name: self.name.clone(),
+        })
+    }
+
+    /// Inserts all key/value pairs from `iter`, with the same overwrite rules as [`insert()`],
+    /// returning the pairs that were replaced. Capacity for the batch is reserved up front.
+    ///
+    /// [`insert()`]: #method.insert
+    /// # Panics
+    /// Will panic if any pair would overwrite a value that is currently loaned or awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let replaced = lib.insert_many(vec![(1, 10), (2, 20)]);
+    /// assert_eq!(replaced, vec![(1, 1)]);
+    /// assert_eq!(lib.len(), 2);
+    /// ```
+    pub fn insert_many<I>(&mut self, iter: I) -> Vec<(K, V)>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let iter = iter.into_iter();
+        self.settle();
+        self.reserve(iter.size_hint().0);
+        let mut replaced = Vec::new();
+        for (key, val) in iter {
+            let h = self.hash_key(&key);
+            if !self.store.contains_key(&h) && self.is_reserved(&key) {
+                _panic(&self.name, "Cannot insert into reserved key")
+            }
+            match self.store.insert(h, Present(key, val)) {
+                Some(Present(k, v)) => replaced.push((k, v)),
+                Some(Loaned(_)) => _panic(&self.name, "Cannot overwrite loaned value"),
+                Some(AwaitingDrop(_)) => _panic(&self.name, "Cannot overwrite value awaiting drop"),
+                None => {}
+            }
+            self.poisoned.remove(&h);
+            self.touch(h);
+            self.stamp(h);
+            self.weigh(h);
+        }
+        self.evict_excess(None);
+        replaced
+    }
+
+    /// Inserts a new key/value pair into the store only if the key is vacant, handing the key and
+    /// value back if it is already present, loaned, awaiting drop, or reserved.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// assert_eq!(lib.insert_if_vacant(1, 1), Ok(()));
+    /// assert_eq!(lib.insert_if_vacant(1, 2), Err((1, 2)));
+    /// assert_eq!(*lib.lend(&1).unwrap(), 1);
+    /// ```
+    pub fn insert_if_vacant(&mut self, key: K, val: V) -> Result<(), (K, V)> {
+        let h = self.hash_key(&key);
+        self.settle();
+        if self.store.contains_key(&h) || self.is_reserved(&key) {
+            return Err((key, val));
+        }
+        self.insert_hashed(h, key, val);
+        Ok(())
+    }
+
+    /// Reserves `key` if it is vacant, returning a `Reservation` that holds it until it is handed
+    /// to [`insert_reserved()`] or dropped. Returns `None` if the key is present, loaned,
+    /// awaiting drop, or already reserved.
+    ///
+    /// While the key is reserved, attempts to insert into it by other means are rejected as if
+    /// it were occupied, so an identifier can be allocated before its value has been built. The
+    /// reservation holds the key's hash, so absent keys whose hashes collide with it are held too.
+    ///
+    /// [`insert_reserved()`]: #method.insert_reserved
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, &str> = LendingLibrary::new();
+    /// let reservation = lib.reserve_key(1).unwrap();
+    /// assert!(lib.reserve_key(1).is_none());
+    /// assert_eq!(lib.insert_if_vacant(1, "b"), Err((1, "b")));
+    /// lib.insert_reserved(reservation, "a");
+    /// assert_eq!(lib[&1], "a");
+    /// ```
+    pub fn reserve_key(&mut self, key: K) -> Option<Reservation<K, V, S>> {
+        let h = self.hash_key(&key);
+        self.settle();
+        let base = self.hasher.hash_one(&key);
+        if self.store.contains_key(&h) || !self.reserved.insert(base) {
+            return None;
+        }
+        Some(Reservation {
+            owner: self,
+            alive: Arc::downgrade(&self.alive),
+            hash: base,
+            key: Some(key),
+        })
+    }
+
+    /// Inserts `val` under the key held by `reservation`, releasing it.
+    /// # Panics
+    /// Will panic if `reservation` was made by a different library.
+    pub fn insert_reserved(&mut self, mut reservation: Reservation<K, V, S>, val: V) {
+        if !self.is_issuer(&reservation.alive) {
+            _panic(&self.name, "Inserting with a reservation from a different library")
+        }
+        let key = reservation.key.take().unwrap();
+        self.reserved.remove(&reservation.hash);
+        let h = self.hash_key(&key);
+        self.insert_hashed(h, key, val);
+    }
+
+    /// Returns true if `key` is reserved by a `Reservation`.
+    pub fn is_reserved<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.reserved.contains(&self.hasher.hash_one(key))
+    }
+
+    /// Gets the entry for `key` in the library, for in-place manipulation.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<&str, i32> = LendingLibrary::new();
+    /// *lib.entry("a").or_insert(0) += 1;
+    /// lib.entry("a").and_modify(|v| *v += 1).or_insert(0);
+    /// assert_eq!(*lib.lend(&"a").unwrap(), 2);
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        let h = self.hash_key(&key);
+        self.settle();
+        match self.store.get(&h) {
+            Some(Present(..)) | Some(Loaned(_)) => Entry::Occupied(OccupiedEntry {
+                lib: self,
+                hash: h,
+            }),
+            Some(AwaitingDrop(_)) | None => Entry::Vacant(VacantEntry {
+                lib: self,
+                hash: h,
+                key,
+            }),
+        }
+    }
+
+    /// Creates a raw entry builder, for locating entries by a hash computed ahead of time with the
+    /// library's [`hasher()`]. This avoids hashing a key more than once when probing, inserting
+    /// and lending in hot paths.
+    ///
+    /// [`hasher()`]: #method.hasher
+    /// # Example
+    /// ```
+    /// use lending_library::{entry::RawEntryMut, LendingLibrary};
+    /// use std::hash::BuildHasher;
+    /// let mut lib: LendingLibrary<&str, i32> = LendingLibrary::new();
+    /// let h = lib.hasher().hash_one("a");
+    /// match lib.raw_entry_mut().from_hash(h) {
+    ///     RawEntryMut::Occupied(_) => unreachable!(),
+    ///     RawEntryMut::Vacant(e) => {
+    ///         e.insert("a", 1);
+    ///     }
+    /// }
+    /// match lib.raw_entry_mut().from_hash(h) {
+    ///     RawEntryMut::Occupied(e) => assert_eq!(*e.lend(), 1),
+    ///     RawEntryMut::Vacant(_) => unreachable!(),
+    /// }
+    /// ```
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S> {
+        self.settle();
+        RawEntryBuilderMut { lib: self }
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, inserting the result of `f`
+    /// first if the key is absent. `f` is only called if the value is needed.
+    /// # Panics
+    /// Will panic if the value is currently loaned, or is awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, String> = LendingLibrary::new();
+    /// lib.get_or_insert_with(1, || String::from("a")).push('b');
+    /// lib.get_or_insert_with(1, || unreachable!()).push('c');
+    /// assert_eq!(*lib.lend(&1).unwrap(), "abc");
+    /// ```
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Loans the value stored under `key`, inserting the result of `f` first if the key is absent.
+    /// `f` is only called if the value is needed.
+    /// # Panics
+    /// Will panic if the value is currently loaned, or is awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, String> = LendingLibrary::new();
+    /// {
+    ///     let mut v = lib.lend_or_insert_with(1, || String::from("a"));
+    ///     v.push('b');
+    /// }
+    /// assert_eq!(*lib.lend_or_insert_with(1, || unreachable!()), "ab");
+    /// ```
+    #[track_caller]
+    pub fn lend_or_insert_with<F>(&mut self, key: K, f: F) -> Loan<K, V, S>
+    where
+        F: FnOnce() -> V,
+    {
+        match self.entry(key) {
+            Entry::Occupied(e) => e.lend(),
+            Entry::Vacant(e) => e.insert_and_lend(f()),
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, inserting `V::default()` first
+    /// if the key is absent.
+    /// # Panics
+    /// Will panic if the value is currently loaned, or is awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<&str, u64> = LendingLibrary::new();
+    /// *lib.get_or_default("hits") += 1;
+    /// *lib.get_or_default("hits") += 1;
+    /// assert_eq!(*lib.get_or_default("hits"), 2);
+    /// ```
+    pub fn get_or_default(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        self.get_or_insert_with(key, V::default)
+    }
+
+    /// Loans the value stored under `key`, inserting `V::default()` first if the key is absent.
+    /// # Panics
+    /// Will panic if the value is currently loaned, or is awaiting drop.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<&str, u64> = LendingLibrary::new();
+    /// {
+    ///     let mut v = lib.lend_or_default("hits");
+    ///     assert_eq!(*v, 0);
+    ///     *v += 1;
+    /// }
+    /// assert_eq!(*lib.lend_or_default("hits"), 1);
+    /// ```
+    #[track_caller]
+    pub fn lend_or_default(&mut self, key: K) -> Loan<K, V, S>
+    where
+        V: Default,
+    {
+        self.lend_or_insert_with(key, V::default)
+    }
+
+    /// Removes a key/value pair from the store. Returning true if the key was present in the store
+    /// and false otherwise.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// assert!(!lib.remove(&1));
+    /// lib.insert(1, 1);
+    /// assert!(lib.contains_key(&1));
+    /// assert!(lib.remove(&1));
+    /// assert!(!lib.contains_key(&1));
+    /// assert!(!lib.remove(&1));
+    /// ```
+    #[doc(alias = "delete")]
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        match self.store.remove(&h){
+            Some(v) => match v {
+                Present(..) => {
+                    self.forget(h);
+                    true
+                }
+                Loaned(k) => {
+                    self.store.insert(h,AwaitingDrop(k));
+                    self.awaiting += 1;
+                    true
+                }
                 AwaitingDrop(k) => {
                     self.store.insert(h,AwaitingDrop(k));
                     false
                 }
             }
-            None => false
+            None => false
+        }
+    }
+
+    /// Removes a key/value pair from the store, returning the value if the key was present in the
+    /// store and `None` otherwise.
+    /// # Panics
+    /// Will panic if the value is currently loaned, as it cannot be handed over until returned.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// assert_eq!(lib.take(&1), Some(1));
+    /// assert_eq!(lib.take(&1), None);
+    /// ```
+    #[doc(alias = "remove_entry")]
+    pub fn take<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        match self.store.get(&h) {
+            Some(Present(..)) => match self.store.remove(&h) {
+                Some(Present(_, v)) => {
+                    self.forget(h);
+                    Some(v)
+                }
+                _ => unreachable!(),
+            },
+            Some(Loaned(_)) => _panic(&self.name, "Cannot take loaned value"),
+            Some(AwaitingDrop(_)) | None => None,
+        }
+    }
+
+    /// Loans a value from the library, returning `Some(Loan<K, V>)` if the value is present, and `None` if it is not.
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan, or one that is
+    /// poisoned (see [`lend_checked()`](#method.lend_checked)).
+    /// # Examples
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::with_capacity(0);
+    /// lib.insert(1, 1);
+    /// {
+    ///     let mut v = lib.lend(&1).unwrap();
+    ///     *v += 5;
+    /// }
+    /// ```
+    #[doc(alias = "borrow", alias = "checkout")]
+    #[track_caller]
+    pub fn lend<Q>(&mut self, key: &Q) -> Option<Loan<K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        self.lend_hashed(h)
+    }
+
+    /// Loans a value from the library like [`lend()`], but rather than returning `None` if the
+    /// value is absent or panicking if it cannot be lent, returns a `LendError` saying why.
+    ///
+    /// [`lend()`]: #method.lend
+    /// # Example
+    /// ```
+    /// use lending_library::{LendErrorKind, LendingLibrary};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// assert_eq!(lib.try_lend(&2).unwrap_err().kind(), LendErrorKind::NotFound);
+    /// let v = lib.try_lend(&1).unwrap();
+    /// assert_eq!(lib.try_lend(&1).unwrap_err().kind(), LendErrorKind::AlreadyLoaned);
+    /// lib.remove(&1);
+    /// assert_eq!(lib.try_lend(&1).unwrap_err().kind(), LendErrorKind::AwaitingDrop);
+    /// drop(v);
+    /// assert_eq!(lib.try_lend(&1).unwrap_err().kind(), LendErrorKind::NotFound);
+    /// ```
+    #[track_caller]
+    pub fn try_lend<Q>(&mut self, key: &Q) -> Result<Loan<K, V, S>, LendError>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        let kind = match self.store.get(&h) {
+            Some(Present(..)) if self.poisoned.contains(&h) => LendErrorKind::Poisoned,
+            Some(Present(..)) => return Ok(self.lend_hashed_unchecked(h).unwrap()),
+            Some(Loaned(_)) => LendErrorKind::AlreadyLoaned,
+            Some(AwaitingDrop(_)) => LendErrorKind::AwaitingDrop,
+            None => LendErrorKind::NotFound,
+        };
+        Err(LendError {
+            kind,
+            name: self.name.clone(),
+        })
+    }
+
+    /// Returns a [`LoanWatch`] that can be used to wait, possibly on another thread, until the
+    /// current loan of the value stored under `key` is returned. If the value is not on loan, the
+    /// watch is returned already complete.
+    ///
+    /// Values lent with [`lend_send()`] count as returned once the library has taken them back,
+    /// at the start of its next mutating operation.
+    ///
+    /// [`LoanWatch`]: struct.LoanWatch.html
+    /// [`lend_send()`]: #method.lend_send
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::{thread, time::Duration};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let v = lib.lend(&1).unwrap();
+    /// let watch = lib.watch(&1);
+    /// assert!(!watch.is_returned());
+    /// let waiter = thread::spawn(move || watch.wait_timeout(Duration::from_secs(10)));
+    /// drop(v);
+    /// assert!(waiter.join().unwrap());
+    /// ```
+    pub fn watch<Q>(&mut self, key: &Q) -> LoanWatch
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        let (tx, rx) = mpsc::channel();
+        if self.issued.contains_key(&h) {
+            self.watchers.entry(h).or_default().push(tx);
+        } else {
+            let _ = tx.send(());
+        }
+        LoanWatch {
+            rx,
+            returned: Cell::new(false),
+        }
+    }
+
+    /// Loans a value from the library like [`lend()`], but as a [`ScopedLoan`] that mutably borrows
+    /// the library until it is dropped. This allows only one loan at a time, but the compiler then
+    /// guarantees that the loan is returned before the library is used again or dropped, rather
+    /// than that being checked at runtime.
+    ///
+    /// [`lend()`]: #method.lend
+    /// [`ScopedLoan`]: struct.ScopedLoan.html
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan, or one that is
+    /// poisoned.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// *lib.lend_scoped(&1).unwrap() += 1;
+    /// assert_eq!(lib.get_mut(&1), Some(&mut 2));
+    /// ```
+    ///
+    /// The library cannot be dropped while the loan is alive:
+    ///
+    /// ```compile_fail
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let v = lib.lend_scoped(&1).unwrap();
+    /// drop(lib);
+    /// drop(v);
+    /// ```
+    #[track_caller]
+    pub fn lend_scoped<Q>(&mut self, key: &Q) -> Option<ScopedLoan<'_, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lend(key).map(|loan| ScopedLoan {
+            loan,
+            lib: PhantomData,
+        })
+    }
+
+    /// Loans a value from the library like [`lend()`], but rather than panicking if the value is
+    /// poisoned, returns the loan wrapped in a `PoisonError`.
+    ///
+    /// A value is poisoned when it is returned by a loan dropped during a panic, as the borrower
+    /// may have left it in an inconsistent state. It stays poisoned until [`clear_poison()`] is
+    /// called or a new value is inserted under its key.
+    ///
+    /// [`lend()`]: #method.lend
+    /// [`clear_poison()`]: #method.clear_poison
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::panic::{self, AssertUnwindSafe};
+    /// let mut lib: LendingLibrary<i32, Vec<i32>> = LendingLibrary::new();
+    /// lib.insert(1, vec![]);
+    /// let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     let mut v = lib.lend(&1).unwrap();
+    ///     v.push(1);
+    ///     panic!("failed halfway through");
+    /// }));
+    /// assert!(lib.is_poisoned(&1));
+    /// let mut v = lib.lend_checked(&1).unwrap().unwrap_err().into_inner();
+    /// v.clear();
+    /// drop(v);
+    /// lib.clear_poison(&1);
+    /// assert!(lib.lend_checked(&1).unwrap().is_ok());
+    /// ```
+    #[track_caller]
+    pub fn lend_checked<Q>(&mut self, key: &Q) -> Option<LockResult<Loan<K, V, S>>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        let poisoned = self.poisoned.contains(&h);
+        self.lend_hashed_unchecked(h).map(|loan| match poisoned {
+            true => Err(PoisonError::new(loan)),
+            false => Ok(loan),
+        })
+    }
+
+    /// Returns true if the value stored under `key` was returned by a loan dropped during a panic,
+    /// and has not had its poisoning cleared since.
+    pub fn is_poisoned<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.poisoned.contains(&self.hash_key(key)) && self.contains_key(key)
+    }
+
+    /// Clears the poisoning of the value stored under `key`, returning true if it was poisoned.
+    pub fn clear_poison<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        self.poisoned.remove(&h)
+    }
+
+    /// Loans a value from the library as a [`SendLoan`], which can be moved to another thread,
+    /// returning `None` if the value is not present.
+    ///
+    /// Dropping a `SendLoan` sends its value back to the library over a channel, and the library
+    /// takes it back at the start of its next mutating operation. Until then, the value still
+    /// counts as loaned.
+    ///
+    /// [`SendLoan`]: struct.SendLoan.html
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan, or one that is
+    /// poisoned.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// use std::thread;
+    /// let mut lib: LendingLibrary<i32, Vec<i32>> = LendingLibrary::new();
+    /// lib.insert(1, vec![]);
+    /// let mut v = lib.lend_send(&1).unwrap();
+    /// thread::spawn(move || v.push(1)).join().unwrap();
+    /// assert_eq!(lib.get_mut(&1), Some(&mut vec![1]));
+    /// ```
+    #[track_caller]
+    pub fn lend_send<Q>(&mut self, key: &Q) -> Option<SendLoan<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        let mut loan = self.lend_hashed(h)?;
+        let inner = loan.inner.take();
+        Some(SendLoan {
+            key: h,
+            generation: loan.generation,
+            inner,
+            dirty: false,
+            tx: self.returns.get_or_insert_with(Returns::new).sender(),
+        })
+    }
+
+    /// Loans a value from the library for reading only, returning `None` if it is not present.
+    /// Any number of read loans may be held for the same value at once; while any are
+    /// outstanding the value counts as loaned, so it cannot be lent exclusively or modified.
+    /// # Panics
+    /// Will panic if the value is exclusively loaned, or has been removed while loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, String> = LendingLibrary::new();
+    /// lib.insert(1, "a".to_string());
+    /// {
+    ///     let r1 = lib.lend_shared(&1).unwrap();
+    ///     let r2 = lib.lend_shared(&1).unwrap();
+    ///     assert_eq!((r1.as_str(), r2.as_str()), ("a", "a"));
+    ///     assert_eq!(lib.get_mut(&1), None);
+    /// }
+    /// lib.get_mut(&1).unwrap().push('b');
+    /// ```
+    #[track_caller]
+    pub fn lend_shared<Q>(&mut self, key: &Q) -> Option<ReadLoan<K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        let ptr: *mut Self = self;
+        match self.store.get(&h) {
+            Some(Present(..)) if self.poisoned.contains(&h) => {
+                _panic(&self.name, "Lending poisoned value")
+            }
+            Some(Present(..)) => {
+                let val = match self.store.remove(&h) {
+                    Some(Present(k, v)) => {
+                        self.store.insert(h, Loaned(k));
+                        v
+                    }
+                    _ => unreachable!(),
+                };
+                self.touch(h);
+                self.weights.lend(h);
+                self.issue(h);
+                self.shared.insert(h, Shared::new(val));
+            }
+            Some(Loaned(_)) if self.shared.contains_key(&h) => {}
+            Some(Loaned(_)) => _panic(&self.name, "Lending already loaned value"),
+            Some(AwaitingDrop(_)) => _panic(&self.name, "Lending value awaiting drop"),
+            None => return None,
+        }
+        let shared = self.shared.get_mut(&h).unwrap();
+        shared.readers += 1;
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(ReadLoan {
+            owner: ptr,
+            alive: Arc::downgrade(&self.alive),
+            key: h,
+            generation: self.issued[&h].generation,
+            ptr: shared.ptr(),
+        })
+    }
+
+    /// Loans a value from the library like [`lend()`], recording `tag` as the identity of the
+    /// borrower. The tag is listed by [`outstanding_report()`], and named in the panic raised if
+    /// the loan outlives the store.
+    ///
+    /// [`lend()`]: #method.lend
+    /// [`outstanding_report()`]: #method.outstanding_report
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan, or one that is
+    /// poisoned.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::named("meshes");
+    /// lib.insert(1, 1);
+    /// let v = lib.lend_tagged(&1, format!("worker {}", 3)).unwrap();
+    /// assert_eq!(lib.outstanding_report()[0].tag(), Some("worker 3"));
+    /// ```
+    #[track_caller]
+    pub fn lend_tagged<Q, T>(&mut self, key: &Q, tag: T) -> Option<Loan<K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        T: Into<Cow<'static, str>>,
+    {
+        let loan = self.lend(key)?;
+        self.issued.get_mut(&loan.key).unwrap().tag = Some(tag.into());
+        Some(loan)
+    }
+
+    /// Loans several values from the library together, returning `None` without lending anything
+    /// if any key is absent or the same key is given more than once.
+    /// # Panics
+    /// Will panic, before lending anything, if any of the values still has an outstanding loan or
+    /// is poisoned (see [`lend_checked()`](#method.lend_checked)).
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, i32> = (0..4).map(|i| (i, i)).collect();
+    /// {
+    ///     let mut loans = lib.lend_many(&[1, 2]).unwrap();
+    ///     *loans[0] += *loans[1];
+    /// }
+    /// assert_eq!(*lib.lend(&1).unwrap(), 3);
+    /// assert!(lib.lend_many(&[1, 1]).is_none());
+    /// assert!(lib.lend_many(&[1, 5]).is_none());
+    /// ```
+    #[track_caller]
+    pub fn lend_many<'a, I, Q>(&mut self, keys: I) -> Option<Vec<Loan<K, V, S>>>
+    where
+        I: IntoIterator<Item = &'a Q>,
+        K: Borrow<Q>,
+        Q: 'a + ?Sized + Hash + Eq,
+    {
+        self.settle();
+        let mut hashes = Vec::new();
+        let mut seen = HashSet::new();
+        for key in keys {
+            let h = self.hash_key(key);
+            if !seen.insert(h) {
+                return None;
+            }
+            match self.store.get(&h) {
+                Some(Present(..)) if self.is_expired(h) => return None,
+                Some(Present(..)) if self.poisoned.contains(&h) => {
+                    _panic(&self.name, "Lending poisoned value")
+                }
+                Some(Present(..)) => hashes.push(h),
+                Some(Loaned(_)) => _panic(&self.name, "Lending already loaned value"),
+                Some(AwaitingDrop(_)) => _panic(&self.name, "Lending value awaiting drop"),
+                None => return None,
+            }
+        }
+        let mut loans = Vec::with_capacity(hashes.len());
+        for h in hashes {
+            loans.push(self.lend_hashed(h)?);
         }
+        Some(loans)
     }
 
-    /// Loans a value from the library, returning `Some(Loan<K, V>)` if the value is present, and `None` if it is not.
+    /// Loans a value from the library only if `f` returns true when applied to it, returning `None`
+    /// if the key is absent or the condition does not hold.
     /// # Panics
     /// Will panic if you try and loan a value that still has an outstanding loan.
-    /// # Examples
+    /// # Example
     /// ```
     /// use lending_library::LendingLibrary;
-    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::with_capacity(0);
-    /// lib.insert(1, 1);
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 5);
+    /// assert!(lib.lend_if(&1, |v| *v > 10).is_none());
+    /// assert!(lib.lend_if(&1, |v| *v < 10).is_some());
+    /// ```
+    #[track_caller]
+    pub fn lend_if<Q, F>(&mut self, key: &Q, f: F) -> Option<Loan<K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&V) -> bool,
+    {
+        let h = self.hash_key(key);
+        self.settle();
+        if let Some(Present(_, v)) = self.store.get(&h) {
+            if !f(v) {
+                return None;
+            }
+        }
+        self.lend_hashed(h)
+    }
+
+    /// Loans a value from the library only if it is equal to `expected`, returning `None` if the
+    /// key is absent or the value differs.
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan.
+    /// # Example
+    /// ```
+    /// use lending_library::LendingLibrary;
+    /// let mut lib: LendingLibrary<i32, &str> = LendingLibrary::new();
+    /// lib.insert(1, "pending");
     /// {
-    ///     let mut v = lib.lend(&1).unwrap();
-    ///     *v += 5;
+    ///     let mut v = lib.lend_if_eq(&1, &"pending").unwrap();
+    ///     *v = "done";
     /// }
+    /// assert!(lib.lend_if_eq(&1, &"pending").is_none());
     /// ```
-    pub fn lend(&mut self, key: &K) -> Option<Loan<K, V>> {
-        let h = _hash(key);
+    #[track_caller]
+    pub fn lend_if_eq<Q>(&mut self, key: &Q, expected: &V) -> Option<Loan<K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: PartialEq,
+    {
+        self.lend_if(key, |v| v == expected)
+    }
+
+    fn insert_hashed(&mut self, h: u64, key: K, val: V) -> Option<V> {
+        self.settle();
+        if !self.store.contains_key(&h) && self.is_reserved(&key) {
+            _panic(&self.name, "Cannot insert into reserved key")
+        }
+        let old = match self.store.insert(h, Present(key, val)) {
+            Some(v) => match v {
+                Present(_, v) => Some(v),
+                Loaned(_) => _panic(&self.name, "Cannot overwrite loaned value"),
+                AwaitingDrop(_) => _panic(&self.name, "Cannot overwrite value awaiting drop"),
+            },
+            None => None,
+        };
+        self.replaced(h);
+        old
+    }
+}
+
+impl<K, V, S> LendingLibrary<K, V, S>
+where
+    K: Hash,
+{
+    /// Updates the bookkeeping for `h` after a new value has been stored under it.
+    fn replaced(&mut self, h: u64) {
+        self.poisoned.remove(&h);
+        self.touch(h);
+        self.stamp(h);
+        self.weigh(h);
+        self.evict_excess(Some(h));
+    }
+
+    #[track_caller]
+    fn lend_hashed(&mut self, h: u64) -> Option<Loan<K, V, S>> {
+        if let Some(Present(..)) = self.store.get(&h) {
+            if self.poisoned.contains(&h) {
+                _panic(&self.name, "Lending poisoned value")
+            }
+        }
+        self.lend_hashed_unchecked(h)
+    }
+
+    #[track_caller]
+    fn lend_hashed_unchecked(&mut self, h: u64) -> Option<Loan<K, V, S>> {
         let ptr: *mut Self = self;
-        match self.store.remove(&h){
+        match self.store.get(&h) {
+            Some(Loaned(_)) => _panic(&self.name, "Lending already loaned value"),
+            Some(AwaitingDrop(_)) => _panic(&self.name, "Lending value awaiting drop"),
+            _ => {}
+        }
+        match self.store.remove(&h) {
             Some(v) => match v {
                 Present(k, v) => {
                     self.outstanding.fetch_add(1, Ordering::Relaxed);
                     self.store.insert(h, Loaned(k));
+                    self.touch(h);
+                    self.weights.lend(h);
                     Some(Loan {
                         owner: ptr,
+                        alive: Arc::downgrade(&self.alive),
                         key: h,
+                        generation: self.issue(h),
                         inner: Some(v),
+                        dirty: false,
                     })
                 }
-                Loaned(_) => panic!("Lending already loaned value"),
-                AwaitingDrop(_) => panic!("Lending value awaiting drop"),
-            }
+                Loaned(_) | AwaitingDrop(_) => unreachable!(),
+            },
             None => None,
         }
     }
 
-    fn checkin(&mut self, key: u64, val: V) {
+    fn loaned_keys(&self) -> Vec<&K> {
+        self.store
+            .values()
+            .filter_map(|v| match *v {
+                Loaned(ref k) | AwaitingDrop(ref k) => Some(k),
+                Present(..) => None,
+            })
+            .collect()
+    }
+
+    fn any_loaned(&self, hashes: &[u64]) -> bool {
+        hashes
+            .iter()
+            .any(|h| matches!(self.store.get(h), Some(Loaned(_)) | Some(AwaitingDrop(_))))
+    }
+
+    fn loaned_error(&self, hashes: &[u64]) -> LoanedError<'_, K> {
+        let mut seen = HashSet::new();
+        LoanedError {
+            keys: hashes
+                .iter()
+                .filter(|h| seen.insert(**h))
+                .filter_map(|h| match self.store.get(h) {
+                    Some(Loaned(k)) | Some(AwaitingDrop(k)) => Some(k),
+                    _ => None,
+                })
+                .collect(),
+            name: self.name.as_deref(),
+        }
+    }
+
+    fn is_limited(&self) -> bool {
+        self.max_entries.is_some() || self.max_weight.is_some()
+    }
+
+    fn limits_changed(&mut self, was_limited: bool) {
+        if !was_limited {
+            for h in self.store.keys() {
+                self.recency.touch(*h);
+            }
+        }
+        if self.is_limited() {
+            self.evict_excess(None);
+        } else {
+            self.recency.clear();
+        }
+    }
+
+    fn weight_of(&self, key: &K, val: &V) -> usize {
+        match self.weigher {
+            Some(ref f) => f(key, val),
+            None => 1,
+        }
+    }
+
+    fn weigh(&mut self, h: u64) {
+        if self.max_weight.is_some() {
+            if let Some(Present(k, v)) = self.store.get(&h) {
+                let w = self.weight_of(k, v);
+                self.weights.set(h, w);
+            }
+        }
+    }
+
+    fn reweigh(&mut self) {
+        if self.max_weight.is_none() {
+            self.weights.clear();
+            return;
+        }
+        // Loaned values cannot be weighed until they are returned, so keep their last weight.
+        let store = &self.store;
+        self.weights.retain(|h| matches!(store.get(&h), Some(Loaned(_))));
+        let hashes: Vec<u64> = self.store.keys().cloned().collect();
+        for h in hashes {
+            self.weigh(h);
+        }
+    }
+
+    fn is_over_limit(&self) -> bool {
+        self.max_entries.is_some_and(|m| self.store.len() > m)
+            || self.max_weight.is_some_and(|m| self.weights.total() > m)
+    }
+
+    fn touch(&mut self, h: u64) {
+        if self.is_limited() {
+            self.recency.touch(h);
+            if self.recency.len() > 2 * self.store.len() + 16 {
+                let store = &self.store;
+                self.recency.retain(|h| store.contains_key(&h));
+            }
+        }
+    }
+
+    fn forget(&mut self, h: u64) {
+        if self.is_limited() {
+            self.recency.forget(h);
+        }
+        self.deadlines.forget(h);
+        self.weights.forget(h);
+        self.poisoned.remove(&h);
+        self.dirty.remove(&h);
+    }
+
+    fn swap_state(&mut self, a: u64, b: u64) {
+        let (da, db) = (self.deadlines.get(a), self.deadlines.get(b));
+        self.deadlines.forget(a);
+        self.deadlines.forget(b);
+        if let Some(d) = db {
+            self.deadlines.set(a, d);
+        }
+        if let Some(d) = da {
+            self.deadlines.set(b, d);
+        }
+        for set in [&mut self.poisoned, &mut self.dirty] {
+            let (sa, sb) = (set.remove(&a), set.remove(&b));
+            if sb {
+                set.insert(a);
+            }
+            if sa {
+                set.insert(b);
+            }
+        }
+    }
+
+    fn stamp(&mut self, h: u64) {
+        if let Some(ttl) = self.ttl {
+            self.deadlines.set(h, Instant::now() + ttl);
+        }
+    }
+
+    fn is_expired(&self, h: u64) -> bool {
+        self.ttl.is_some() && self.deadlines.is_expired(h, Instant::now())
+    }
+
+    fn settle(&mut self) {
+        self.collect_returns();
+        self.expire();
+    }
+
+    fn collect_returns(&mut self) {
+        let returned = match self.returns {
+            Some(ref mut r) => r.drain(),
+            None => return,
+        };
+        for r in returned {
+            self.take_back(r);
+        }
+    }
+
+    fn take_back(&mut self, r: Returned<V>) {
+        if r.dirty {
+            self.mark_dirty(r.key);
+        }
+        match r.poisoned {
+            true => self.checkin_poisoned(r.key, r.generation, r.val),
+            false => self.checkin(r.key, r.generation, r.val),
+        }
+    }
+
+    fn expire(&mut self) -> usize {
+        if self.ttl.is_none() {
+            return 0;
+        }
+        let now = Instant::now();
+        let mut purged = 0;
+        while let Some(h) = self.deadlines.pop_expired(now) {
+            match self.store.get(&h) {
+                Some(Present(..)) => {
+                    self.store.remove(&h);
+                    self.forget(h);
+                    purged += 1;
+                }
+                Some(Loaned(_)) => {}
+                Some(AwaitingDrop(_)) | None => self.deadlines.forget(h),
+            }
+        }
+        purged
+    }
+
+    fn evict_excess(&mut self, keep: Option<u64>) {
+        if self.weights.len() > self.store.len() {
+            let store = &self.store;
+            self.weights.retain(|h| store.contains_key(&h));
+        }
+        let mut cursor = None;
+        while self.is_over_limit() {
+            let (t, h) = match self.recency.next_after(cursor) {
+                Some(e) => e,
+                None => break,
+            };
+            cursor = Some(t);
+            match self.store.get(&h) {
+                Some(Present(..)) if Some(h) != keep => {}
+                Some(Present(..)) | Some(Loaned(_)) => continue,
+                Some(AwaitingDrop(_)) | None => {
+                    self.recency.forget(h);
+                    continue;
+                }
+            }
+            self.forget(h);
+            if let Some(Present(k, v)) = self.store.remove(&h) {
+                if let Some(ref f) = self.evictor {
+                    f(k, v);
+                }
+            }
+        }
+    }
+
+    fn share(&mut self, key: u64, val: V) -> NonNull<V> {
+        let mut shared = Shared::new(val);
+        shared.readers = 1;
+        let ptr = shared.ptr();
+        self.shared.insert(key, shared);
+        ptr
+    }
+
+    fn add_reader(&mut self, key: u64, alive: &Weak<()>) {
+        if !self.is_issuer(alive) {
+            _panic(&self.name, "Cloning read loan from a library that did not issue it")
+        }
+        match self.shared.get_mut(&key) {
+            Some(s) => s.readers += 1,
+            None => _panic(&self.name, "Cloning read loan not registered with store"),
+        }
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn unshare(&mut self, key: u64) -> Option<V> {
+        match self.shared.get(&key) {
+            Some(s) if s.readers == 1 => Some(self.shared.remove(&key).unwrap().into_inner()),
+            _ => None,
+        }
+    }
+
+    #[track_caller]
+    fn issue(&mut self, key: u64) -> u64 {
+        self.generation += 1;
+        self.issued.insert(key, Issue {
+            generation: self.generation,
+            at: Instant::now(),
+            dirty: false,
+            tag: None,
+            #[cfg(feature = "debug")]
+            location: Location::caller(),
+        });
+        self.generation
+    }
+
+    fn mark_dirty(&mut self, key: u64) {
+        self.dirty.insert(key);
+        if let Some(i) = self.issued.get_mut(&key) {
+            i.dirty = true;
+        }
+    }
+
+    fn discharge(&mut self, key: u64) {
+        self.issued.remove(&key);
+        for tx in self.watchers.remove(&key).unwrap_or_default() {
+            let _ = tx.send(());
+        }
+    }
+
+    fn is_issuer(&self, alive: &Weak<()>) -> bool {
+        alive.as_ptr() == Arc::as_ptr(&self.alive)
+    }
+
+    fn check_issuer(&self, alive: &Weak<()>) {
+        if !self.is_issuer(alive) {
+            _panic(&self.name, "Returning loan to a library that did not issue it")
+        }
+    }
+
+    fn generation_of(&self, key: u64) -> Option<u64> {
+        self.issued.get(&key).map(|i| i.generation)
+    }
+
+    #[cfg(feature = "debug")]
+    fn lent_at(&self) -> String {
+        let mut at: Vec<String> = self.issued
+            .values()
+            .map(|i| match i.tag {
+                Some(ref t) => format!("{} ({})", i.location, t),
+                None => i.location.to_string(),
+            })
+            .collect();
+        at.sort();
+        format!(" Lent at: {}", at.join(", "))
+    }
+
+    #[cfg(not(feature = "debug"))]
+    fn lent_at(&self) -> String {
+        let mut by: Vec<&str> = self.issued.values().filter_map(|i| i.tag.as_deref()).collect();
+        if by.is_empty() {
+            return String::new();
+        }
+        by.sort();
+        format!(" Held by: {}", by.join(", "))
+    }
+
+    fn check_returning(&self, key: u64, generation: u64) {
+        match self.store.get(&key) {
+            Some(Present(..)) => _panic(&self.name, "Returning replaced item"),
+            None => _panic(&self.name, "Returning item not from store"),
+            Some(_) if self.generation_of(key) != Some(generation) => {
+                _panic(&self.name, "Returning loan from a stale generation")
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn checkin_poisoned(&mut self, key: u64, generation: u64, val: V) {
+        if self.generation_of(key) != Some(generation) {
+            return;
+        }
+        match self.store.get(&key) {
+            Some(Loaned(_)) => {
+                self.poisoned.insert(key);
+                self.checkin(key, generation, val);
+            }
+            Some(AwaitingDrop(_)) => self.checkin(key, generation, val),
+            _ => {}
+        }
+    }
+
+    fn release_shared(&mut self, key: u64, generation: u64) {
+        self.check_returning(key, generation);
+        let last = match self.shared.get_mut(&key) {
+            Some(s) => {
+                s.readers -= 1;
+                s.readers == 0
+            }
+            None => _panic(&self.name, "Returning item not from store"),
+        };
+        if last {
+            let val = self.shared.remove(&key).unwrap().into_inner();
+            self.checkin(key, generation, val);
+        } else {
+            self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn loaned_key(&self, key: u64) -> &K {
+        match self.store.get(&key) {
+            Some(Loaned(k)) | Some(AwaitingDrop(k)) => k,
+            _ => _panic(&self.name, "Loan not from store"),
+        }
+    }
+
+    fn retire(&mut self, key: u64, generation: u64) {
+        self.check_returning(key, generation);
+        self.discharge(key);
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        match self.store.remove(&key) {
+            Some(Loaned(_)) => {
+                self.weights.release(key);
+                self.forget(key);
+            }
+            Some(AwaitingDrop(_)) => {
+                self.awaiting -= 1;
+                self.weights.release(key);
+                self.forget(key);
+            }
+            Some(Present(..)) => _panic(&self.name, "Returning replaced item"),
+            None => _panic(&self.name, "Returning item not from store"),
+        }
+    }
+
+    fn checkin(&mut self, key: u64, generation: u64, val: V) {
+        self.check_returning(key, generation);
+        let dirty = self.issued.get(&key).is_some_and(|i| i.dirty);
+        self.discharge(key);
         match self.store.remove(&key) {
             Some(v) => {
                 self.outstanding.fetch_sub(1, Ordering::Relaxed);
                 match v {
-                    Present(..) => panic!("Returning replaced item"),
+                    Present(..) => _panic(&self.name, "Returning replaced item"),
                     Loaned(k) => {
+                        self.weights.release(key);
+                        if self.is_expired(key) {
+                            self.forget(key);
+                            return;
+                        }
+                        let val = match self.normalizer {
+                            Some(ref f) if !self.poisoned.contains(&key) => f(val),
+                            _ => val,
+                        };
+                        let val = match self.validator {
+                            Some(ref f) if dirty => match f(&k, val) {
+                                Some(v) => v,
+                                None => {
+                                    self.forget(key);
+                                    return;
+                                }
+                            },
+                            _ => val,
+                        };
                         self.store.insert(key, Present(k, val));
+                        self.touch(key);
+                        self.weigh(key);
+                        self.evict_excess(Some(key));
+                    }
+                    AwaitingDrop(_) => {
+                        self.awaiting -= 1;
+                        self.weights.release(key);
+                        self.forget(key);
                     }
-                    AwaitingDrop(_) => {},
                 }
             }
-            None => panic!("Returning item not from store"),
+            None => _panic(&self.name, "Returning item not from store"),
         }
     }
 }
 
-impl<K, V> Drop for LendingLibrary<K, V>
+impl<K, V, S> Drop for LendingLibrary<K, V, S>
 where
     K: Hash,
 {
     fn drop(&mut self) {
         if !thread::panicking() {
+            self.collect_returns();
             let count = self.outstanding.load(Ordering::SeqCst);
-            if count != 0 {
-                panic!("{} value loans outlived store.", count)
+            let escrowed = self.escrowed.load(Ordering::SeqCst);
+            if escrowed != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store. ({} held in raw escrow){}",
+                                            count, escrowed, self.lent_at()))
+            } else if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.{}", count, self.lent_at()))
             }
         }
     }
 }
 
-impl<K, V> Default for LendingLibrary<K, V>
+impl<K, V, S> Default for LendingLibrary<K, V, S>
 where
-    K: Hash,
+    K: Hash + Eq,
+    S: BuildHasher + Default,
 {
     fn default() -> Self {
-        LendingLibrary::new()
+        LendingLibrary::with_hasher(S::default())
+    }
+}
+impl<K, V, S> Extend<(K, V)> for LendingLibrary<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Inserts all key/value pairs from `iter`, with the same overwrite rules as `insert()`.
+    /// # Panics
+    /// Will panic if any pair would overwrite a value that is currently loaned or awaiting drop.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for LendingLibrary<K, V, S>
+where
+    K: Hash + Eq + Copy,
+    V: Copy,
+    S: BuildHasher,
+{
+    /// Inserts copies of all key/value pairs from `iter`, with the same overwrite rules as
+    /// `insert()`.
+    /// # Panics
+    /// Will panic if any pair would overwrite a value that is currently loaned or awaiting drop.
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(&k, &v)| (k, v)))
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for LendingLibrary<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut lib = LendingLibrary::default();
+        lib.extend(iter);
+        lib
+    }
+}
+
+impl<K, V, S> From<HashMap<K, V, S>> for LendingLibrary<K, V>
+where
+    K: Hash + Eq,
+{
+    fn from(map: HashMap<K, V, S>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K, V> From<Vec<(K, V)>> for LendingLibrary<K, V>
+where
+    K: Hash + Eq,
+{
+    fn from(vec: Vec<(K, V)>) -> Self {
+        vec.into_iter().collect()
+    }
+}
+
+/// Converts a library into a `HashMap`, leaving the library empty.
+///
+/// # Errors
+/// If the library has any outstanding loans it is left untouched, and a `LoanedError` listing the
+/// keys of the loaned values is returned instead.
+/// # Example
+/// ```
+/// use lending_library::LendingLibrary;
+/// use std::{collections::HashMap, convert::TryFrom};
+/// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+/// lib.insert(1, 1);
+/// {
+///     let _v = lib.lend(&1).unwrap();
+/// }
+/// let map = HashMap::try_from(&mut lib).unwrap();
+/// assert_eq!(map[&1], 1);
+/// assert!(lib.is_empty());
+/// ```
+impl<'a, K, V, S> TryFrom<&'a mut LendingLibrary<K, V, S>> for HashMap<K, V>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Error = LoanedError<'a, K>;
+
+    fn try_from(lib: &'a mut LendingLibrary<K, V, S>) -> Result<Self, Self::Error> {
+        lib.settle();
+        if lib.outstanding.load(Ordering::SeqCst) != 0 {
+            return Err(LoanedError {
+                keys: lib.loaned_keys(),
+                name: lib.name.as_deref(),
+            });
+        }
+        Ok(lib.drain_settled().collect())
+    }
+}
+
+impl<K, V, S, Q> Index<&Q> for LendingLibrary<K, V, S>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: ?Sized + Hash + Eq,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// Returns a reference to the value stored under `key`.
+    /// # Panics
+    /// Will panic if the key is not present in the library, or if its value is currently loaned.
+    fn index(&self, key: &Q) -> &V {
+        let h = self.hash_key(key);
+        match self.store.get(&h) {
+            Some(Present(_, v)) if !self.is_expired(h) => v,
+            Some(Present(..)) => _panic(&self.name, "Key not present in library"),
+            Some(Loaned(_)) => _panic(&self.name, "Cannot index loaned value"),
+            Some(AwaitingDrop(_)) | None => _panic(&self.name, "Key not present in library"),
+        }
+    }
+}
+
+/// Clones every present entry into a new library. Values sent back by dropped `SendLoan`s that the
+/// library has yet to take back are cloned as if they had been.
+///
+/// # Panics
+/// Will panic, listing their keys, if any values are currently loaned, as they are not available to
+/// be cloned. Entries that are awaiting drop are not part of the library's contents, and are not
+/// cloned.
+impl<K, V, S> Clone for LendingLibrary<K, V, S>
+where
+    K: Hash + Eq + Clone + Debug,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    fn clone(&self) -> Self {
+        let mut pending: HashMap<u64, Returned<V>> = match self.returns {
+            Some(ref r) => r.peek()
+                .into_iter()
+                .filter(|r| self.generation_of(r.key) == Some(r.generation))
+                .map(|r| (r.key, r))
+                .collect(),
+            None => HashMap::new(),
+        };
+        let loaned: Vec<&K> = self.store
+            .iter()
+            .filter_map(|(h, v)| match *v {
+                Loaned(ref k) if !pending.contains_key(h) => Some(k),
+                Present(..) | Loaned(_) | AwaitingDrop(_) => None,
+            })
+            .collect();
+        if !loaned.is_empty() {
+            _panic(
+                &self.name,
+                &format!("Cannot clone a store with loaned items: {:?}", loaned),
+            )
+        }
+        let mut lib = LendingLibrary::with_capacity_and_hasher(self.len(), self.hasher.clone());
+        lib.name = self.name.clone();
+        lib.normalizer = self.normalizer.clone();
+        lib.max_entries = self.max_entries;
+        lib.recency = self.recency.clone();
+        lib.evictor = self.evictor.clone();
+        lib.validator = self.validator.clone();
+        lib.max_weight = self.max_weight;
+        lib.weigher = self.weigher.clone();
+        lib.weights = self.weights.clone();
+        lib.ttl = self.ttl;
+        lib.deadlines = self.deadlines.clone();
+        lib.poisoned = self.poisoned.clone();
+        lib.dirty = self.dirty.clone();
+        lib.probes = AtomicUsize::new(self.probes.load(Ordering::Relaxed));
+        for (h, v) in &self.store {
+            match *v {
+                Present(ref k, ref v) => {
+                    lib.store.insert(*h, Present(k.clone(), v.clone()));
+                }
+                Loaned(ref k) => match pending.remove(h) {
+                    Some(r) => {
+                        lib.store.insert(*h, Loaned(k.clone()));
+                        lib.issued.insert(*h, self.issued[h].clone());
+                        lib.outstanding.fetch_add(1, Ordering::Relaxed);
+                        lib.take_back(r);
+                    }
+                    None => unreachable!(),
+                },
+                AwaitingDrop(_) => {}
+            }
+        }
+        lib
+    }
+}
+
+struct Marker(&'static str);
+
+impl Debug for Marker {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(self.0)
+    }
+}
+
+/// Formats the library as a map, showing `<loaned>` or `<awaiting drop>` in place of any values
+/// that are currently loaned.
+impl<K, V, S> Debug for LendingLibrary<K, V, S>
+where
+    K: Hash + Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let mut map = f.debug_map();
+        for (h, v) in &self.store {
+            match *v {
+                Present(..) if self.is_expired(*h) => continue,
+                Present(ref k, ref v) => map.entry(k, v),
+                Loaned(ref k) => map.entry(k, &Marker("<loaned>")),
+                AwaitingDrop(ref k) => map.entry(k, &Marker("<awaiting drop>")),
+            };
+        }
+        map.finish()
     }
-}
\ No newline at end of file
+}
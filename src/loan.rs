@@ -7,23 +7,828 @@ This file is part of the lending-library open-source project: github.com/harkone
 Its licensing is governed by the LICENSE file at the root of the project.
 */
 
-use super::LendingLibrary;
-use std::{fmt::{Debug, Error as FmtError, Formatter},
-          hash::Hash,
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use super::{_panic, LendingLibrary};
+#[cfg(feature = "debug")]
+use std::panic::Location;
+use std::{borrow::{Borrow, BorrowMut, Cow},
+          cell::Cell,
+          cmp::Ordering as CmpOrdering,
+          collections::hash_map::RandomState,
+          fmt::{Debug, Display, Error as FmtError, Formatter},
+          hash::{Hash, Hasher},
+          marker::PhantomData,
+          mem,
           ops::{Deref, DerefMut},
-          thread};
+          ptr::NonNull,
+          rc::Rc,
+          sync::{atomic::Ordering,
+                 Arc,
+                 mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+                 Mutex,
+                 PoisonError,
+                 Weak},
+          thread,
+          time::{Duration, Instant}};
 
 /// A smart pointer representing the loan of a key/value pair from a `LendingLibrary` instance.
-pub struct Loan<K, V>
+pub struct Loan<K, V, S = RandomState>
 where
     K: Hash,
 {
-    pub(super) owner: *mut LendingLibrary<K, V>,
+    pub(super) owner: *mut LendingLibrary<K, V, S>,
+    pub(super) alive: Weak<()>,
     pub(super) key: u64,
+    pub(super) generation: u64,
     pub(super) inner: Option<V>,
+    pub(super) dirty: bool,
 }
 
-impl<K, V> Debug for Loan<K, V>
+/// A loan that mutably borrows the `LendingLibrary` it came from, created by
+/// [`LendingLibrary::lend_scoped()`]. The compiler ensures the library is neither dropped nor used
+/// while this is alive, so it can never outlive the store.
+///
+/// [`LendingLibrary::lend_scoped()`]: struct.LendingLibrary.html#method.lend_scoped
+pub struct ScopedLoan<'a, K, V, S = RandomState>
+where
+    K: Hash + 'a,
+    V: 'a,
+    S: 'a,
+{
+    pub(super) loan: Loan<K, V, S>,
+    pub(super) lib: PhantomData<&'a mut LendingLibrary<K, V, S>>,
+}
+
+/// A handle for waiting until the loan of a value has been returned to its `LendingLibrary`,
+/// created by [`LendingLibrary::watch()`].
+///
+/// [`LendingLibrary::watch()`]: struct.LendingLibrary.html#method.watch
+pub struct LoanWatch {
+    pub(super) rx: Receiver<()>,
+    pub(super) returned: Cell<bool>,
+}
+
+/// A smart pointer representing a shared, read-only loan of a value from a `LendingLibrary`
+/// instance. Any number of these may be held for the same value at once, including clones of one
+/// another, and the value is returned to the store when the last of them is dropped.
+pub struct ReadLoan<K, V, S = RandomState>
+where
+    K: Hash,
+{
+    pub(super) owner: *mut LendingLibrary<K, V, S>,
+    pub(super) alive: Weak<()>,
+    pub(super) key: u64,
+    pub(super) generation: u64,
+    pub(super) ptr: NonNull<V>,
+}
+
+/// A reservation of a vacant key in a `LendingLibrary`, created by
+/// [`LendingLibrary::reserve_key()`]. While it is held, the key cannot be inserted into except by
+/// handing the reservation to [`LendingLibrary::insert_reserved()`]; dropping it releases the key.
+///
+/// This is a key token in the sense of [`set::KeyToken`], but is named `Reservation` because the
+/// two are unrelated: a `KeyToken` is a loan of a key that is already in a `LendingSet`, whereas a
+/// `Reservation` holds a key that is not yet in the library.
+///
+/// [`LendingLibrary::reserve_key()`]: struct.LendingLibrary.html#method.reserve_key
+/// [`LendingLibrary::insert_reserved()`]: struct.LendingLibrary.html#method.insert_reserved
+/// [`set::KeyToken`]: set/struct.KeyToken.html
+#[must_use]
+pub struct Reservation<K, V, S = RandomState>
+where
+    K: Hash,
+{
+    pub(super) owner: *mut LendingLibrary<K, V, S>,
+    pub(super) alive: Weak<()>,
+    pub(super) hash: u64,
+    pub(super) key: Option<K>,
+}
+
+/// A smart pointer representing the loan of a value from a `LendingLibrary` instance that can be
+/// moved to another thread. Rather than returning the value to the store directly, dropping it
+/// sends the value back over a channel, and the store takes it back on its next operation.
+pub struct SendLoan<V> {
+    pub(super) key: u64,
+    pub(super) generation: u64,
+    pub(super) inner: Option<V>,
+    pub(super) dirty: bool,
+    pub(super) tx: Sender<Returned<V>>,
+}
+
+/// A function that checks a value returned from a dirty loan, handing back the value to store, or
+/// `None` to remove the entry.
+pub(super) type Validator<K, V> = Arc<dyn Fn(&K, V) -> Option<V> + Send + Sync>;
+
+/// A value sent back to the library by a `SendLoan`.
+#[derive(Clone)]
+pub(super) struct Returned<V> {
+    pub(super) key: u64,
+    pub(super) generation: u64,
+    pub(super) val: V,
+    /// Whether the value was mutably accessed during the loan.
+    pub(super) dirty: bool,
+    /// Whether the loan was dropped during a panic.
+    pub(super) poisoned: bool,
+}
+
+/// The channel that `SendLoan`s return their values over.
+pub(super) struct Returns<V> {
+    tx: Sender<Returned<V>>,
+    rx: Mutex<Receiver<Returned<V>>>,
+}
+
+impl<V> Returns<V> {
+    pub(super) fn new() -> Returns<V> {
+        let (tx, rx) = mpsc::channel();
+        Returns {
+            tx,
+            rx: Mutex::new(rx),
+        }
+    }
+
+    /// Returns copies of the values sent back but not yet drained, leaving them in the channel.
+    pub(super) fn peek(&self) -> Vec<Returned<V>>
+    where
+        V: Clone,
+    {
+        let rx = self.rx.lock().unwrap_or_else(PoisonError::into_inner);
+        let pending: Vec<Returned<V>> = rx.try_iter().collect();
+        for r in &pending {
+            let _ = self.tx.send(r.clone());
+        }
+        pending
+    }
+
+    pub(super) fn sender(&self) -> Sender<Returned<V>> {
+        self.tx.clone()
+    }
+
+    /// Takes every value that has been returned so far.
+    pub(super) fn drain(&mut self) -> Vec<Returned<V>> {
+        self.rx
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .try_iter()
+            .collect()
+    }
+}
+
+/// A value lent out to readers, boxed so that it stays in place while the store changes around it.
+pub(super) struct Shared<V> {
+    ptr: NonNull<V>,
+    pub(super) readers: usize,
+}
+
+impl<V> Shared<V> {
+    pub(super) fn new(val: V) -> Shared<V> {
+        Shared {
+            ptr: NonNull::from(Box::leak(Box::new(val))),
+            readers: 0,
+        }
+    }
+
+    pub(super) fn ptr(&self) -> NonNull<V> {
+        self.ptr
+    }
+
+    pub(super) fn into_inner(self) -> V {
+        let val = unsafe { Box::from_raw(self.ptr.as_ptr()) };
+        mem::forget(self);
+        *val
+    }
+}
+
+impl<V> Drop for Shared<V> {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Box::from_raw(self.ptr.as_ptr()));
+        }
+    }
+}
+
+unsafe impl<V: Send> Send for Shared<V> {}
+unsafe impl<V: Sync> Sync for Shared<V> {}
+
+/// A loan projected onto part of its value by [`Loan::map()`] or [`Loan::split()`]. The whole
+/// value is still returned to the store when this, and any other part split from the same loan,
+/// has been dropped.
+///
+/// [`Loan::map()`]: struct.Loan.html#method.map
+/// [`Loan::split()`]: struct.Loan.html#method.split
+pub struct MappedLoan<K, V, T, S = RandomState>
+where
+    K: Hash,
+    T: ?Sized,
+{
+    /// The underlying loan, and whether the value has been mutably accessed through any part of
+    /// it, which is passed on to the loan when the last part is dropped.
+    loan: Rc<(Loan<K, V, S>, Cell<bool>)>,
+    ptr: *mut T,
+}
+
+/// A loan that is yet to be returned to a `LendingLibrary`, as listed by
+/// [`LendingLibrary::outstanding_report()`].
+///
+/// [`LendingLibrary::outstanding_report()`]: struct.LendingLibrary.html#method.outstanding_report
+#[derive(Debug)]
+pub struct OutstandingLoan<'a, K: 'a> {
+    pub(super) key: &'a K,
+    pub(super) tag: Option<&'a str>,
+    pub(super) generation: u64,
+    pub(super) issued: Instant,
+    #[cfg(feature = "debug")]
+    pub(super) location: &'static Location<'static>,
+}
+
+/// The library's record of an outstanding loan, kept under the hash of its key.
+#[derive(Clone)]
+pub(super) struct Issue {
+    pub(super) generation: u64,
+    pub(super) at: Instant,
+    /// Whether the value has been mutably accessed during the loan.
+    pub(super) dirty: bool,
+    pub(super) tag: Option<Cow<'static, str>>,
+    #[cfg(feature = "debug")]
+    pub(super) location: &'static Location<'static>,
+}
+
+impl<'a, K> OutstandingLoan<'a, K> {
+    /// Returns the key of the loaned value.
+    pub fn key(&self) -> &'a K {
+        self.key
+    }
+
+    /// Returns the tag the value was lent with by [`LendingLibrary::lend_tagged()`], if any.
+    ///
+    /// [`LendingLibrary::lend_tagged()`]: struct.LendingLibrary.html#method.lend_tagged
+    pub fn tag(&self) -> Option<&'a str> {
+        self.tag
+    }
+
+    /// Returns how long the value has been on loan.
+    pub fn held_for(&self) -> Duration {
+        self.issued.elapsed()
+    }
+
+    /// Returns the location of the call that lent the value. This is only available with the
+    /// `debug` feature enabled.
+    #[cfg(feature = "debug")]
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+}
+
+impl LoanWatch {
+    /// Returns true if the loan has been returned, or the library has been dropped.
+    pub fn is_returned(&self) -> bool {
+        if !self.returned.get() {
+            match self.rx.try_recv() {
+                Ok(()) | Err(TryRecvError::Disconnected) => self.returned.set(true),
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        self.returned.get()
+    }
+
+    /// Blocks the current thread until the loan has been returned, or the library has been
+    /// dropped.
+    pub fn wait(&self) {
+        if !self.returned.get() {
+            let _ = self.rx.recv();
+            self.returned.set(true);
+        }
+    }
+
+    /// Blocks the current thread until the loan has been returned, or the library has been
+    /// dropped, for at most `timeout`. Returns true if the loan was returned in time.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        if !self.returned.get() {
+            match self.rx.recv_timeout(timeout) {
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => self.returned.set(true),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+        self.returned.get()
+    }
+}
+
+impl Debug for LoanWatch {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.debug_struct("LoanWatch")
+            .field("returned", &self.is_returned())
+            .finish()
+    }
+}
+
+/// The two halves of a loaned pair produced by [`Loan::split()`].
+///
+/// [`Loan::split()`]: struct.Loan.html#method.split
+type Halves<K, A, B, S> = (MappedLoan<K, (A, B), A, S>, MappedLoan<K, (A, B), B, S>);
+
+/// A token representing a loaned value that has been moved into raw escrow by
+/// [`Loan::into_raw()`]. It must be handed back to [`Loan::from_raw()`] along with the raw pointer
+/// to reconstitute the loan; if it is lost the value is leaked and the store will report it as an
+/// outstanding loan when dropped.
+///
+/// [`Loan::into_raw()`]: struct.Loan.html#method.into_raw
+/// [`Loan::from_raw()`]: struct.Loan.html#method.from_raw
+#[must_use]
+pub struct RawToken<K, V, S = RandomState>
+where
+    K: Hash,
+{
+    owner: *mut LendingLibrary<K, V, S>,
+    alive: Weak<()>,
+    key: u64,
+    generation: u64,
+    ptr: *mut V,
+}
+
+/// A view of a `Loan` that serializes the loaned value together with its key, created by
+/// [`Loan::with_key()`].
+///
+/// [`Loan::with_key()`]: struct.Loan.html#method.with_key
+#[cfg(feature = "serde")]
+pub struct WithKey<'a, K, V, S = RandomState>
+where
+    K: Hash + 'a,
+    V: 'a,
+    S: 'a,
+{
+    loan: &'a Loan<K, V, S>,
+}
+
+impl<K, V, S> Loan<K, V, S>
+where
+    K: Hash,
+{
+    /// Returns a view of the loan that serializes as a struct with `key` and `value` fields,
+    /// rather than as the bare value.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// extern crate lending_library;
+    /// extern crate serde_json;
+    /// use lending_library::{LendingLibrary, Loan};
+    /// fn main() {
+    ///     let mut lib: LendingLibrary<&str, i32> = LendingLibrary::new();
+    ///     lib.insert("a", 1);
+    ///     let v = lib.lend(&"a").unwrap();
+    ///     assert_eq!(serde_json::to_string(&v).unwrap(), "1");
+    ///     assert_eq!(serde_json::to_string(&Loan::with_key(&v)).unwrap(),
+    ///                r#"{"key":"a","value":1}"#);
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn with_key(loan: &Self) -> WithKey<'_, K, V, S> {
+        WithKey { loan }
+    }
+
+    /// Returns true if the loaned value has been mutably accessed through this loan.
+    ///
+    /// When the loan is returned, the library records that the value is dirty, so that the keys of
+    /// changed values can be collected with [`drain_dirty_keys()`].
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    ///
+    /// [`drain_dirty_keys()`]: struct.LendingLibrary.html#method.drain_dirty_keys
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let mut v = lib.lend(&1).unwrap();
+    /// assert!(!Loan::is_dirty(&v));
+    /// *v += 1;
+    /// assert!(Loan::is_dirty(&v));
+    /// ```
+    pub fn is_dirty(loan: &Self) -> bool {
+        loan.dirty
+    }
+
+    /// Swaps the values held by two loans, so that each is returned to the other's entry.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<&str, Vec<u32>> = LendingLibrary::new();
+    /// lib.insert("busy", vec![1, 2, 3]);
+    /// lib.insert("idle", vec![]);
+    /// {
+    ///     let mut busy = lib.lend(&"busy").unwrap();
+    ///     let mut idle = lib.lend(&"idle").unwrap();
+    ///     Loan::swap_with(&mut busy, &mut idle);
+    /// }
+    /// assert_eq!(lib.get_mut(&"idle"), Some(&mut vec![1, 2, 3]));
+    /// ```
+    pub fn swap_with(loan: &mut Self, other: &mut Self) {
+        mem::swap::<V>(loan, other);
+    }
+
+    /// Returns true if the loan was issued by `lib`.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut a: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// let b: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// a.insert(1, 1);
+    /// let v = a.lend(&1).unwrap();
+    /// assert!(Loan::issued_by(&v, &a));
+    /// assert!(!Loan::issued_by(&v, &b));
+    /// ```
+    pub fn issued_by(loan: &Self, lib: &LendingLibrary<K, V, S>) -> bool {
+        lib.is_issuer(&loan.alive)
+    }
+
+    /// Returns how long the value has been on loan.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// use std::{thread, time::Duration};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let v = lib.lend(&1).unwrap();
+    /// thread::sleep(Duration::from_millis(5));
+    /// assert!(Loan::held_for(&v) >= Duration::from_millis(5));
+    /// ```
+    pub fn held_for(loan: &Self) -> Duration {
+        match unsafe { (*loan.owner).issued.get(&loan.key) } {
+            Some(i) => i.at.elapsed(),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    /// Moves the loaned value into raw escrow, returning a raw pointer to it along with a
+    /// `RawToken` that can later be used to reconstitute the loan. This allows a value to be
+    /// passed through FFI callbacks that only accept raw pointers.
+    ///
+    /// The loan remains outstanding while in escrow, so the value must be restored with
+    /// [`Loan::from_raw()`] before the store is dropped.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    ///
+    /// [`Loan::from_raw()`]: #method.from_raw
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let (ptr, token) = Loan::into_raw(lib.lend(&1).unwrap());
+    /// unsafe { *ptr += 1; }
+    /// let v = unsafe { Loan::from_raw(token, ptr) };
+    /// assert_eq!(*v, 2);
+    /// ```
+    pub fn into_raw(mut loan: Self) -> (*mut V, RawToken<K, V, S>) {
+        let ptr = Box::into_raw(Box::new(loan.inner.take().unwrap()));
+        unsafe {
+            (*loan.owner).escrowed.fetch_add(1, Ordering::Relaxed);
+        }
+        (ptr, RawToken {
+            owner: loan.owner,
+            alive: loan.alive.clone(),
+            key: loan.key,
+            generation: loan.generation,
+            ptr,
+        })
+    }
+
+    /// Reconstitutes a loan from a raw pointer and the `RawToken` produced alongside it by
+    /// [`Loan::into_raw()`].
+    ///
+    /// [`Loan::into_raw()`]: #method.into_raw
+    /// # Panics
+    /// Will panic if `ptr` is not the pointer that was issued with `token`.
+    /// # Safety
+    /// `ptr` must not have been freed or aliased since it was produced by `into_raw`, and the
+    /// store it was loaned from must still be alive.
+    pub unsafe fn from_raw(token: RawToken<K, V, S>, ptr: *mut V) -> Self {
+        if token.ptr != ptr {
+            _panic(&(*token.owner).name, "Raw pointer does not match escrow token")
+        }
+        (*token.owner).escrowed.fetch_sub(1, Ordering::Relaxed);
+        Loan {
+            owner: token.owner,
+            alive: token.alive,
+            key: token.key,
+            generation: token.generation,
+            inner: Some(*Box::from_raw(ptr)),
+            dirty: true,
+        }
+    }
+
+    /// Returns the loaned value to the store. This is what happens when a loan is dropped, but
+    /// makes the point of return explicit, so that any later use of the loan fails to compile.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let v = lib.lend(&1).unwrap();
+    /// Loan::checkin(v);
+    /// assert_eq!(lib.get_mut(&1), Some(&mut 1));
+    /// ```
+    pub fn checkin(loan: Self) {
+        drop(loan);
+    }
+
+    /// Applies `f` to the loaned value and then returns it to the store, as for
+    /// [`Loan::checkin()`].
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    ///
+    /// [`Loan::checkin()`]: #method.checkin
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, Vec<i32>> = LendingLibrary::new();
+    /// lib.insert(1, vec![1, 2]);
+    /// let v = lib.lend(&1).unwrap();
+    /// Loan::checkin_with(v, |v| v.clear());
+    /// assert_eq!(lib.get_mut(&1), Some(&mut vec![]));
+    /// ```
+    pub fn checkin_with<F>(mut loan: Self, f: F)
+    where
+        F: FnOnce(&mut V),
+    {
+        f(&mut loan);
+        drop(loan);
+    }
+
+    /// Consumes the loan, removing its entry from the store and handing the value to the caller
+    /// to keep.
+    ///
+    /// The loan stops counting as outstanding, so this is the way to deliberately detach a value
+    /// for good; unlike leaking the loan with `mem::forget`, it will not cause the store to panic
+    /// when dropped.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, String> = LendingLibrary::new();
+    /// lib.insert(1, "a".to_string());
+    /// let v = Loan::into_inner(lib.lend(&1).unwrap());
+    /// assert_eq!(v, "a");
+    /// assert!(!lib.contains_key(&1));
+    /// ```
+    pub fn into_inner(mut loan: Self) -> V {
+        let val = loan.inner.take().unwrap();
+        unsafe {
+            (*loan.owner).check_issuer(&loan.alive);
+            (*loan.owner).retire(loan.key, loan.generation);
+        }
+        val
+    }
+
+    /// Consumes the loan, keeping its value forever, as for [`Loan::into_inner()`].
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    ///
+    /// [`Loan::into_inner()`]: #method.into_inner
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, String> = LendingLibrary::new();
+    /// lib.insert(1, "a".to_string());
+    /// let v = Loan::forget(lib.lend(&1).unwrap());
+    /// assert_eq!(v, "a");
+    /// assert!(!lib.contains_key(&1));
+    /// ```
+    pub fn forget(loan: Self) -> V {
+        Loan::into_inner(loan)
+    }
+
+    /// Consumes the loan, removing its entry from the store and dropping the value rather than
+    /// returning it. This ensures that a value found to be broken is never lent out again.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, String> = LendingLibrary::new();
+    /// lib.insert(1, "a".to_string());
+    /// Loan::discard(lib.lend(&1).unwrap());
+    /// assert!(lib.lend(&1).is_none());
+    /// ```
+    pub fn discard(loan: Self) {
+        drop(Loan::into_inner(loan));
+    }
+
+    /// Converts the loan into a shared [`ReadLoan`], so that others can read the value alongside
+    /// this borrower without it first being returned to the store.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    ///
+    /// [`ReadLoan`]: struct.ReadLoan.html
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let mut w = lib.lend(&1).unwrap();
+    /// *w += 1;
+    /// let r = Loan::downgrade(w);
+    /// assert_eq!(*lib.lend_shared(&1).unwrap(), 2);
+    /// assert_eq!(*r, 2);
+    /// ```
+    pub fn downgrade(mut loan: Self) -> ReadLoan<K, V, S> {
+        let val = loan.inner.take().unwrap();
+        let ptr = unsafe {
+            if loan.dirty {
+                (*loan.owner).mark_dirty(loan.key);
+            }
+            (*loan.owner).share(loan.key, val)
+        };
+        ReadLoan {
+            owner: loan.owner,
+            alive: loan.alive.clone(),
+            key: loan.key,
+            generation: loan.generation,
+            ptr,
+        }
+    }
+
+    /// Narrows the loan to a part of the loaned value, such as one field of a struct, so that it
+    /// can be handed on without exposing the rest. The whole value is still returned to the store
+    /// when the resulting `MappedLoan` is dropped.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// struct Conn { id: u32, buf: Vec<u8> }
+    /// let mut lib: LendingLibrary<i32, Conn> = LendingLibrary::new();
+    /// lib.insert(1, Conn { id: 7, buf: vec![] });
+    /// {
+    ///     let mut buf = Loan::map(lib.lend(&1).unwrap(), |c| &mut c.buf);
+    ///     buf.push(1);
+    /// }
+    /// let c = lib.lend(&1).unwrap();
+    /// assert_eq!((c.id, c.buf.len()), (7, 1));
+    /// ```
+    pub fn map<T, F>(loan: Self, f: F) -> MappedLoan<K, V, T, S>
+    where
+        T: ?Sized,
+        F: FnOnce(&mut V) -> &mut T,
+    {
+        let mut loan = Rc::new((loan, Cell::new(false)));
+        let ptr: *mut T = f(Rc::get_mut(&mut loan).unwrap().0.inner.as_mut().unwrap());
+        MappedLoan { loan, ptr }
+    }
+
+    /// Narrows the loan to a part of the loaned value like [`Loan::map()`], but with a projection
+    /// that may fail, such as into one variant of an enum. If the projection returns `None`, the
+    /// original loan is handed back unchanged.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    ///
+    /// [`Loan::map()`]: #method.map
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, Result<u32, String>> = LendingLibrary::new();
+    /// lib.insert(1, Err("failed".to_string()));
+    /// let loan = lib.lend(&1).unwrap();
+    /// let loan = Loan::try_map(loan, |r| r.as_mut().ok()).unwrap_err();
+    /// let msg = Loan::try_map(loan, |r| r.as_mut().err()).unwrap();
+    /// assert_eq!(*msg, "failed");
+    /// ```
+    pub fn try_map<T, F>(loan: Self, f: F) -> Result<MappedLoan<K, V, T, S>, Self>
+    where
+        T: ?Sized,
+        F: FnOnce(&mut V) -> Option<&mut T>,
+    {
+        let mut loan = Rc::new((loan, Cell::new(false)));
+        match f(Rc::get_mut(&mut loan).unwrap().0.inner.as_mut().unwrap()).map(|t| t as *mut T) {
+            Some(ptr) => Ok(MappedLoan { loan, ptr }),
+            None => Err(Rc::try_unwrap(loan).ok().unwrap().0),
+        }
+    }
+}
+
+impl<K, A, B, S> Loan<K, (A, B), S>
+where
+    K: Hash,
+{
+    /// Splits the loan of a pair into loans of each half, which can be handed on and dropped
+    /// independently. The pair is returned to the store once both halves have been dropped.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, Loan};
+    /// let mut lib: LendingLibrary<i32, (Vec<u8>, String)> = LendingLibrary::new();
+    /// lib.insert(1, (vec![], String::new()));
+    /// let (mut bytes, mut text) = Loan::split(lib.lend(&1).unwrap());
+    /// bytes.push(1);
+    /// drop(bytes);
+    /// text.push('a');
+    /// drop(text);
+    /// assert_eq!(lib.get_mut(&1), Some(&mut (vec![1], "a".to_string())));
+    /// ```
+    pub fn split(loan: Self) -> Halves<K, A, B, S> {
+        let mut loan = Rc::new((loan, Cell::new(false)));
+        let (a, b): (*mut A, *mut B) = {
+            let pair = Rc::get_mut(&mut loan).unwrap().0.inner.as_mut().unwrap();
+            (&mut pair.0, &mut pair.1)
+        };
+        let first = MappedLoan {
+            loan: loan.clone(),
+            ptr: a,
+        };
+        (first, MappedLoan { loan, ptr: b })
+    }
+}
+
+impl<K, V, T, S> MappedLoan<K, V, T, S>
+where
+    K: Hash,
+    T: ?Sized,
+{
+    /// Narrows the loan further, to a part of the currently mapped value.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    pub fn map<U, F>(loan: Self, f: F) -> MappedLoan<K, V, U, S>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let ptr: *mut U = f(unsafe { &mut *loan.ptr });
+        MappedLoan {
+            loan: loan.loan.clone(),
+            ptr,
+        }
+    }
+
+    /// Narrows the loan further with a projection that may fail, handing back the loan unchanged
+    /// if it returns `None`.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    pub fn try_map<U, F>(loan: Self, f: F) -> Result<MappedLoan<K, V, U, S>, Self>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(unsafe { &mut *loan.ptr }).map(|u| u as *mut U) {
+            Some(ptr) => Ok(MappedLoan {
+                loan: loan.loan.clone(),
+                ptr,
+            }),
+            None => Err(loan),
+        }
+    }
+}
+
+impl<K, V, S> ReadLoan<K, V, S>
+where
+    K: Hash,
+{
+    /// Converts a read loan into an exclusive [`Loan`] if it is the only read loan outstanding for
+    /// its value, and hands it back unchanged otherwise.
+    ///
+    /// This is an associated function so as not to conflict with methods on the loaned value.
+    ///
+    /// [`Loan`]: struct.Loan.html
+    /// # Example
+    /// ```
+    /// use lending_library::{LendingLibrary, ReadLoan};
+    /// let mut lib: LendingLibrary<i32, i32> = LendingLibrary::new();
+    /// lib.insert(1, 1);
+    /// let r1 = lib.lend_shared(&1).unwrap();
+    /// let r2 = lib.lend_shared(&1).unwrap();
+    /// let r1 = ReadLoan::upgrade(r1).unwrap_err();
+    /// drop(r2);
+    /// let mut w = ReadLoan::upgrade(r1).unwrap();
+    /// *w += 1;
+    /// ```
+    pub fn upgrade(mut loan: Self) -> Result<Loan<K, V, S>, Self> {
+        let val = match unsafe { (*loan.owner).unshare(loan.key) } {
+            Some(v) => v,
+            None => return Err(loan),
+        };
+        let upgraded = Loan {
+            owner: loan.owner,
+            alive: mem::replace(&mut loan.alive, Weak::new()),
+            key: loan.key,
+            generation: loan.generation,
+            inner: Some(val),
+            dirty: false,
+        };
+        mem::forget(loan);
+        Ok(upgraded)
+    }
+}
+
+impl<K, V, S> Debug for Loan<K, V, S>
 where
     K: Hash,
     V: Debug,
@@ -33,7 +838,17 @@ where
     }
 }
 
-impl<K, V> PartialEq for Loan<K, V>
+impl<K, V, S> Display for Loan<K, V, S>
+where
+    K: Hash,
+    V: Display,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Display>::fmt(self, f)
+    }
+}
+
+impl<K, V, S> PartialEq for Loan<K, V, S>
 where
     K: Hash,
     V: PartialEq,
@@ -43,20 +858,99 @@ where
     }
 }
 
-impl<K, V> Drop for Loan<K, V>
+#[cfg(feature = "serde")]
+impl<K, V, S> Serialize for Loan<K, V, S>
+where
+    K: Hash,
+    V: Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        <V as Serialize>::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a, K, V, S> Serialize for WithKey<'a, K, V, S>
+where
+    K: Hash + Serialize,
+    V: Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        let key = unsafe { (*self.loan.owner).loaned_key(self.loan.key) };
+        let mut state = serializer.serialize_struct("Loan", 2)?;
+        state.serialize_field("key", key)?;
+        state.serialize_field("value", &**self.loan)?;
+        state.end()
+    }
+}
+
+impl<K, V, S> Eq for Loan<K, V, S>
+where
+    K: Hash,
+    V: Eq,
+{
+}
+
+impl<K, V, S> Hash for Loan<K, V, S>
+where
+    K: Hash,
+    V: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        <V as Hash>::hash(self, state)
+    }
+}
+
+impl<K, V, S> PartialOrd for Loan<K, V, S>
+where
+    K: Hash,
+    V: PartialOrd,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        <V as PartialOrd>::partial_cmp(self, other)
+    }
+}
+
+impl<K, V, S> Ord for Loan<K, V, S>
+where
+    K: Hash,
+    V: Ord,
+{
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        <V as Ord>::cmp(self, other)
+    }
+}
+
+impl<K, V, S> Drop for Loan<K, V, S>
 where
     K: Hash,
 {
     fn drop(&mut self) {
-        if self.inner.is_some() && !thread::panicking() {
-            unsafe {
-                (*self.owner).checkin(self.key, self.inner.take().unwrap());
+        if let Some(val) = self.inner.take() {
+            if !thread::panicking() {
+                unsafe {
+                    (*self.owner).check_issuer(&self.alive);
+                    if self.dirty {
+                        (*self.owner).mark_dirty(self.key);
+                    }
+                    (*self.owner).checkin(self.key, self.generation, val);
+                }
+            } else if self.alive.strong_count() != 0 {
+                unsafe {
+                    if !(*self.owner).is_issuer(&self.alive) {
+                        return;
+                    }
+                    if self.dirty {
+                        (*self.owner).mark_dirty(self.key);
+                    }
+                    (*self.owner).checkin_poisoned(self.key, self.generation, val);
+                }
             }
         }
     }
 }
 
-impl<K, V> Deref for Loan<K, V>
+impl<K, V, S> Deref for Loan<K, V, S>
 where
     K: Hash,
 {
@@ -67,11 +961,285 @@ where
     }
 }
 
-impl<K, V> DerefMut for Loan<K, V>
+impl<K, V, S> DerefMut for Loan<K, V, S>
 where
     K: Hash,
 {
     fn deref_mut(&mut self) -> &mut V {
+        self.dirty = true;
         self.inner.as_mut().unwrap()
     }
 }
+
+impl<K, V, S> AsRef<V> for Loan<K, V, S>
+where
+    K: Hash,
+{
+    fn as_ref(&self) -> &V {
+        self
+    }
+}
+
+impl<K, V, S> AsMut<V> for Loan<K, V, S>
+where
+    K: Hash,
+{
+    fn as_mut(&mut self) -> &mut V {
+        self
+    }
+}
+
+impl<K, V, S> Borrow<V> for Loan<K, V, S>
+where
+    K: Hash,
+{
+    fn borrow(&self) -> &V {
+        self
+    }
+}
+
+impl<K, V, S> BorrowMut<V> for Loan<K, V, S>
+where
+    K: Hash,
+{
+    fn borrow_mut(&mut self) -> &mut V {
+        self
+    }
+}
+
+impl<K, V, T, S> Debug for MappedLoan<K, V, T, S>
+where
+    K: Hash,
+    T: ?Sized + Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <T as Debug>::fmt(self, f)
+    }
+}
+
+impl<K, V, T, S> Deref for MappedLoan<K, V, T, S>
+where
+    K: Hash,
+    T: ?Sized,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<K, V, T, S> DerefMut for MappedLoan<K, V, T, S>
+where
+    K: Hash,
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.loan.1.set(true);
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl<K, V, T, S> Drop for MappedLoan<K, V, T, S>
+where
+    K: Hash,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        if let Some(&mut (ref mut loan, ref dirty)) = Rc::get_mut(&mut self.loan) {
+            if dirty.get() {
+                loan.dirty = true;
+            }
+        }
+    }
+}
+
+impl<K, V, S> Debug for ReadLoan<K, V, S>
+where
+    K: Hash,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<K, V, S> PartialEq for ReadLoan<K, V, S>
+where
+    K: Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<K, V, S> Clone for ReadLoan<K, V, S>
+where
+    K: Hash,
+{
+    fn clone(&self) -> Self {
+        unsafe {
+            (*self.owner).add_reader(self.key, &self.alive);
+        }
+        ReadLoan {
+            owner: self.owner,
+            alive: self.alive.clone(),
+            key: self.key,
+            generation: self.generation,
+            ptr: self.ptr,
+        }
+    }
+}
+
+impl<K, V, S> Reservation<K, V, S>
+where
+    K: Hash,
+{
+    /// Returns a reference to the reserved key.
+    pub fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+}
+
+impl<K, V, S> Debug for Reservation<K, V, S>
+where
+    K: Hash + Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.debug_tuple("Reservation").field(self.key()).finish()
+    }
+}
+
+impl<K, V, S> Drop for Reservation<K, V, S>
+where
+    K: Hash,
+{
+    fn drop(&mut self) {
+        if self.key.is_some() && self.alive.strong_count() != 0 {
+            unsafe {
+                if (*self.owner).is_issuer(&self.alive) {
+                    (*self.owner).reserved.remove(&self.hash);
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, S> Drop for ReadLoan<K, V, S>
+where
+    K: Hash,
+{
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            unsafe {
+                (*self.owner).check_issuer(&self.alive);
+                (*self.owner).release_shared(self.key, self.generation);
+            }
+        } else if self.alive.strong_count() != 0 {
+            unsafe {
+                if (*self.owner).is_issuer(&self.alive) {
+                    (*self.owner).release_shared(self.key, self.generation);
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, S> Deref for ReadLoan<K, V, S>
+where
+    K: Hash,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<V> Debug for SendLoan<V>
+where
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<V> PartialEq for SendLoan<V>
+where
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<V> Drop for SendLoan<V> {
+    fn drop(&mut self) {
+        if let Some(val) = self.inner.take() {
+            let _ = self.tx.send(Returned {
+                key: self.key,
+                generation: self.generation,
+                val,
+                dirty: self.dirty,
+                poisoned: thread::panicking(),
+            });
+        }
+    }
+}
+
+impl<V> Deref for SendLoan<V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<V> DerefMut for SendLoan<V> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.dirty = true;
+        self.inner.as_mut().unwrap()
+    }
+}
+
+impl<'a, K, V, S> Debug for ScopedLoan<'a, K, V, S>
+where
+    K: Hash,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<'a, K, V, S> PartialEq for ScopedLoan<'a, K, V, S>
+where
+    K: Hash,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.loan == other.loan
+    }
+}
+
+impl<'a, K, V, S> Deref for ScopedLoan<'a, K, V, S>
+where
+    K: Hash,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.loan
+    }
+}
+
+impl<'a, K, V, S> DerefMut for ScopedLoan<'a, K, V, S>
+where
+    K: Hash,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.loan
+    }
+}
@@ -0,0 +1,65 @@
+/* Notice
+lru.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+use std::{collections::{BTreeMap, HashMap},
+          ops::Bound::{Excluded, Unbounded}};
+
+/// Tracks the order in which the entries of a library, identified by key hash, were last used.
+#[derive(Clone, Default)]
+pub(super) struct Recency {
+    tick: u64,
+    ticks: HashMap<u64, u64>,
+    order: BTreeMap<u64, u64>,
+}
+
+impl Recency {
+    /// Marks `hash` as the most recently used entry.
+    pub(super) fn touch(&mut self, hash: u64) {
+        self.tick += 1;
+        if let Some(old) = self.ticks.insert(hash, self.tick) {
+            self.order.remove(&old);
+        }
+        self.order.insert(self.tick, hash);
+    }
+
+    /// Stops tracking `hash`.
+    pub(super) fn forget(&mut self, hash: u64) {
+        if let Some(old) = self.ticks.remove(&hash) {
+            self.order.remove(&old);
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    /// Stops tracking every entry for which `f` returns false.
+    pub(super) fn retain<F: FnMut(u64) -> bool>(&mut self, mut f: F) {
+        self.ticks.retain(|h, _t| f(*h));
+        self.order.retain(|_t, h| f(*h));
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.ticks.clear();
+        self.order.clear();
+    }
+
+    /// Returns the least recently used entry that was used after `after`, along with the point at
+    /// which it was used, so that callers can walk entries from oldest to newest.
+    pub(super) fn next_after(&self, after: Option<u64>) -> Option<(u64, u64)> {
+        let start = match after {
+            Some(t) => Excluded(t),
+            None => Unbounded,
+        };
+        self.order
+            .range((start, Unbounded))
+            .next()
+            .map(|(t, h)| (*t, *h))
+    }
+}
@@ -0,0 +1,395 @@
+/* Notice
+multimap.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A variant of `LendingLibrary` that stores several values under each key.
+
+use super::_panic;
+use std::{borrow::Borrow,
+          collections::HashMap,
+          fmt::{Debug, Error as FmtError, Formatter},
+          hash::Hash,
+          iter::FromIterator,
+          ops::{Deref, DerefMut},
+          sync::atomic::{AtomicUsize, Ordering},
+          thread};
+
+enum Slot<V> {
+    Vacant,
+    Present(V),
+    Loaned,
+    AwaitingDrop,
+}
+
+use self::Slot::{AwaitingDrop, Loaned, Present, Vacant};
+
+/// A data store that lends temporary ownership of stored values, holding a pool of values under
+/// each key.
+///
+/// Each value under a key can be loaned independently, with the same loan semantics as
+/// [`LendingLibrary`]. Loans hold a copy of their key, so keys must be `Clone`.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::multimap::LendingMultiMap;
+/// let mut pools: LendingMultiMap<&str, u32> = LendingMultiMap::new();
+/// pools.insert("db1", 1);
+/// pools.insert("db1", 2);
+/// {
+///     let a = pools.lend_one(&"db1").unwrap();
+///     let b = pools.lend_one(&"db1").unwrap();
+///     assert_ne!(*a, *b);
+///     assert!(pools.lend_one(&"db1").is_none());
+/// }
+/// assert_eq!(pools.lend_all(&"db1").len(), 2);
+/// ```
+pub struct LendingMultiMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    store: HashMap<K, Vec<Slot<V>>>,
+    outstanding: AtomicUsize,
+    name: Option<String>,
+}
+
+/// A smart pointer representing the loan of a value from a `LendingMultiMap` instance.
+pub struct Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    owner: *mut LendingMultiMap<K, V>,
+    key: Option<K>,
+    index: usize,
+    inner: Option<V>,
+}
+
+impl<K, V> LendingMultiMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Creates a new empty `LendingMultiMap`.
+    /// # Example
+    /// ```
+    /// use lending_library::multimap::LendingMultiMap;
+    /// let mut lib: LendingMultiMap<i32, i32> = LendingMultiMap::new();
+    /// ```
+    pub fn new() -> LendingMultiMap<K, V> {
+        LendingMultiMap {
+            store: HashMap::new(),
+            outstanding: AtomicUsize::new(0),
+            name: None,
+        }
+    }
+
+    /// Creates a new empty `LendingMultiMap` with a name, which is included in any panic messages
+    /// it raises.
+    pub fn named<N: Into<String>>(name: N) -> LendingMultiMap<K, V> {
+        let mut lib = LendingMultiMap::new();
+        lib.name = Some(name.into());
+        lib
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the total number of values in the store, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.store.keys().map(|k| self.count(k)).sum()
+    }
+
+    /// Returns true if the store holds no values, including loaned values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if any values are stored under `key`, including loaned values.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.count(key) != 0
+    }
+
+    /// Returns the number of values stored under `key`, including those currently loaned.
+    pub fn count<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.store.get(key).map_or(0, |slots| {
+            slots
+                .iter()
+                .filter(|s| matches!(**s, Present(_) | Loaned))
+                .count()
+        })
+    }
+
+    /// Returns the number of values stored under `key` that are available to be loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::multimap::LendingMultiMap;
+    /// let mut lib: LendingMultiMap<i32, i32> = vec![(1, 1), (1, 2)].into_iter().collect();
+    /// let _v = lib.lend_one(&1).unwrap();
+    /// assert_eq!((lib.count(&1), lib.available(&1)), (2, 1));
+    /// ```
+    pub fn available<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.store.get(key).map_or(0, |slots| {
+            slots.iter().filter(|s| matches!(**s, Present(_))).count()
+        })
+    }
+
+    /// An iterator visiting all keys with values stored under them in arbitary order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.store.keys().filter(move |k| self.contains_key(*k))
+    }
+
+    /// Adds a value to those stored under `key`.
+    pub fn insert(&mut self, key: K, val: V) {
+        let slots = self.store.entry(key).or_default();
+        match slots.iter().position(|s| matches!(*s, Vacant)) {
+            Some(i) => slots[i] = Present(val),
+            None => slots.push(Present(val)),
+        }
+    }
+
+    /// Removes all values stored under `key`, returning how many there were. Values that are
+    /// currently loaned are dropped when their loans are returned.
+    pub fn remove<Q>(&mut self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let mut removed = 0;
+        if let Some(slots) = self.store.get_mut(key) {
+            for s in slots.iter_mut() {
+                match *s {
+                    Present(_) => *s = Vacant,
+                    Loaned => *s = AwaitingDrop,
+                    Vacant | AwaitingDrop => continue,
+                }
+                removed += 1;
+            }
+        }
+        self.tidy(key);
+        removed
+    }
+
+    /// Removes and returns all values stored under `key` that are not currently loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::multimap::LendingMultiMap;
+    /// let mut lib: LendingMultiMap<i32, i32> = vec![(1, 1), (1, 2)].into_iter().collect();
+    /// let _v = lib.lend_one(&1).unwrap();
+    /// assert_eq!(lib.take_available(&1).len(), 1);
+    /// assert_eq!(lib.count(&1), 1);
+    /// ```
+    pub fn take_available<Q>(&mut self, key: &Q) -> Vec<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let mut taken = Vec::new();
+        if let Some(slots) = self.store.get_mut(key) {
+            for s in slots.iter_mut() {
+                if let Present(_) = *s {
+                    if let Present(v) = ::std::mem::replace(s, Vacant) {
+                        taken.push(v);
+                    }
+                }
+            }
+        }
+        self.tidy(key);
+        taken
+    }
+
+    /// Loans one of the values stored under `key` that is not already loaned, returning `None` if
+    /// there are none.
+    pub fn lend_one<Q>(&mut self, key: &Q) -> Option<Loan<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let index = self
+            .store
+            .get(key)?
+            .iter()
+            .position(|s| matches!(*s, Present(_)))?;
+        self.lend_index(key, index)
+    }
+
+    /// Loans all of the values stored under `key` that are not already loaned.
+    pub fn lend_all<Q>(&mut self, key: &Q) -> Vec<Loan<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let indices: Vec<usize> = match self.store.get(key) {
+            Some(slots) => slots
+                .iter()
+                .enumerate()
+                .filter(|&(_i, s)| matches!(*s, Present(_)))
+                .map(|(i, _s)| i)
+                .collect(),
+            None => return Vec::new(),
+        };
+        indices
+            .into_iter()
+            .filter_map(|i| self.lend_index(key, i))
+            .collect()
+    }
+
+    fn lend_index<Q>(&mut self, key: &Q, index: usize) -> Option<Loan<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let ptr: *mut Self = self;
+        let k = self.store.get_key_value(key)?.0.clone();
+        let slots = self.store.get_mut(key)?;
+        let val = match ::std::mem::replace(&mut slots[index], Loaned) {
+            Present(v) => v,
+            _ => unreachable!(),
+        };
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            key: Some(k),
+            index,
+            inner: Some(val),
+        })
+    }
+
+    fn tidy<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let empty = match self.store.get(key) {
+            Some(slots) => slots.iter().all(|s| matches!(*s, Vacant)),
+            None => false,
+        };
+        if empty {
+            self.store.remove(key);
+        }
+    }
+
+    fn checkin(&mut self, key: K, index: usize, val: V) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        match self.store.get_mut(&key).and_then(|slots| slots.get_mut(index)) {
+            Some(s) => match *s {
+                Loaned => *s = Present(val),
+                AwaitingDrop => *s = Vacant,
+                Present(_) => _panic(&self.name, "Returning replaced item"),
+                Vacant => _panic(&self.name, "Returning item not from store"),
+            },
+            None => _panic(&self.name, "Returning item not from store"),
+        }
+        self.tidy(&key);
+    }
+}
+
+impl<K, V> Drop for LendingMultiMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let count = self.outstanding.load(Ordering::SeqCst);
+            if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.", count))
+            }
+        }
+    }
+}
+
+impl<K, V> Default for LendingMultiMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        LendingMultiMap::new()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for LendingMultiMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut lib = LendingMultiMap::new();
+        for (k, v) in iter {
+            lib.insert(k, v);
+        }
+        lib
+    }
+}
+
+impl<K, V> Debug for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<K, V> PartialEq for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<K, V> Drop for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            unsafe {
+                (*self.owner).checkin(self.key.take().unwrap(),
+                                      self.index,
+                                      self.inner.take().unwrap());
+            }
+        }
+    }
+}
+
+impl<K, V> Deref for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<K, V> DerefMut for Loan<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.inner.as_mut().unwrap()
+    }
+}
@@ -0,0 +1,145 @@
+/* Notice
+query.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A loan-aware query over the entries of a `LendingLibrary`, created by
+//! [`LendingLibrary::query()`].
+//!
+//! [`LendingLibrary::query()`]: ../struct.LendingLibrary.html#method.query
+
+use super::{LendingLibrary, LoanedError, State};
+use std::{collections::hash_map::RandomState,
+          hash::Hash};
+
+type Filter<'a, K, V> = Box<dyn FnMut(&K, &V) -> bool + 'a>;
+type Map<'a, K, V, T> = Box<dyn FnMut(&'a K, &'a V) -> T + 'a>;
+
+/// A query over the present entries of a `LendingLibrary`, built up from filters and maps and then
+/// run in a single pass by [`collect()`], [`fold()`] or [`count()`]. Its results are `(&K, &V)`
+/// pairs until mapped.
+///
+/// If any value is on loan when the query is run, it returns a `LoanedError` listing the loaned
+/// keys instead of a partial result. Filters always see each entry's key and value, whether they
+/// are added before or after a map.
+///
+/// [`collect()`]: #method.collect
+/// [`fold()`]: #method.fold
+/// [`count()`]: #method.count
+pub struct Query<'a, K: 'a, V: 'a, T, S: 'a = RandomState>
+where
+    K: Hash,
+{
+    pub(super) lib: &'a LendingLibrary<K, V, S>,
+    pub(super) filter: Filter<'a, K, V>,
+    pub(super) map: Map<'a, K, V, T>,
+}
+
+impl<'a, K, V, T, S> Query<'a, K, V, T, S>
+where
+    K: Hash,
+    T: 'a,
+{
+    /// Keeps only the entries for which `f` returns true.
+    pub fn filter<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(&K, &V) -> bool + 'a,
+    {
+        let mut filter = self.filter;
+        Query {
+            lib: self.lib,
+            filter: Box::new(move |k, v| filter(k, v) && f(k, v)),
+            map: self.map,
+        }
+    }
+
+    /// Keeps only the entries whose key `f` returns true for.
+    pub fn filter_keys<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(&K) -> bool + 'a,
+    {
+        self.filter(move |k, _v| f(k))
+    }
+
+    /// Applies `f` to each result of the query.
+    pub fn map<U, F>(self, mut f: F) -> Query<'a, K, V, U, S>
+    where
+        F: FnMut(T) -> U + 'a,
+    {
+        let mut map = self.map;
+        Query {
+            lib: self.lib,
+            filter: self.filter,
+            map: Box::new(move |k, v| f(map(k, v))),
+        }
+    }
+
+    /// Runs the query, folding its results into an accumulator with `f`.
+    /// # Errors
+    /// Returns a `LoanedError` listing the keys of the loaned values if any value is on loan.
+    pub fn fold<B, F>(mut self, init: B, mut f: F) -> Result<B, LoanedError<'a, K>>
+    where
+        F: FnMut(B, T) -> B,
+    {
+        let lib = self.lib;
+        let mut acc = init;
+        let mut loaned = Vec::new();
+        for (h, s) in &lib.store {
+            match *s {
+                State::Present(ref k, ref v) => {
+                    if loaned.is_empty() && !lib.is_expired(*h) && (self.filter)(k, v) {
+                        acc = f(acc, (self.map)(k, v));
+                    }
+                }
+                State::Loaned(ref k) => loaned.push(k),
+                State::AwaitingDrop(_) => {}
+            }
+        }
+        if !loaned.is_empty() {
+            return Err(LoanedError {
+                keys: loaned,
+                name: lib.name.as_deref(),
+            });
+        }
+        Ok(acc)
+    }
+
+    /// Runs the query, collecting its results.
+    /// # Errors
+    /// Returns a `LoanedError` listing the keys of the loaned values if any value is on loan.
+    pub fn collect<C>(self) -> Result<C, LoanedError<'a, K>>
+    where
+        C: Default + Extend<T>,
+    {
+        self.fold(C::default(), |mut c, t| {
+            c.extend(Some(t));
+            c
+        })
+    }
+
+    /// Runs the query, counting its results.
+    /// # Errors
+    /// Returns a `LoanedError` listing the keys of the loaned values if any value is on loan.
+    pub fn count(self) -> Result<usize, LoanedError<'a, K>> {
+        self.fold(0, |n, _| n + 1)
+    }
+}
+
+impl<'a, K, V, W, S> Query<'a, K, V, (&'a K, W), S>
+where
+    K: Hash,
+    W: 'a,
+{
+    /// Applies `f` to the value half of each `(key, value)` result of the query.
+    pub fn map_values<U, F>(self, mut f: F) -> Query<'a, K, V, (&'a K, U), S>
+    where
+        U: 'a,
+        F: FnMut(W) -> U + 'a,
+    {
+        self.map(move |(k, w)| (k, f(w)))
+    }
+}
@@ -0,0 +1,226 @@
+/* Notice
+set.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A set variant of `LendingLibrary`, which lends out exclusive tokens for its keys.
+
+use super::{LendingLibrary, Loan};
+use std::{borrow::Borrow,
+          collections::hash_map::RandomState,
+          fmt::{Debug, Error as FmtError, Formatter},
+          hash::{BuildHasher, Hash},
+          iter::FromIterator};
+
+/// A set of keys that lends out exclusive [`KeyToken`]s, with the same loan semantics as
+/// [`LendingLibrary`].
+///
+/// Holding the token for a key shows that no-one else holds it, which can be used to coordinate
+/// exclusive access to external resources identified only by ID.
+///
+/// [`KeyToken`]: struct.KeyToken.html
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::set::LendingSet;
+/// let mut devices: LendingSet<&str> = LendingSet::new();
+/// devices.insert("/dev/ttyUSB0");
+/// {
+///     let _token = devices.lend("/dev/ttyUSB0").unwrap();
+///     assert!(devices.is_loaned("/dev/ttyUSB0"));
+/// }
+/// assert!(!devices.is_loaned("/dev/ttyUSB0"));
+/// ```
+pub struct LendingSet<K, S = RandomState>
+where
+    K: Hash,
+{
+    lib: LendingLibrary<K, (), S>,
+}
+
+/// A token representing the exclusive loan of a key from a `LendingSet`. The key is returned to
+/// the set when the token is dropped.
+pub struct KeyToken<K, S = RandomState>
+where
+    K: Hash,
+{
+    _loan: Loan<K, (), S>,
+}
+
+impl<K> LendingSet<K, RandomState>
+where
+    K: Hash + Eq,
+{
+    /// Creates a new empty `LendingSet`.
+    pub fn new() -> LendingSet<K> {
+        LendingSet {
+            lib: LendingLibrary::new(),
+        }
+    }
+
+    /// Creates a new empty `LendingSet` with a name, which is included in any panic messages it
+    /// raises.
+    pub fn named<N: Into<String>>(name: N) -> LendingSet<K> {
+        LendingSet {
+            lib: LendingLibrary::named(name),
+        }
+    }
+}
+
+impl<K, S> LendingSet<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Creates an empty `LendingSet` which will use the given hash builder to hash keys.
+    pub fn with_hasher(hasher: S) -> LendingSet<K, S> {
+        LendingSet {
+            lib: LendingLibrary::with_hasher(hasher),
+        }
+    }
+
+    /// Returns the name given to the set by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&str> {
+        self.lib.name()
+    }
+
+    /// Returns the number of keys in the set, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.lib.len()
+    }
+
+    /// Returns true if the set is empty, including loaned keys.
+    pub fn is_empty(&self) -> bool {
+        self.lib.is_empty()
+    }
+
+    /// An iterator visiting all keys in arbitary order, including those currently loaned.
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.lib.keys()
+    }
+
+    /// Adds a key to the set, returning true if it was not already present.
+    /// # Panics
+    /// Will panic if the key was removed while loaned, and its token is yet to be returned.
+    pub fn insert(&mut self, key: K) -> bool {
+        if self.lib.contains_key(&key) {
+            return false;
+        }
+        self.lib.insert(key, ());
+        true
+    }
+
+    /// Returns true if the set contains `key`, whether or not it is currently loaned.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lib.contains_key(key)
+    }
+
+    /// Returns true if the token for `key` is currently loaned.
+    pub fn is_loaned<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lib.contains_key(key) && self.lib.get_key_value(key).is_none()
+    }
+
+    /// Removes a key from the set, returning true if it was present. If the key is currently
+    /// loaned, its token remains valid until dropped.
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lib.remove(key)
+    }
+
+    /// Loans the token for `key` from the set, returning `None` if the key is not present.
+    ///
+    /// A token carries no value that a panic could leave half-updated, so a key whose token was
+    /// dropped during a panic can be lent again as normal.
+    /// # Panics
+    /// Will panic if the token for `key` is already loaned.
+    pub fn lend<Q>(&mut self, key: &Q) -> Option<KeyToken<K, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.lib.clear_poison(key);
+        self.lib.lend(key).map(|loan| KeyToken { _loan: loan })
+    }
+
+    /// Loans the token for `key` from the set, returning `None` if the key is not present or its
+    /// token is already loaned.
+    /// # Example
+    /// ```
+    /// use lending_library::set::LendingSet;
+    /// let mut files: LendingSet<u32> = (0..4).collect();
+    /// let _held = files.try_lend(&1).unwrap();
+    /// assert!(files.try_lend(&1).is_none());
+    /// ```
+    pub fn try_lend<Q>(&mut self, key: &Q) -> Option<KeyToken<K, S>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        if self.is_loaned(key) {
+            return None;
+        }
+        self.lend(key)
+    }
+}
+
+impl<K, S> Default for LendingSet<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        LendingSet {
+            lib: LendingLibrary::default(),
+        }
+    }
+}
+
+impl<K, S> Extend<K> for LendingSet<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for k in iter {
+            self.insert(k);
+        }
+    }
+}
+
+impl<K, S> FromIterator<K> for LendingSet<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut set = LendingSet::default();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<K, S> Debug for KeyToken<K, S>
+where
+    K: Hash,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str("KeyToken")
+    }
+}
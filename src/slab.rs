@@ -0,0 +1,338 @@
+/* Notice
+slab.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A dense, `Vec` backed variant of `LendingLibrary` for small integer keys.
+
+use super::_panic;
+use std::{fmt::{Debug, Error as FmtError, Formatter},
+          iter::FromIterator,
+          ops::{Deref, DerefMut},
+          sync::atomic::{AtomicUsize, Ordering},
+          thread};
+
+enum Slot<V> {
+    Vacant,
+    Present(V),
+    Loaned,
+    AwaitingDrop,
+}
+
+use self::Slot::{AwaitingDrop, Loaned, Present, Vacant};
+
+/// A data store that lends temporary ownership of stored values, indexed by `usize` keys.
+///
+/// This has the same loan semantics as [`LendingLibrary`], but stores its values in a dense
+/// vector indexed directly by key rather than in a `HashMap`. This avoids hashing entirely and
+/// keeps values close together in memory, at the cost of memory proportional to the largest key
+/// stored, so it is best suited to small, densely allocated keys.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::slab::LendingSlab;
+/// let mut lib: LendingSlab<&str> = LendingSlab::new();
+/// lib.insert(0, "a");
+/// lib.insert(2, "c");
+/// {
+///     let mut v = lib.lend(2).unwrap();
+///     *v = "C";
+/// }
+/// let vals: Vec<_> = lib.iter().collect();
+/// assert_eq!(vals, vec![(0, &"a"), (2, &"C")]);
+/// ```
+pub struct LendingSlab<V> {
+    store: Vec<Slot<V>>,
+    outstanding: AtomicUsize,
+    name: Option<String>,
+}
+
+/// A smart pointer representing the loan of a value from a `LendingSlab` instance.
+pub struct Loan<V> {
+    owner: *mut LendingSlab<V>,
+    key: usize,
+    inner: Option<V>,
+}
+
+impl<V> LendingSlab<V> {
+    /// Creates a new empty `LendingSlab`.
+    /// # Example
+    /// ```
+    /// use lending_library::slab::LendingSlab;
+    /// let mut lib: LendingSlab<i32> = LendingSlab::new();
+    /// ```
+    pub fn new() -> LendingSlab<V> {
+        LendingSlab::with_capacity(0)
+    }
+
+    /// Creates an empty `LendingSlab` with room for keys up to `capacity` without reallocating.
+    pub fn with_capacity(capacity: usize) -> LendingSlab<V> {
+        LendingSlab {
+            store: Vec::with_capacity(capacity),
+            outstanding: AtomicUsize::new(0),
+            name: None,
+        }
+    }
+
+    /// Creates a new empty `LendingSlab` with a name, which is included in any panic messages it
+    /// raises.
+    pub fn named<N: Into<String>>(name: N) -> LendingSlab<V> {
+        let mut lib = LendingSlab::new();
+        lib.name = Some(name.into());
+        lib
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the number of keys the library can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.store.capacity()
+    }
+
+    /// Returns the number of items in the store, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.store
+            .iter()
+            .filter(|v| matches!(**v, Present(_) | Loaned))
+            .count()
+    }
+
+    /// Returns true if the store is empty, including loaned items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if a record with key `key` exists in the store, and false otherwise.
+    pub fn contains_key(&self, key: usize) -> bool {
+        matches!(self.store.get(key), Some(Present(_)) | Some(Loaned))
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, returning `None` if the key is
+    /// absent or the value is currently loaned.
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut V> {
+        match self.store.get_mut(key) {
+            Some(Present(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Inserts a new key/value pair into the store, growing it to hold `key` if needed. If a pair
+    /// with that key already exists, the previous value will be returned.
+    ///
+    /// Growing the store allocates a slot for every key up to and including `key`, so inserting a
+    /// large key costs memory and time proportional to the key itself, not to the number of
+    /// values stored.
+    /// # Panics
+    /// Will panic if the existing value is currently loaned or awaiting drop, or if `key` is
+    /// `usize::MAX`, since the store could then never hold it.
+    pub fn insert(&mut self, key: usize, val: V) -> Option<V> {
+        if key >= self.store.len() {
+            let len = match key.checked_add(1) {
+                Some(len) => len,
+                None => _panic(&self.name, "Key out of range"),
+            };
+            self.store.resize_with(len, || Vacant);
+        }
+        match ::std::mem::replace(&mut self.store[key], Present(val)) {
+            Vacant => None,
+            Present(v) => Some(v),
+            Loaned => _panic(&self.name, "Cannot overwrite loaned value"),
+            AwaitingDrop => _panic(&self.name, "Cannot overwrite value awaiting drop"),
+        }
+    }
+
+    /// Inserts a value into the store under a newly allocated key, which is returned. Keys are
+    /// allocated from a monotonic counter, one past the largest key the store has ever held, so a
+    /// key is never reissued even after its value is removed. For compact storage with reusable
+    /// slots, see [`LendingSlotMap`].
+    ///
+    /// [`LendingSlotMap`]: ../slotmap/struct.LendingSlotMap.html
+    /// # Example
+    /// ```
+    /// use lending_library::slab::LendingSlab;
+    /// let mut lib: LendingSlab<&str> = LendingSlab::new();
+    /// let a = lib.insert_value("a");
+    /// let b = lib.insert_value("b");
+    /// assert_eq!((a, b), (0, 1));
+    /// lib.remove(b);
+    /// assert_eq!(lib.insert_value("c"), 2);
+    /// ```
+    pub fn insert_value(&mut self, val: V) -> usize {
+        self.store.push(Present(val));
+        self.store.len() - 1
+    }
+
+    /// Removes a key/value pair from the store. Returning true if the key was present in the store
+    /// and false otherwise. A loaned value is dropped when its loan is returned.
+    pub fn remove(&mut self, key: usize) -> bool {
+        match self.store.get_mut(key) {
+            Some(slot) => match *slot {
+                Present(_) => {
+                    *slot = Vacant;
+                    true
+                }
+                Loaned => {
+                    *slot = AwaitingDrop;
+                    true
+                }
+                Vacant | AwaitingDrop => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Removes a key/value pair from the store, returning the value if the key was present in the
+    /// store and `None` otherwise.
+    /// # Panics
+    /// Will panic if the value is currently loaned.
+    pub fn take(&mut self, key: usize) -> Option<V> {
+        match self.store.get_mut(key) {
+            Some(slot) => match *slot {
+                Present(_) => match ::std::mem::replace(slot, Vacant) {
+                    Present(v) => Some(v),
+                    _ => unreachable!(),
+                },
+                Loaned => _panic(&self.name, "Cannot take loaned value"),
+                Vacant | AwaitingDrop => None,
+            },
+            None => None,
+        }
+    }
+
+    /// Loans a value from the library, returning `Some(Loan<V>)` if the value is present, and
+    /// `None` if it is not.
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan.
+    pub fn lend(&mut self, key: usize) -> Option<Loan<V>> {
+        let ptr: *mut Self = self;
+        let slot = self.store.get_mut(key)?;
+        let val = match *slot {
+            Present(_) => match ::std::mem::replace(slot, Loaned) {
+                Present(v) => v,
+                _ => unreachable!(),
+            },
+            Vacant => return None,
+            Loaned => _panic(&self.name, "Lending already loaned value"),
+            AwaitingDrop => _panic(&self.name, "Lending value awaiting drop"),
+        };
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            key,
+            inner: Some(val),
+        })
+    }
+
+    /// An iterator visiting all key/value pairs in key order.
+    /// # Panics
+    /// The iterator will panic if it encounters an item that is currently loaned from the store,
+    /// so this should only be used where you are sure you have returned all loaned items.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &V)> {
+        let name = &self.name;
+        self.store.iter().enumerate().filter_map(move |(k, v)| match *v {
+            Present(ref v) => Some((k, v)),
+            Loaned => _panic(name, "Trying to iterate over a store with loaned items."),
+            Vacant | AwaitingDrop => None,
+        })
+    }
+
+    /// An iterator visiting all keys in order, including those whose values are currently loaned.
+    pub fn keys(&self) -> impl Iterator<Item = usize> + '_ {
+        self.store.iter().enumerate().filter_map(|(k, v)| match *v {
+            Present(_) | Loaned => Some(k),
+            Vacant | AwaitingDrop => None,
+        })
+    }
+
+    fn checkin(&mut self, key: usize, val: V) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        match self.store.get_mut(key) {
+            Some(slot) => match *slot {
+                Present(_) => _panic(&self.name, "Returning replaced item"),
+                Loaned => *slot = Present(val),
+                AwaitingDrop => *slot = Vacant,
+                Vacant => _panic(&self.name, "Returning item not from store"),
+            },
+            None => _panic(&self.name, "Returning item not from store"),
+        }
+    }
+}
+
+impl<V> Drop for LendingSlab<V> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let count = self.outstanding.load(Ordering::SeqCst);
+            if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.", count))
+            }
+        }
+    }
+}
+
+impl<V> Default for LendingSlab<V> {
+    fn default() -> Self {
+        LendingSlab::new()
+    }
+}
+
+impl<V> FromIterator<(usize, V)> for LendingSlab<V> {
+    fn from_iter<I: IntoIterator<Item = (usize, V)>>(iter: I) -> Self {
+        let mut lib = LendingSlab::new();
+        for (k, v) in iter {
+            lib.insert(k, v);
+        }
+        lib
+    }
+}
+
+impl<V> Debug for Loan<V>
+where
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<V> PartialEq for Loan<V>
+where
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<V> Drop for Loan<V> {
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            unsafe {
+                (*self.owner).checkin(self.key, self.inner.take().unwrap());
+            }
+        }
+    }
+}
+
+impl<V> Deref for Loan<V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<V> DerefMut for Loan<V> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.inner.as_mut().unwrap()
+    }
+}
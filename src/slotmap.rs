@@ -0,0 +1,482 @@
+/* Notice
+slotmap.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A variant of `LendingLibrary` that allocates generational keys for its values.
+
+use super::_panic;
+use std::{fmt::{Debug, Error as FmtError, Formatter},
+          marker::PhantomData,
+          ops::{Deref, DerefMut},
+          sync::atomic::{AtomicUsize, Ordering},
+          thread};
+
+/// The slot index and generation that make up a key for a `LendingSlotMap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyData {
+    idx: u32,
+    generation: u32,
+}
+
+/// A key issued by a `LendingSlotMap`.
+///
+/// Typed keys implementing this trait can be declared with the [`new_key_type!`] macro.
+///
+/// [`new_key_type!`]: ../macro.new_key_type.html
+pub trait Key: Copy + From<KeyData> {
+    /// Returns the slot index and generation held by this key.
+    fn data(&self) -> KeyData;
+}
+
+/// The key type used by a `LendingSlotMap` created with [`LendingSlotMap::new()`].
+///
+/// [`LendingSlotMap::new()`]: struct.LendingSlotMap.html#method.new
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefaultKey(KeyData);
+
+impl From<KeyData> for DefaultKey {
+    fn from(data: KeyData) -> Self {
+        DefaultKey(data)
+    }
+}
+
+impl Key for DefaultKey {
+    fn data(&self) -> KeyData {
+        self.0
+    }
+}
+
+/// Declares new key types for use with a `LendingSlotMap`, so that keys issued by different maps
+/// cannot be confused.
+/// # Example
+/// ```
+/// #[macro_use]
+/// extern crate lending_library;
+/// use lending_library::slotmap::LendingSlotMap;
+///
+/// new_key_type! {
+///     struct SessionKey;
+/// }
+///
+/// fn main() {
+///     let mut lib: LendingSlotMap<SessionKey, &str> = LendingSlotMap::with_key();
+///     let k: SessionKey = lib.insert("a");
+///     assert_eq!(*lib.lend(k).unwrap(), "a");
+/// }
+/// ```
+#[macro_export]
+macro_rules! new_key_type {
+    ($($(#[$attr:meta])* $vis:vis struct $name:ident;)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+            $vis struct $name($crate::slotmap::KeyData);
+
+            impl ::std::convert::From<$crate::slotmap::KeyData> for $name {
+                fn from(data: $crate::slotmap::KeyData) -> Self {
+                    $name(data)
+                }
+            }
+
+            impl $crate::slotmap::Key for $name {
+                fn data(&self) -> $crate::slotmap::KeyData {
+                    self.0
+                }
+            }
+        )*
+    };
+}
+
+enum Slot<V> {
+    Vacant,
+    Present(V),
+    Loaned,
+    AwaitingDrop,
+}
+
+use self::Slot::{AwaitingDrop, Loaned, Present, Vacant};
+
+struct Entry<V> {
+    generation: u32,
+    slot: Slot<V>,
+}
+
+impl<V> Entry<V> {
+    fn key<K: Key>(&self, idx: usize) -> K {
+        K::from(KeyData {
+            idx: idx as u32,
+            generation: self.generation,
+        })
+    }
+}
+
+/// A data store that lends temporary ownership of stored values, allocating a key for each value
+/// as it is inserted.
+///
+/// This has the same loan semantics as [`LendingLibrary`], but rather than being supplied by the
+/// caller, keys are issued by [`insert()`]. Each key records the generation of the slot it refers
+/// to, so a key to a removed value will not find a later value that reuses its slot. A slot that
+/// has been reused `u32::MAX` times is retired rather than reused again.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// [`insert()`]: #method.insert
+/// # Example
+/// ```
+/// use lending_library::slotmap::LendingSlotMap;
+/// let mut lib: LendingSlotMap<_, &str> = LendingSlotMap::new();
+/// let a = lib.insert("a");
+/// lib.remove(a);
+/// let b = lib.insert("b");
+/// assert!(lib.lend(a).is_none());
+/// assert_eq!(*lib.lend(b).unwrap(), "b");
+/// ```
+pub struct LendingSlotMap<K, V>
+where
+    K: Key,
+{
+    store: Vec<Entry<V>>,
+    free: Vec<u32>,
+    len: usize,
+    outstanding: AtomicUsize,
+    name: Option<String>,
+    _key: PhantomData<fn(K) -> K>,
+}
+
+/// A smart pointer representing the loan of a value from a `LendingSlotMap` instance.
+pub struct Loan<K, V>
+where
+    K: Key,
+{
+    owner: *mut LendingSlotMap<K, V>,
+    key: KeyData,
+    inner: Option<V>,
+}
+
+impl<V> LendingSlotMap<DefaultKey, V> {
+    /// Creates a new empty `LendingSlotMap` issuing `DefaultKey`s.
+    /// # Example
+    /// ```
+    /// use lending_library::slotmap::{DefaultKey, LendingSlotMap};
+    /// let mut lib: LendingSlotMap<DefaultKey, i32> = LendingSlotMap::new();
+    /// ```
+    pub fn new() -> LendingSlotMap<DefaultKey, V> {
+        LendingSlotMap::with_key()
+    }
+
+    /// Creates a new empty `LendingSlotMap` issuing `DefaultKey`s, with a name that is included in
+    /// any panic messages it raises.
+    /// # Example
+    /// ```
+    /// use lending_library::slotmap::LendingSlotMap;
+    /// let lib: LendingSlotMap<_, i32> = LendingSlotMap::named("handles");
+    /// assert_eq!(lib.name(), Some("handles"));
+    /// ```
+    pub fn named<N: Into<String>>(name: N) -> LendingSlotMap<DefaultKey, V> {
+        let mut lib = LendingSlotMap::new();
+        lib.name = Some(name.into());
+        lib
+    }
+}
+
+impl<K, V> LendingSlotMap<K, V>
+where
+    K: Key,
+{
+    /// Creates a new empty `LendingSlotMap` issuing keys of type `K`, such as those declared with
+    /// [`new_key_type!`].
+    ///
+    /// [`new_key_type!`]: ../macro.new_key_type.html
+    pub fn with_key() -> LendingSlotMap<K, V> {
+        LendingSlotMap::with_capacity_and_key(0)
+    }
+
+    /// Creates an empty `LendingSlotMap` issuing keys of type `K`, with room for at least
+    /// `capacity` values without reallocating.
+    pub fn with_capacity_and_key(capacity: usize) -> LendingSlotMap<K, V> {
+        LendingSlotMap {
+            store: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            len: 0,
+            outstanding: AtomicUsize::new(0),
+            name: None,
+            _key: PhantomData,
+        }
+    }
+
+    /// Creates a new empty `LendingSlotMap` issuing keys of type `K`, with a name that is included
+    /// in any panic messages it raises.
+    pub fn named_with_key<N: Into<String>>(name: N) -> LendingSlotMap<K, V> {
+        let mut lib = LendingSlotMap::with_key();
+        lib.name = Some(name.into());
+        lib
+    }
+
+    /// Returns the name given to the library by [`named()`] or [`named_with_key()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    /// [`named_with_key()`]: #method.named_with_key
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the number of items in the store, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the store is empty, including loaned items.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn entry(&self, key: K) -> Option<&Entry<V>> {
+        let data = key.data();
+        self.store
+            .get(data.idx as usize)
+            .filter(|e| e.generation == data.generation)
+    }
+
+    fn entry_mut(&mut self, key: K) -> Option<&mut Entry<V>> {
+        let data = key.data();
+        self.store
+            .get_mut(data.idx as usize)
+            .filter(|e| e.generation == data.generation)
+    }
+
+    /// Returns true if `key` refers to a value in the store, and false otherwise.
+    pub fn contains_key(&self, key: K) -> bool {
+        matches!(self.entry(key), Some(&Entry { slot: Present(_), .. })
+                                  | Some(&Entry { slot: Loaned, .. }))
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, returning `None` if the key is
+    /// stale or the value is currently loaned.
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        match self.entry_mut(key) {
+            Some(&mut Entry { slot: Present(ref mut v), .. }) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Inserts a value into the store, returning the key allocated for it.
+    /// # Panics
+    /// Will panic if the store already holds `u32::MAX` slots.
+    pub fn insert(&mut self, val: V) -> K {
+        self.len += 1;
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.store[idx as usize].slot = Present(val);
+                idx
+            }
+            None => {
+                if self.store.len() >= u32::MAX as usize {
+                    _panic(&self.name, "Slot map is full")
+                }
+                self.store.push(Entry {
+                    generation: 0,
+                    slot: Present(val),
+                });
+                (self.store.len() - 1) as u32
+            }
+        };
+        K::from(KeyData {
+            idx,
+            generation: self.store[idx as usize].generation,
+        })
+    }
+
+    /// Empties the slot at `idx` for reuse, unless its generation is exhausted, in which case the
+    /// slot is retired so that no stale key can match a later value.
+    fn vacate(&mut self, idx: u32) {
+        let e = &mut self.store[idx as usize];
+        e.slot = Vacant;
+        if e.generation < u32::MAX {
+            e.generation += 1;
+            self.free.push(idx);
+        }
+    }
+
+    /// Removes a value from the store. Returning true if the key referred to a value in the store
+    /// and false otherwise. A loaned value is dropped when its loan is returned, and its slot is
+    /// not reused until then.
+    pub fn remove(&mut self, key: K) -> bool {
+        let present = match self.entry_mut(key) {
+            Some(e) => match e.slot {
+                Present(_) => true,
+                Loaned => {
+                    e.slot = AwaitingDrop;
+                    false
+                }
+                Vacant | AwaitingDrop => return false,
+            },
+            None => return false,
+        };
+        self.len -= 1;
+        if present {
+            self.vacate(key.data().idx);
+        }
+        true
+    }
+
+    /// Removes a value from the store, returning it if the key referred to a value in the store
+    /// and `None` otherwise.
+    /// # Panics
+    /// Will panic if the value is currently loaned.
+    pub fn take(&mut self, key: K) -> Option<V> {
+        let val = match self.entry_mut(key) {
+            Some(e) => match e.slot {
+                Present(_) => match ::std::mem::replace(&mut e.slot, Vacant) {
+                    Present(v) => v,
+                    _ => unreachable!(),
+                },
+                Loaned => _panic(&self.name, "Cannot take loaned value"),
+                Vacant | AwaitingDrop => return None,
+            },
+            None => return None,
+        };
+        self.len -= 1;
+        self.vacate(key.data().idx);
+        Some(val)
+    }
+
+    /// Loans a value from the library, returning `Some(Loan<K, V>)` if the key refers to a value
+    /// in the store, and `None` if it does not.
+    /// # Panics
+    /// Will panic if you try and loan a value that still has an outstanding loan.
+    pub fn lend(&mut self, key: K) -> Option<Loan<K, V>> {
+        let ptr: *mut Self = self;
+        let val = {
+            let e = self.entry_mut(key)?;
+            match e.slot {
+                Present(_) => match ::std::mem::replace(&mut e.slot, Loaned) {
+                    Present(v) => v,
+                    _ => unreachable!(),
+                },
+                Loaned => _panic(&self.name, "Lending already loaned value"),
+                Vacant | AwaitingDrop => return None,
+            }
+        };
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            key: key.data(),
+            inner: Some(val),
+        })
+    }
+
+    /// An iterator visiting all key/value pairs in slot order.
+    /// # Panics
+    /// The iterator will panic if it encounters an item that is currently loaned from the store,
+    /// so this should only be used where you are sure you have returned all loaned items.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &V)> {
+        let name = &self.name;
+        self.store.iter().enumerate().filter_map(move |(idx, e)| match e.slot {
+            Present(ref v) => Some((e.key(idx), v)),
+            Loaned => _panic(name, "Trying to iterate over a store with loaned items."),
+            Vacant | AwaitingDrop => None,
+        })
+    }
+
+    /// An iterator visiting all keys in slot order, including those whose values are currently
+    /// loaned.
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.store.iter().enumerate().filter_map(|(idx, e)| match e.slot {
+            Present(_) | Loaned => Some(e.key(idx)),
+            Vacant | AwaitingDrop => None,
+        })
+    }
+
+    fn checkin(&mut self, key: KeyData, val: V) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        match self.store.get_mut(key.idx as usize) {
+            Some(e) if e.generation == key.generation => match e.slot {
+                Loaned => e.slot = Present(val),
+                AwaitingDrop => self.vacate(key.idx),
+                Present(_) => _panic(&self.name, "Returning replaced item"),
+                Vacant => _panic(&self.name, "Returning item not from store"),
+            },
+            _ => _panic(&self.name, "Returning item not from store"),
+        }
+    }
+}
+
+impl<K, V> Drop for LendingSlotMap<K, V>
+where
+    K: Key,
+{
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let count = self.outstanding.load(Ordering::SeqCst);
+            if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.", count))
+            }
+        }
+    }
+}
+
+impl<K, V> Default for LendingSlotMap<K, V>
+where
+    K: Key,
+{
+    fn default() -> Self {
+        LendingSlotMap::with_key()
+    }
+}
+
+impl<K, V> Debug for Loan<K, V>
+where
+    K: Key,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<K, V> PartialEq for Loan<K, V>
+where
+    K: Key,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<K, V> Drop for Loan<K, V>
+where
+    K: Key,
+{
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            unsafe {
+                (*self.owner).checkin(self.key, self.inner.take().unwrap());
+            }
+        }
+    }
+}
+
+impl<K, V> Deref for Loan<K, V>
+where
+    K: Key,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<K, V> DerefMut for Loan<K, V>
+where
+    K: Key,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.inner.as_mut().unwrap()
+    }
+}
@@ -7,8 +7,28 @@ This file is part of the lending-library open-source project: github.com/harkone
 Its licensing is governed by the LICENSE file at the root of the project.
 */
 
-use super::{LendingLibrary, Loan};
-use std::sync::atomic::Ordering;
+use super::{array::LendingArray,
+            bimap::LendingBiMap,
+            btree::LendingBTree,
+            multimap::LendingMultiMap,
+            set::LendingSet,
+            slab::LendingSlab,
+            slotmap::{DefaultKey, Key, LendingSlotMap},
+            vec::LendingVec,
+            checked::{Error as CheckedError, ErrorKind as CheckedErrorKind},
+            entry::{Entry, RawEntryMut},
+            InsertErrorKind, LendErrorKind, LendingLibrary, Loan, MappedLoan, ReadLoan,
+            RenameErrorKind, SendLoan};
+use std::{borrow::BorrowMut,
+          collections::{hash_map::DefaultHasher, HashMap, HashSet},
+          convert::TryFrom,
+          hash::{BuildHasher, BuildHasherDefault, Hasher},
+          panic::{self, AssertUnwindSafe},
+          sync::{atomic::Ordering,
+                 Arc,
+                 Mutex},
+          thread,
+          time::Duration};
 
 #[test]
 fn basic_use() {
@@ -73,6 +93,11 @@ fn capacity() {
     assert!(s.capacity() >= 10);
     s.shrink_to_fit();
     assert_eq!(s.capacity(), 0);
+    s = LendingLibrary::with_capacity(100);
+    s.shrink_to(20);
+    assert!(s.capacity() >= 20 && s.capacity() < 100);
+    s.shrink_to(0);
+    assert_eq!(s.capacity(), 0);
 }
 
 #[test]
@@ -117,8 +142,11 @@ fn double_reinsert() {
         let _v = s.lend(&1);
         let _v2 = Loan {
             owner: &mut s as *mut LendingLibrary<i64, String>,
+            alive: Arc::downgrade(&s.alive),
             key: _v.unwrap().key,
+            generation: 0,
             inner: Some(String::from("test")),
+            dirty: false,
         };
     }
 }
@@ -130,12 +158,35 @@ fn returning_none_store() {
     {
         let _v = Loan {
             owner: &mut s as *mut LendingLibrary<i64, String>,
+            alive: Arc::downgrade(&s.alive),
             key: 0,
+            generation: 0,
             inner: Some(String::from("boo")),
+            dirty: false,
         };
     }
 }
 
+#[test]
+#[should_panic(expected = "Returning loan from a stale generation")]
+fn stale_generation() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("test"));
+    let v = s.lend(&1).unwrap();
+    let stale = Loan {
+        owner: &mut s as *mut LendingLibrary<i64, String>,
+        alive: Arc::downgrade(&s.alive),
+        key: v.key,
+        generation: v.generation - 1,
+        inner: Some(String::from("stale")),
+        dirty: false,
+    };
+    Loan::discard(v);
+    s.insert(1, String::from("fresh"));
+    let _fresh = s.lend(&1).unwrap();
+    drop(stale);
+}
+
 #[test]
 #[should_panic(expected = "Lending already loaned value")]
 fn double_checkout() {
@@ -216,3 +267,2411 @@ fn no_iter_mut_loaned() {
         println!("a");
     }
 }
+
+#[test]
+fn errors_named() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::named("sessions");
+    s.insert(1, 1);
+    let _v = s.lend(&1).unwrap();
+    assert_eq!(
+        s.try_insert(1, 2).unwrap_err().to_string(),
+        "Library \"sessions\": Cannot overwrite loaned value"
+    );
+    assert_eq!(
+        s.try_iter().err().unwrap().to_string(),
+        "Library \"sessions\": 1 values are still on loan: [1]"
+    );
+    assert_eq!(
+        s.try_lend(&1).unwrap_err().to_string(),
+        "Library \"sessions\": Lending already loaned value"
+    );
+    assert_eq!(
+        s.checked().take(&1).unwrap_err().to_string(),
+        "Library \"sessions\": Value is currently loaned"
+    );
+    assert_eq!(
+        s.checked().lend(&2).unwrap_err().to_string(),
+        "Library \"sessions\": Key not present in library"
+    );
+    let mut t: LendingLibrary<i64, i64> = LendingLibrary::new();
+    t.insert(1, 1);
+    let _w = t.lend(&1).unwrap();
+    assert_eq!(t.try_insert(1, 2).unwrap_err().to_string(), "Cannot overwrite loaned value");
+    assert_eq!(t.checked().get(&2).unwrap_err().to_string(), "Key not present in library");
+}
+
+#[test]
+#[should_panic(expected = "Library \"sessions\": 1 value loans outlived store.")]
+fn failure_to_return_named() {
+    {
+        let mut s: LendingLibrary<i64, String> = LendingLibrary::named("sessions");
+        s.insert(1, String::from("test"));
+        let _v = s.lend(&1).unwrap();
+        drop(s);
+    }
+}
+
+#[test]
+fn get_mut() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    assert_eq!(s.get_mut(&1), None);
+    s.insert(1, String::from("test"));
+    s.get_mut(&1).unwrap().push_str("-more");
+    {
+        let v = s.lend(&1).unwrap();
+        assert_eq!(*v, "test-more");
+        assert_eq!(s.get_mut(&1), None);
+        s.remove(&1);
+        assert_eq!(s.get_mut(&1), None);
+    }
+    assert_eq!(s.get_mut(&1), None);
+}
+
+#[test]
+fn get_key_value() {
+    let mut s: LendingLibrary<String, i64> = LendingLibrary::new();
+    assert_eq!(s.get_key_value("a"), None);
+    s.insert(String::from("a"), 1);
+    let (k, v) = s.get_key_value("a").unwrap();
+    assert_eq!((k.as_str(), *v), ("a", 1));
+    {
+        let _v = s.lend("a").unwrap();
+        assert_eq!(s.get_key_value("a"), None);
+        s.remove("a");
+    }
+    assert_eq!(s.get_key_value("a"), None);
+}
+
+#[test]
+fn raw_escrow() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("test"));
+    let (ptr, token) = Loan::into_raw(s.lend(&1).unwrap());
+    assert_eq!(s.raw_escrows(), 1);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 1);
+    unsafe { (*ptr).push_str("-raw") };
+    {
+        let v = unsafe { Loan::from_raw(token, ptr) };
+        assert_eq!(s.raw_escrows(), 0);
+        assert_eq!(*v, "test-raw");
+    }
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(*s.lend(&1).unwrap(), "test-raw");
+}
+
+#[test]
+#[should_panic(expected = "1 value loans outlived store. (1 held in raw escrow)")]
+fn raw_escrow_leak() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("test"));
+    let _escrow = Loan::into_raw(s.lend(&1).unwrap());
+    drop(s);
+}
+
+#[test]
+#[should_panic(expected = "Raw pointer does not match escrow token")]
+fn raw_escrow_mismatch() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("test"));
+    s.insert(2, String::from("test"));
+    let (_ptr_a, token_a) = Loan::into_raw(s.lend(&1).unwrap());
+    let (ptr_b, _token_b) = Loan::into_raw(s.lend(&2).unwrap());
+    let _v = unsafe { Loan::from_raw(token_a, ptr_b) };
+}
+
+#[test]
+fn explicit_checkin() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let v = s.lend(&1).unwrap();
+    Loan::checkin(v);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    let v = s.lend(&1).unwrap();
+    Loan::checkin_with(v, |v| *v += 1);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(s[&1], 2);
+}
+
+#[test]
+fn into_inner() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("a"));
+    s.insert(2, String::from("b"));
+    let a = s.lend(&1).unwrap();
+    let b = s.lend(&2).unwrap();
+    s.remove(&2);
+    assert_eq!(Loan::into_inner(a), "a");
+    assert_eq!(Loan::forget(b), "b");
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert!(s.is_empty());
+    s.insert(1, String::from("c"));
+    assert_eq!(s[&1], "c");
+}
+
+#[test]
+fn discard() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    {
+        let v = s.lend(&1).unwrap();
+        assert!(s.contains_key(&1));
+        Loan::discard(v);
+        assert!(!s.contains_key(&1));
+    }
+    assert_eq!(s.len(), 2);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn shared_loans() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.set_normalizer(|v: String| v.to_uppercase());
+    s.insert(1, String::from("a"));
+    s.insert(2, String::from("b"));
+    {
+        let r1 = s.lend_shared(&1).unwrap();
+        let r2 = s.lend_shared(&1).unwrap();
+        assert_eq!(r1, r2);
+        assert_eq!(format!("{:?}", r1), "\"a\"");
+        assert!(s.contains_key(&1));
+        assert_eq!(s.get_mut(&1), None);
+        assert_eq!(s.outstanding.load(Ordering::SeqCst), 2);
+        drop(r1);
+        assert_eq!(s.get_mut(&1), None);
+        let _r3 = s.lend_shared(&2).unwrap();
+        s.remove(&2);
+        assert!(s.lend_shared(&3).is_none());
+    }
+    assert_eq!(s[&1], "A");
+    assert!(!s.contains_key(&2));
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn downgrade() {
+    let mut s: LendingLibrary<i64, Vec<i64>> = LendingLibrary::new();
+    s.insert(1, vec![]);
+    {
+        let mut w = s.lend(&1).unwrap();
+        w.push(1);
+        let r1 = Loan::downgrade(w);
+        let r2 = s.lend_shared(&1).unwrap();
+        assert_eq!(*r1, vec![1]);
+        assert_eq!(r1, r2);
+        assert_eq!(s.outstanding.load(Ordering::SeqCst), 2);
+    }
+    s.get_mut(&1).unwrap().push(2);
+    {
+        let w = s.lend(&1).unwrap();
+        s.remove(&1);
+        let _r = Loan::downgrade(w);
+    }
+    assert!(!s.contains_key(&1));
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn upgrade() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    {
+        let r1 = s.lend_shared(&1).unwrap();
+        let r2 = s.lend_shared(&1).unwrap();
+        let r2 = ReadLoan::upgrade(r2).unwrap_err();
+        drop(r1);
+        let mut w = ReadLoan::upgrade(r2).unwrap();
+        *w += 1;
+        assert_eq!(s.outstanding.load(Ordering::SeqCst), 1);
+        let r = Loan::downgrade(w);
+        assert_eq!(*r, 2);
+    }
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(s[&1], 2);
+}
+
+#[test]
+#[should_panic(expected = "Lending already loaned value")]
+fn shared_then_exclusive() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let _r = s.lend_shared(&1);
+    let _w = s.lend(&1);
+}
+
+#[test]
+#[should_panic(expected = "Lending already loaned value")]
+fn exclusive_then_shared() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let _w = s.lend(&1);
+    let _r = s.lend_shared(&1);
+}
+
+#[test]
+fn mapped_loans() {
+    let mut s: LendingLibrary<i64, (String, Vec<i64>)> = LendingLibrary::new();
+    s.insert(1, (String::from("a"), vec![]));
+    {
+        let mut v = Loan::map(s.lend(&1).unwrap(), |p| &mut p.1);
+        v.push(1);
+        let mut first = MappedLoan::map(v, |v| &mut v[0]);
+        *first += 1;
+        assert_eq!(format!("{:?}", first), "2");
+        assert!(s.contains_key(&1));
+        assert_eq!(s.get_mut(&1), None);
+    }
+    {
+        let mut name = Loan::map(s.lend(&1).unwrap(), |p| p.0.as_mut_str());
+        name.make_ascii_uppercase();
+    }
+    assert_eq!(s[&1], (String::from("A"), vec![2]));
+}
+
+#[test]
+fn try_mapped_loans() {
+    let mut s: LendingLibrary<i64, Option<Vec<i64>>> = LendingLibrary::new();
+    s.insert(1, None);
+    s.insert(2, Some(vec![]));
+    {
+        let v = Loan::try_map(s.lend(&1).unwrap(), Option::as_mut).unwrap_err();
+        assert_eq!(*v, None);
+        let v = Loan::try_map(s.lend(&2).unwrap(), Option::as_mut).unwrap();
+        let mut v = MappedLoan::try_map(v, |v| v.first_mut()).unwrap_err();
+        v.push(1);
+        let mut first = MappedLoan::try_map(v, |v| v.first_mut()).unwrap();
+        *first += 1;
+    }
+    assert_eq!(s[&1], None);
+    assert_eq!(s[&2], Some(vec![2]));
+}
+
+#[test]
+fn entries() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    *s.entry(1).or_insert(1) += 1;
+    *s.entry(1).or_insert_with(|| 10) += 1;
+    s.entry(2).and_modify(|v| *v = 10).or_insert(5);
+    s.entry(2).and_modify(|v| *v += 1);
+    assert_eq!(s.get_mut(&1), Some(&mut 3));
+    assert_eq!(s.get_mut(&2), Some(&mut 6));
+    {
+        let v = match s.entry(3) {
+            Entry::Occupied(_) => unreachable!(),
+            Entry::Vacant(e) => {
+                assert_eq!(*e.key(), 3);
+                assert!(!e.is_awaiting_drop());
+                e.insert_and_lend(7)
+            }
+        };
+        assert_eq!(*v, 7);
+        match s.entry(3) {
+            Entry::Occupied(mut e) => {
+                assert!(e.is_loaned());
+                assert_eq!(e.get(), None);
+                assert_eq!(e.get_mut(), None);
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        s.remove(&3);
+        match s.entry(3) {
+            Entry::Occupied(_) => unreachable!(),
+            Entry::Vacant(e) => assert!(e.is_awaiting_drop()),
+        }
+    }
+    match s.entry(1) {
+        Entry::Occupied(mut e) => {
+            assert_eq!(e.insert(4), 3);
+            assert_eq!(*e.lend(), 4);
+        }
+        Entry::Vacant(_) => unreachable!(),
+    }
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn raw_entries() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let h = s.hasher().hash_one(1i64);
+    {
+        let v = match s.raw_entry_mut().from_hash(h) {
+            RawEntryMut::Occupied(_) => unreachable!(),
+            RawEntryMut::Vacant(e) => e.insert_and_lend(1, 1),
+        };
+        assert_eq!(*v, 1);
+        match s.raw_entry_mut().from_hash(h) {
+            RawEntryMut::Occupied(e) => assert!(e.is_loaned()),
+            RawEntryMut::Vacant(_) => unreachable!(),
+        }
+        s.remove(&1);
+        match s.raw_entry_mut().from_hash(h) {
+            RawEntryMut::Occupied(_) => unreachable!(),
+            RawEntryMut::Vacant(e) => assert!(e.is_awaiting_drop()),
+        }
+    }
+    match s.raw_entry_mut().from_hash(h) {
+        RawEntryMut::Occupied(_) => unreachable!(),
+        RawEntryMut::Vacant(e) => *e.insert(1, 2) += 1,
+    }
+    assert_eq!(s[&1], 3);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn entry_insert_bookkeeping() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.set_weigher(|_k, v: &String| v.len());
+    s.set_max_weight(Some(4));
+    s.insert(1, String::from("a"));
+    s.insert(2, String::from("b"));
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&2).unwrap();
+        panic!("borrower failed");
+    }));
+    assert!(s.is_poisoned(&2));
+    match s.entry(2) {
+        Entry::Occupied(mut e) => assert_eq!(e.insert(String::from("cdef")), "b"),
+        Entry::Vacant(_) => unreachable!(),
+    }
+    assert!(!s.is_poisoned(&2));
+    assert_eq!(s.total_weight(), 4);
+    assert!(!s.contains_key(&1));
+    assert_eq!(*s.lend(&2).unwrap(), "cdef");
+}
+
+#[test]
+#[should_panic(expected = "Cannot modify loaned value")]
+fn entry_modify_loaned() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let _v = s.lend(&1);
+    s.entry(1).and_modify(|v| *v += 1);
+}
+
+#[test]
+#[should_panic(expected = "Cannot overwrite value awaiting drop")]
+fn entry_insert_awaiting_drop() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let _v = s.lend(&1);
+    s.remove(&1);
+    s.entry(1).or_insert(2);
+}
+
+#[test]
+fn defaults() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    *s.get_or_default(1) += 1;
+    {
+        let mut v = s.lend_or_default(1);
+        assert_eq!(*v, 1);
+        *v += 1;
+        let w = s.lend_or_default(2);
+        assert_eq!(*w, 0);
+    }
+    assert_eq!(*s.get_or_default(1), 2);
+    assert_eq!(s.len(), 2);
+}
+
+#[test]
+fn keys() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.insert(2, 1);
+    s.insert(3, 1);
+    let _a = s.lend(&1);
+    let _b = s.lend(&2);
+    s.remove(&2);
+    let mut keys: Vec<_> = s.keys().cloned().collect();
+    keys.sort();
+    assert_eq!(keys, vec![1, 3]);
+    assert_eq!(s.key_set(), [1, 3].iter().collect::<HashSet<_>>());
+}
+
+#[test]
+fn values() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.insert(2, 2);
+    s.insert(3, 3);
+    let _a = s.lend(&1);
+    let _b = s.lend(&2);
+    s.remove(&2);
+    assert_eq!(s.values().collect::<Vec<_>>(), vec![&3]);
+    for v in s.values_mut() {
+        *v += 1;
+    }
+    assert_eq!(s.values().collect::<Vec<_>>(), vec![&4]);
+}
+
+#[test]
+fn drain() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.insert(2, 2);
+    {
+        let _v = s.lend(&1);
+    }
+    let mut pairs: Vec<_> = s.drain().collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![(1, 1), (2, 2)]);
+    assert!(s.is_empty());
+    assert_eq!(s.lend(&1), None);
+}
+
+#[test]
+#[should_panic(expected = "Cannot drain a store with loaned items: [1]")]
+fn drain_loaned() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.insert(2, 2);
+    let _v = s.lend(&1);
+    for _ in s.drain() {}
+}
+
+#[test]
+fn retain() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    for i in 0..10 {
+        s.insert(i, i);
+    }
+    {
+        let _a = s.lend(&1);
+        let _b = s.lend(&3);
+        s.remove(&3);
+        s.retain(|k, v| {
+            *v += 1;
+            k % 2 == 0
+        });
+        assert_eq!(s.len(), 6);
+    }
+    assert!(s.contains_key(&1));
+    assert!(!s.contains_key(&3));
+    assert_eq!(*s.lend(&1).unwrap(), 1);
+    assert_eq!(*s.lend(&2).unwrap(), 3);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn extract_if() {
+    let mut s: LendingLibrary<i64, i64> = (0..10).map(|i| (i, i)).collect();
+    let limit = 5;
+    {
+        let _a = s.lend(&2);
+        let mut out: Vec<_> = s
+            .extract_if(|k, v| {
+                *v += 1;
+                *k < limit
+            })
+            .collect();
+        out.sort();
+        assert_eq!(out, vec![(0, 1), (1, 2), (3, 4), (4, 5)]);
+        assert_eq!(s.len(), 6);
+    }
+    assert_eq!(*s.lend(&2).unwrap(), 2);
+    assert_eq!(s[&7], 8);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn key_sets() {
+    let mut s: LendingLibrary<i64, i64> = (0..10).map(|i| (i, i)).collect();
+    {
+        let _a = s.lend(&1);
+        let keep: HashSet<i64> = (4..12).collect();
+        s.retain_keys(&keep);
+        assert_eq!(s.len(), 7);
+        assert_eq!(s.remove_keys(&[1, 4, 5, 20]), 3);
+        assert_eq!(s.len(), 4);
+    }
+    assert!(!s.contains_key(&1));
+    assert_eq!(s.missing_from(0..12), vec![0, 1, 2, 3, 4, 5, 10, 11]);
+}
+
+#[test]
+fn lend_many() {
+    let mut s: LendingLibrary<String, i64> = (0..4).map(|i| (i.to_string(), i)).collect();
+    {
+        let mut loans = s.lend_many(vec!["1", "2", "3"]).unwrap();
+        assert_eq!(s.outstanding.load(Ordering::SeqCst), 3);
+        for v in loans.iter_mut() {
+            **v *= 10;
+        }
+        assert!(s.lend_many(vec!["0", "4"]).is_none());
+        assert!(s.lend_many(vec!["0", "0"]).is_none());
+        assert!(s.contains_key("0"));
+    }
+    assert_eq!(s["3"], 30);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(s.lend_many(Vec::<&str>::new()).map(|l| l.len()), Some(0));
+}
+
+#[test]
+#[should_panic(expected = "Lending already loaned value")]
+fn lend_many_loaned() {
+    let mut s: LendingLibrary<i64, i64> = (0..4).map(|i| (i, i)).collect();
+    let _v = s.lend(&2).unwrap();
+    s.lend_many(&[1, 2]);
+}
+
+#[test]
+fn lend_many_poisoned() {
+    let mut s: LendingLibrary<i64, i64> = (0..4).map(|i| (i, i)).collect();
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&2).unwrap();
+        panic!("borrower failed");
+    }));
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        s.lend_many(&[1, 2]);
+    }));
+    assert!(r.is_err());
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert!(!s.is_poisoned(&1));
+    assert!(s.is_poisoned(&2));
+}
+
+#[test]
+fn conditional_lend() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    assert_eq!(s.lend_if_eq(&1, &1), None);
+    s.insert(1, 1);
+    assert_eq!(s.lend_if_eq(&1, &2), None);
+    assert_eq!(s.lend_if(&1, |v| *v > 1), None);
+    {
+        let mut v = s.lend_if_eq(&1, &1).unwrap();
+        *v = 2;
+    }
+    assert_eq!(s.lend_if_eq(&1, &1), None);
+    assert_eq!(*s.lend_if(&1, |v| *v > 1).unwrap(), 2);
+}
+
+#[test]
+#[should_panic(expected = "Lending already loaned value")]
+fn conditional_lend_loaned() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let _a = s.lend(&1);
+    let _b = s.lend_if_eq(&1, &1);
+}
+
+#[test]
+fn into_iter() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.insert(2, 2);
+    {
+        let _v = s.lend(&1);
+    }
+    let mut pairs: Vec<_> = s.into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![(1, 1), (2, 2)]);
+}
+
+#[test]
+#[should_panic(expected = "1 value loans outlived store.")]
+fn into_iter_loaned() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let _v = s.lend(&1);
+    for _ in s {}
+}
+
+#[test]
+fn extend() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 0);
+    s.extend(vec![(1, 1), (2, 2)]);
+    s.extend(vec![(&3, &3)]);
+    assert_eq!(s.len(), 3);
+    assert_eq!(*s.lend(&1).unwrap(), 1);
+    assert_eq!(*s.lend(&3).unwrap(), 3);
+}
+
+#[test]
+#[should_panic(expected = "Cannot overwrite loaned value")]
+fn extend_loaned() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 0);
+    let _v = s.lend(&1);
+    s.extend(vec![(1, 1)]);
+}
+
+#[test]
+fn from_iter() {
+    let mut s: LendingLibrary<i64, i64> = (0..10).map(|i| (i, i * 2)).collect();
+    assert_eq!(s.len(), 10);
+    assert_eq!(*s.lend(&4).unwrap(), 8);
+}
+
+#[test]
+fn from_hashmap() {
+    let mut map = HashMap::new();
+    map.insert(1, String::from("one"));
+    map.insert(2, String::from("two"));
+    let mut s = LendingLibrary::from(map);
+    assert_eq!(s.len(), 2);
+    assert_eq!(*s.lend(&2).unwrap(), "two");
+}
+
+#[test]
+fn vec_conversions() {
+    let mut s = LendingLibrary::from(vec![(3, "c"), (1, "a"), (2, "b"), (1, "z")]);
+    assert_eq!(s.len(), 3);
+    {
+        let mut v = s.lend(&1).unwrap();
+        *v = "a";
+    }
+    let mut unsorted = s.clone().into_vec();
+    unsorted.sort();
+    assert_eq!(unsorted, s.into_sorted_vec());
+}
+
+#[test]
+#[should_panic(expected = "1 value loans outlived store.")]
+fn into_vec_loaned() {
+    let mut s = LendingLibrary::from(vec![(1, 1)]);
+    let _v = s.lend(&1);
+    s.into_vec();
+}
+
+#[test]
+fn try_into_hashmap() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.insert(2, 2);
+    s.insert(3, 3);
+    {
+        let _a = s.lend(&1);
+        let _b = s.lend(&2);
+        s.remove(&2);
+        let err = HashMap::try_from(&mut s).unwrap_err();
+        let mut keys = err.keys().to_vec();
+        keys.sort();
+        assert_eq!(keys, vec![&1, &2]);
+    }
+    assert_eq!(s.len(), 2);
+    let map = HashMap::try_from(&mut s).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&3], 3);
+    assert!(s.is_empty());
+}
+
+#[test]
+fn take() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    assert_eq!(s.take(&1), None);
+    s.insert(1, String::from("test"));
+    s.insert(2, String::from("test"));
+    assert_eq!(s.take(&1), Some(String::from("test")));
+    assert!(!s.contains_key(&1));
+    {
+        let _v = s.lend(&2);
+        s.remove(&2);
+        assert_eq!(s.take(&2), None);
+    }
+    assert!(s.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Cannot take loaned value")]
+fn take_loaned() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("test"));
+    let _v = s.lend(&1);
+    s.take(&1);
+}
+
+#[test]
+fn lazy_insert() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let mut calls = 0;
+    *s.get_or_insert_with(1, || {
+        calls += 1;
+        1
+    }) += 1;
+    *s.get_or_insert_with(1, || {
+        calls += 1;
+        1
+    }) += 1;
+    assert_eq!(calls, 1);
+    {
+        let v = s.lend_or_insert_with(1, || unreachable!());
+        assert_eq!(*v, 3);
+        let w = s.lend_or_insert_with(2, || 7);
+        assert_eq!(*w, 7);
+    }
+    assert_eq!(s.len(), 2);
+}
+
+#[test]
+fn try_insert() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    assert_eq!(s.try_insert(1, 1).unwrap(), None);
+    assert_eq!(s.try_insert(1, 2).unwrap(), Some(1));
+    {
+        let v = s.lend(&1).unwrap();
+        let err = s.try_insert(1, 3).unwrap_err();
+        assert_eq!(err.kind(), InsertErrorKind::Loaned);
+        assert_eq!(format!("{}", err), "Cannot overwrite loaned value");
+        s.remove(&1);
+        let err = s.try_insert(1, 4).unwrap_err();
+        assert_eq!(err.kind(), InsertErrorKind::AwaitingDrop);
+        assert_eq!(err.into_inner(), (1, 4));
+        assert_eq!(*v, 2);
+    }
+    assert_eq!(s.try_insert(1, 5).unwrap(), None);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn insert_many() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    assert!(s.insert_many((0..5).map(|i| (i, i))).is_empty());
+    let mut replaced = s.insert_many((3..8).map(|i| (i, i * 10)));
+    replaced.sort();
+    assert_eq!(replaced, vec![(3, 3), (4, 4)]);
+    assert_eq!(s.len(), 8);
+    assert_eq!(s[&4], 40);
+}
+
+#[test]
+#[should_panic(expected = "Cannot overwrite loaned value")]
+fn insert_many_loaned() {
+    let mut s: LendingLibrary<i64, i64> = (0..5).map(|i| (i, i)).collect();
+    let _v = s.lend(&3);
+    s.insert_many(vec![(5, 5), (3, 30)]);
+}
+
+#[test]
+fn swap_and_rename_keys() {
+    let mut s: LendingLibrary<String, i64> = (0..4).map(|i| (i.to_string(), i)).collect();
+    assert!(s.swap_keys("0", "1").unwrap());
+    assert_eq!((s["0"], s["1"]), (1, 0));
+    assert!(s.swap_keys("2", "2").unwrap());
+    assert!(!s.swap_keys("2", "9").unwrap());
+    assert_eq!(s["2"], 2);
+    assert!(s.rename_key("2", String::from("9")).unwrap());
+    assert!(!s.contains_key("2"));
+    let err = s.rename_key("9", String::from("3")).unwrap_err();
+    assert_eq!((err.kind(), err.key().as_str()), (RenameErrorKind::Occupied, "3"));
+    assert_eq!(s["9"], 2);
+    s.remove("3");
+    assert!(s.rename_key("9", String::from("3")).unwrap());
+    assert_eq!((s["3"], s.len()), (2, 3));
+    {
+        let _v = s.lend("3").unwrap();
+        assert_eq!(s.swap_keys("3", "3").unwrap_err().keys(), &[&String::from("3")]);
+        let err = s.rename_key("0", String::from("3")).unwrap_err();
+        assert_eq!(err.kind(), RenameErrorKind::Loaned);
+        s.remove("3");
+        assert!(s.swap_keys("0", "3").is_err());
+        assert_eq!(s["0"], 1);
+    }
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn insert_if_vacant() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    assert_eq!(s.insert_if_vacant(1, 1), Ok(()));
+    assert_eq!(s.insert_if_vacant(1, 2), Err((1, 2)));
+    {
+        let v = s.lend(&1).unwrap();
+        assert_eq!(s.insert_if_vacant(1, 3), Err((1, 3)));
+        s.remove(&1);
+        assert_eq!(s.insert_if_vacant(1, 4), Err((1, 4)));
+        assert_eq!(*v, 1);
+    }
+    assert_eq!(s.insert_if_vacant(1, 5), Ok(()));
+    assert_eq!(*s.lend(&1).unwrap(), 5);
+}
+
+#[test]
+fn append() {
+    let mut a: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let mut b: LendingLibrary<i64, i64> = LendingLibrary::new();
+    a.insert(1, 1);
+    a.insert(2, 1);
+    b.insert(2, 2);
+    b.insert(3, 2);
+    b.insert(4, 2);
+    {
+        let _v = b.lend(&4);
+        a.append(&mut b);
+        assert_eq!(a.len(), 3);
+        assert_eq!(b.len(), 1);
+        assert!(b.contains_key(&4));
+    }
+    assert_eq!(*a.lend(&2).unwrap(), 2);
+    assert_eq!(*b.lend(&4).unwrap(), 2);
+}
+
+#[test]
+#[should_panic(expected = "Cannot overwrite loaned value")]
+fn append_loaned() {
+    let mut a: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let mut b: LendingLibrary<i64, i64> = LendingLibrary::new();
+    a.insert(1, 1);
+    b.insert(1, 2);
+    let _v = a.lend(&1);
+    a.append(&mut b);
+}
+
+#[test]
+fn split_off() {
+    let mut s: LendingLibrary<i64, i64> = (0..10).map(|i| (i, i)).collect();
+    {
+        let _v = s.lend(&1);
+        let mut evens = s.split_off(|k| k % 2 == 0).unwrap();
+        assert_eq!(s.len(), 5);
+        assert_eq!(evens.len(), 5);
+        assert_eq!(*evens.lend(&4).unwrap(), 4);
+        assert_eq!(s.lend(&4), None);
+        match s.split_off(|k| *k < 5) {
+            Ok(_) => unreachable!(),
+            Err(err) => assert_eq!(err.keys(), &[&1]),
+        }
+    }
+    assert_eq!(s.len(), 5);
+}
+
+#[test]
+fn index() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 5);
+    assert_eq!(s[&1], 5);
+}
+
+#[test]
+#[should_panic(expected = "Cannot index loaned value")]
+fn index_loaned() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 5);
+    let _v = s.lend(&1);
+    let _ = s[&1];
+}
+
+#[test]
+#[should_panic(expected = "Key not present in library")]
+fn index_absent() {
+    let s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let _ = s[&1];
+}
+
+#[test]
+fn clone() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::named("orig");
+    s.insert(1, String::from("one"));
+    s.insert(2, String::from("two"));
+    let mut c = {
+        let _v = s.lend(&2);
+        s.remove(&2);
+        s.clone()
+    };
+    assert_eq!(c.name(), Some("orig"));
+    assert_eq!(c.len(), 1);
+    c.get_mut(&1).unwrap().push('!');
+    assert_eq!(*s.lend(&1).unwrap(), "one");
+    assert_eq!(*c.lend(&1).unwrap(), "one!");
+}
+
+#[test]
+#[should_panic(expected = "Cannot clone a store with loaned items: [1]")]
+fn clone_loaned() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("one"));
+    let _v = s.lend(&1);
+    let _c = s.clone();
+}
+
+#[test]
+fn clone_keeps_poison() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.insert(2, 2);
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&1).unwrap();
+        panic!("borrower failed");
+    }));
+    assert!(r.is_err());
+    let c = s.clone();
+    assert!(c.is_poisoned(&1));
+    assert!(!c.is_poisoned(&2));
+}
+
+#[test]
+fn try_iter() {
+    let mut s: LendingLibrary<i64, i64> = (0..10).map(|i| (i, i)).collect();
+    {
+        let _v = s.lend(&9);
+        s.remove(&9);
+        let sum: i64 = s.try_iter().unwrap().map(|(_k, v)| v).sum();
+        assert_eq!(sum, 36);
+        let _w = s.lend(&2);
+        assert_eq!(s.try_iter().err().unwrap().keys(), &[&2]);
+    }
+    assert_eq!(s.try_iter().unwrap().count(), 9);
+}
+
+#[test]
+fn query() {
+    let mut s: LendingLibrary<i64, String> = (0..6).map(|i| (i, i.to_string())).collect();
+    let q: HashMap<i64, usize> = s.query()
+        .filter_keys(|k| k % 2 == 1)
+        .map_values(|v| v.len() * 10)
+        .map(|(k, n)| (*k, n))
+        .filter(|k, _v| *k != 5)
+        .collect()
+        .unwrap();
+    assert_eq!(q, vec![(1, 10), (3, 10)].into_iter().collect());
+    assert_eq!(s.query().fold(0, |n, (k, _v)| n + k).unwrap(), 15);
+    {
+        let _v = s.lend(&4);
+        let _w = s.lend(&5);
+        s.remove(&5);
+        let mut mapped = 0;
+        let err = s.query()
+            .map(|(_k, v)| {
+                mapped += 1;
+                v.clone()
+            })
+            .collect::<Vec<String>>()
+            .unwrap_err();
+        assert_eq!(err.keys(), &[&4]);
+        assert!(mapped < 5);
+    }
+    s.set_time_to_live(Some(Duration::from_millis(10)));
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(s.query().count().unwrap(), 0);
+}
+
+#[test]
+fn debug() {
+    let mut s: LendingLibrary<i64, &str> = LendingLibrary::new();
+    assert_eq!(format!("{:?}", s), "{}");
+    s.insert(1, "one");
+    assert_eq!(format!("{:?}", s), "{1: \"one\"}");
+    let _v = s.lend(&1);
+    assert_eq!(format!("{:?}", s), "{1: <loaned>}");
+    s.remove(&1);
+    assert_eq!(format!("{:?}", s), "{1: <awaiting drop>}");
+}
+
+#[test]
+fn normalizer() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.set_normalizer(|v: String| v.to_lowercase());
+    s.insert(1, String::from("MiXeD"));
+    assert_eq!(s[&1], "MiXeD");
+    {
+        let mut v = s.lend(&1).unwrap();
+        v.push_str("-MORE");
+    }
+    assert_eq!(s[&1], "mixed-more");
+    let c = s.clone();
+    s.clear_normalizer();
+    {
+        let mut v = s.lend(&1).unwrap();
+        v.push('X');
+    }
+    assert_eq!(s[&1], "mixed-moreX");
+    assert!(c.normalizer.is_some());
+}
+
+#[test]
+fn lru_eviction() {
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut s: LendingLibrary<i64, i64> = (0..4).map(|i| (i, i)).collect();
+    s.set_eviction_hook(move |k, v| sink.lock().unwrap().push((k, v)));
+    s.set_max_entries(Some(2));
+    assert_eq!(s.len(), 2);
+    assert_eq!(evicted.lock().unwrap().len(), 2);
+    evicted.lock().unwrap().clear();
+    let keys: Vec<i64> = s.keys().cloned().collect();
+    let (old, new) = (keys[0], keys[1]);
+    *s.get_mut(&old).unwrap() += 10;
+    s.insert(10, 10);
+    assert_eq!(*evicted.lock().unwrap(), vec![(new, new)]);
+    {
+        let _a = s.lend(&old).unwrap();
+        let _b = s.lend(&10).unwrap();
+        s.insert(11, 11);
+        assert_eq!(s.len(), 3);
+        assert!(s.contains_key(&11));
+    }
+    assert_eq!(s.len(), 2);
+    assert!(!s.contains_key(&11));
+    assert_eq!(s.max_entries(), Some(2));
+    let c = s.clone();
+    s.set_max_entries(None);
+    s.insert_many((20..25).map(|i| (i, i)));
+    assert_eq!(s.len(), 7);
+    assert_eq!(c.max_entries(), Some(2));
+}
+
+#[test]
+fn weight_budget() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.set_max_weight(Some(3));
+    s.insert_many((0..5).map(|i| (i, i.to_string())));
+    assert_eq!((s.len(), s.total_weight()), (3, 3));
+    s.set_weigher(|_k, v: &String| v.len());
+    s.clear();
+    s.insert(1, String::from("ab"));
+    s.insert(2, String::from("c"));
+    {
+        let mut v = s.lend(&2).unwrap();
+        v.push_str("de");
+        let err = s.try_insert(3, String::from("fgh")).unwrap_err();
+        assert_eq!(err.kind(), InsertErrorKind::OverWeight);
+        assert_eq!(s.try_insert(3, String::from("fg")).unwrap(), None);
+        assert!(!s.contains_key(&1));
+    }
+    assert_eq!(s.keys().collect::<Vec<_>>(), vec![&2]);
+    assert_eq!(s.total_weight(), 3);
+    s.set_max_weight(None);
+    assert_eq!(s.total_weight(), 0);
+    assert_eq!(s.max_weight(), None);
+}
+
+#[test]
+fn time_to_live() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    s.set_time_to_live(Some(Duration::from_millis(20)));
+    assert_eq!(s.time_to_live(), Some(Duration::from_millis(20)));
+    {
+        let _v = s.lend(&0).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        assert!(s.contains_key(&0));
+        assert!(!s.contains_key(&1));
+        assert_eq!(s.get_key_value(&2), None);
+        assert_eq!(s.get_mut(&2), None);
+        s.insert(3, 3);
+        assert_eq!(s.len(), 2);
+    }
+    assert!(!s.contains_key(&0));
+    assert_eq!(s[&3], 3);
+    assert_eq!(s.entry(0).or_insert(10), &mut 10);
+    s.set_time_to_live(None);
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(s.purge_expired(), 0);
+    assert_eq!(s.len(), 2);
+}
+
+#[test]
+fn purge_expired() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.set_time_to_live(Some(Duration::from_millis(10)));
+    s.insert_many((0..4).map(|i| (i, i)));
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(s.len(), 0);
+    assert_eq!(s.purge_expired(), 4);
+    assert!(s.is_empty());
+}
+
+#[test]
+fn len_counts_removed_loans() {
+    let mut s: LendingLibrary<i64, i64> = (0..6).map(|i| (i, i)).collect();
+    let a = s.lend(&0).unwrap();
+    let b = s.lend(&1).unwrap();
+    let c = s.lend(&2).unwrap();
+    let d = s.lend_send(&3).unwrap();
+    s.remove(&0);
+    s.remove(&1);
+    s.remove(&1);
+    assert_eq!(s.len(), 4);
+    drop(a);
+    Loan::into_inner(b);
+    assert_eq!(s.len(), 4);
+    s.clear();
+    assert_eq!(s.len(), 0);
+    drop(c);
+    drop(d);
+    s.insert(7, 7);
+    assert_eq!(s.len(), 1);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn custom_hasher() {
+    let mut s: LendingLibrary<i64, i64, BuildHasherDefault<DefaultHasher>> =
+        LendingLibrary::with_hasher(BuildHasherDefault::default());
+    s.insert(1, 1);
+    s.insert(2, 2);
+    {
+        let mut v = s.lend(&1).unwrap();
+        *v += 1;
+    }
+    let mut c = s.clone();
+    assert_eq!(*c.lend(&1).unwrap(), 2);
+    let mut d: LendingLibrary<i64, i64, BuildHasherDefault<DefaultHasher>> =
+        vec![(3, 3)].into_iter().collect();
+    d.append(&mut s);
+    assert_eq!(d.len(), 3);
+    assert_eq!(d[&2], 2);
+}
+
+#[derive(Default)]
+struct ConstantHasher;
+
+impl Hasher for ConstantHasher {
+    fn finish(&self) -> u64 {
+        0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {}
+}
+
+#[test]
+fn colliding_keys() {
+    let mut s: LendingLibrary<String, i32, BuildHasherDefault<ConstantHasher>> =
+        LendingLibrary::with_hasher(BuildHasherDefault::default());
+    s.insert("a".to_string(), 1);
+    s.insert("b".to_string(), 2);
+    s.insert("c".to_string(), 3);
+    assert_eq!(s.len(), 3);
+    assert_eq!((s["a"], s["b"], s["c"]), (1, 2, 3));
+    assert!(!s.contains_key("d"));
+    assert!(s.lend("d").is_none());
+    {
+        let mut a = s.lend("a").unwrap();
+        *a += 10;
+        assert_eq!(s.insert("b".to_string(), 20), Some(2));
+        assert!(s.remove("b"));
+        assert_eq!(s["c"], 3);
+        assert!(!s.contains_key("b"));
+    }
+    s.insert("d".to_string(), 4);
+    assert_eq!((s["a"], s["c"], s["d"]), (11, 3, 4));
+    let r = s.reserve_key("e".to_string()).unwrap();
+    assert!(s.is_reserved("e"));
+    assert_eq!(s.insert_if_vacant("e".to_string(), 5), Err(("e".to_string(), 5)));
+    s.insert_reserved(r, 5);
+    assert!(s.rename_key("a", "f".to_string()).unwrap());
+    let mut c = s.clone();
+    assert_eq!(*c.lend("f").unwrap(), 11);
+    assert_eq!(c.len(), 4);
+    assert_eq!(s.drain().collect::<HashMap<_, _>>().len(), 4);
+}
+
+#[test]
+fn borrowed_keys() {
+    let mut s: LendingLibrary<String, Vec<u8>> = LendingLibrary::new();
+    s.insert("a".to_string(), vec![1]);
+    s.insert("b".to_string(), vec![2]);
+    s.insert("c".to_string(), vec![3]);
+    assert!(s.contains_key("a"));
+    s.get_mut("a").unwrap().push(2);
+    assert_eq!(s["a"], vec![1, 2]);
+    {
+        let mut v = s.lend("b").unwrap();
+        v.push(3);
+        assert!(s.lend_if("c", |v| v.is_empty()).is_none());
+        assert!(s.lend_if_eq("c", &vec![3]).is_some());
+    }
+    assert_eq!(s.take("b"), Some(vec![2, 3]));
+    assert!(s.remove("a"));
+    assert_eq!(s.remove_keys(vec!["c", "d"]), 1);
+    assert!(s.is_empty());
+
+    let mut b: LendingLibrary<Vec<u8>, i32> = LendingLibrary::new();
+    b.insert(vec![1, 2], 1);
+    assert!(b.contains_key(&[1u8, 2][..]));
+}
+
+#[test]
+fn btree() {
+    let mut s: LendingBTree<String, i64> = LendingBTree::named("tree");
+    for i in (0..10).rev() {
+        s.insert(format!("k{}", i), i);
+    }
+    assert_eq!(s.len(), 10);
+    {
+        let mut v = s.lend("k3").unwrap();
+        *v += 100;
+        assert!(s.contains_key("k3"));
+        assert_eq!(s.get_mut("k3"), None);
+        assert_eq!(s.first_key_value(), Some((&String::from("k0"), &0)));
+        let _w = s.lend("k0").unwrap();
+        assert_eq!(s.first_key_value(), Some((&String::from("k1"), &1)));
+        assert!(s.remove("k0"));
+        assert!(!s.contains_key("k0"));
+        assert_eq!(s.len(), 9);
+        let keys: Vec<_> = s.keys().take(2).cloned().collect();
+        assert_eq!(keys, vec!["k1", "k2"]);
+        let vals: Vec<_> = s.range(String::from("k4")..String::from("k7")).map(|(_k, v)| *v).collect();
+        assert_eq!(vals, vec![4, 5, 6]);
+    }
+    assert!(!s.contains_key("k0"));
+    assert_eq!(s.take("k3"), Some(103));
+    assert_eq!(s.insert(String::from("k9"), 90), Some(9));
+    assert_eq!(s.last_key_value(), Some((&String::from("k9"), &90)));
+    let vals: Vec<_> = s.iter().map(|(_k, v)| *v).collect();
+    assert_eq!(vals, vec![1, 2, 4, 5, 6, 7, 8, 90]);
+}
+
+#[test]
+#[should_panic(expected = "Library \"tree\": Trying to iterate over a store with loaned items.")]
+fn btree_iter_loaned() {
+    let mut s: LendingBTree<i64, i64> = LendingBTree::named("tree");
+    s.insert(1, 1);
+    let _v = s.lend(&1);
+    for _ in s.iter() {}
+}
+
+#[test]
+#[should_panic(expected = "1 value loans outlived store.")]
+fn btree_failure_to_return() {
+    {
+        let mut s: LendingBTree<i64, i64> = LendingBTree::new();
+        s.insert(1, 1);
+        let _v = s.lend(&1).unwrap();
+        drop(s);
+    }
+}
+
+#[test]
+fn slab() {
+    let mut s: LendingSlab<String> = LendingSlab::new();
+    assert_eq!(s.insert(3, String::from("three")), None);
+    assert_eq!(s.insert(1, String::from("one")), None);
+    assert_eq!(s.len(), 2);
+    assert!(!s.contains_key(0) && !s.contains_key(7));
+    assert!(s.lend(0).is_none() && s.lend(7).is_none());
+    {
+        let mut v = s.lend(3).unwrap();
+        v.push('!');
+        assert!(s.contains_key(3));
+        assert_eq!(s.get_mut(3), None);
+        assert_eq!(s.keys().collect::<Vec<_>>(), vec![1, 3]);
+        let _w = s.lend(1).unwrap();
+        assert!(s.remove(1));
+        assert!(!s.contains_key(1));
+        assert_eq!(s.len(), 1);
+    }
+    assert!(!s.contains_key(1));
+    assert_eq!(s.insert(1, String::from("uno")), None);
+    s.get_mut(1).unwrap().push('?');
+    let vals: Vec<_> = s.iter().map(|(k, v)| (k, v.as_str())).collect();
+    assert_eq!(vals, vec![(1, "uno?"), (3, "three!")]);
+    assert_eq!(s.take(3), Some(String::from("three!")));
+    assert_eq!(s.take(3), None);
+}
+
+#[test]
+fn slab_insert_value() {
+    let mut s: LendingSlab<i64> = LendingSlab::new();
+    s.insert(4, 4);
+    let k = s.insert_value(5);
+    assert_eq!(k, 5);
+    {
+        let _v = s.lend(k).unwrap();
+        assert!(s.remove(k));
+        assert_eq!(s.insert_value(6), 6);
+    }
+    assert!(!s.contains_key(k));
+    assert_eq!(s.keys().collect::<Vec<_>>(), vec![4, 6]);
+}
+
+#[test]
+#[should_panic(expected = "Lending already loaned value")]
+fn slab_double_lend() {
+    let mut s: LendingSlab<i64> = (0..3).map(|i| (i, i as i64)).collect();
+    let _v = s.lend(1);
+    s.lend(1);
+}
+
+#[test]
+#[should_panic(expected = "Library \"ids\": Key out of range")]
+fn slab_insert_max_key() {
+    let mut s: LendingSlab<i64> = LendingSlab::named("ids");
+    s.insert(usize::MAX, 1);
+}
+
+#[test]
+fn set() {
+    let mut s: LendingSet<String> = LendingSet::named("devices");
+    assert!(s.insert(String::from("a")));
+    assert!(!s.insert(String::from("a")));
+    s.extend(vec![String::from("b"), String::from("c")]);
+    assert_eq!(s.len(), 3);
+    {
+        let _a = s.lend("a").unwrap();
+        assert!(s.is_loaned("a"));
+        assert!(!s.is_loaned("b"));
+        assert!(s.try_lend("a").is_none());
+        assert!(s.contains("a"));
+        assert!(s.remove("a"));
+        assert!(!s.contains("a"));
+        assert!(s.lend("z").is_none());
+    }
+    assert!(!s.contains("a"));
+    assert!(s.insert(String::from("a")));
+    let mut keys: Vec<_> = s.iter().cloned().collect();
+    keys.sort();
+    assert_eq!(keys, vec!["a", "b", "c"]);
+}
+
+#[test]
+#[should_panic(expected = "Library \"devices\": Lending already loaned value")]
+fn set_double_lend() {
+    let mut s: LendingSet<i64> = LendingSet::named("devices");
+    s.insert(1);
+    let _a = s.lend(&1);
+    s.lend(&1);
+}
+
+#[test]
+fn set_token_dropped_in_panic() {
+    let mut s: LendingSet<i64> = LendingSet::named("devices");
+    s.insert(1);
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _a = s.lend(&1).unwrap();
+        panic!("holder failed");
+    }));
+    assert!(r.is_err());
+    assert!(!s.is_loaned(&1));
+    assert!(s.try_lend(&1).is_some());
+    assert!(s.lend(&1).is_some());
+}
+
+new_key_type! {
+    struct TestKey;
+}
+
+#[test]
+fn slotmap() {
+    let mut s: LendingSlotMap<DefaultKey, String> = LendingSlotMap::new();
+    let a = s.insert(String::from("a"));
+    let b = s.insert(String::from("b"));
+    assert_eq!(s.len(), 2);
+    {
+        let mut v = s.lend(a).unwrap();
+        v.push('!');
+        assert!(s.contains_key(a));
+        assert_eq!(s.get_mut(a), None);
+        assert!(s.remove(a));
+        assert!(!s.contains_key(a));
+        let c = s.insert(String::from("c"));
+        assert_ne!(c.data(), a.data());
+        assert_eq!(s.len(), 2);
+        assert!(s.remove(c));
+    }
+    assert!(s.lend(a).is_none());
+    let d = s.insert(String::from("d"));
+    assert!(s.lend(a).is_none());
+    assert_eq!(s.take(b), Some(String::from("b")));
+    assert_eq!(s.take(b), None);
+    assert_eq!(s.keys().collect::<Vec<_>>(), vec![d]);
+    s.get_mut(d).unwrap().push('?');
+    assert_eq!(s.iter().map(|(_k, v)| v.as_str()).collect::<Vec<_>>(), vec!["d?"]);
+}
+
+#[test]
+fn slotmap_typed_keys() {
+    let mut s: LendingSlotMap<TestKey, i64> = LendingSlotMap::with_key();
+    let k = s.insert(1);
+    *s.lend(k).unwrap() += 1;
+    assert_eq!(s.take(k), Some(2));
+    assert!(s.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Library \"handles\": Lending already loaned value")]
+fn slotmap_named() {
+    let mut s: LendingSlotMap<TestKey, i64> = LendingSlotMap::named_with_key("handles");
+    assert_eq!(s.name(), Some("handles"));
+    assert_eq!(LendingSlotMap::<DefaultKey, i64>::named("ids").name(), Some("ids"));
+    let k = s.insert(1);
+    let _a = s.lend(k).unwrap();
+    let _b = s.lend(k);
+}
+
+#[test]
+fn vec() {
+    let mut s: LendingVec<String> = vec![String::from("a"), String::from("b")].into_iter().collect();
+    assert_eq!(s.push(String::from("c")), 2);
+    assert!(s.lend(3).is_none());
+    {
+        let mut v = s.lend(1).unwrap();
+        v.push('!');
+        assert!(s.is_loaned(1));
+        assert_eq!(s.get_mut(1), None);
+        for v in s.iter_mut().flatten() {
+            v.push('?');
+        }
+        let seen: Vec<_> = s.iter().map(|v| v.map(String::as_str)).collect();
+        assert_eq!(seen, vec![Some("a?"), None, Some("c?")]);
+        assert_eq!(s.pop(), Some(String::from("c?")));
+    }
+    assert!(!s.is_loaned(1));
+    assert_eq!(s.pop(), Some(String::from("b!")));
+    assert_eq!(s.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "Cannot take loaned value")]
+fn vec_pop_loaned() {
+    let mut s: LendingVec<i64> = (0..3).collect();
+    let _v = s.lend(2);
+    s.pop();
+}
+
+#[test]
+fn array() {
+    let mut s: LendingArray<&str, i64, 2> = LendingArray::named("fixed");
+    assert_eq!(s.insert("a", 1), Ok(None));
+    assert_eq!(s.insert("b", 2), Ok(None));
+    assert_eq!(s.insert("c", 3), Err(("c", 3)));
+    {
+        let mut v = s.lend(&"a").unwrap();
+        *v += 10;
+        assert!(s.remove(&"a"));
+        assert!(!s.contains_key(&"a"));
+        assert_eq!(s.insert("c", 3), Err(("c", 3)));
+        assert_eq!(s.take(&"b"), Some(2));
+        assert_eq!(s.insert("c", 3), Ok(None));
+    }
+    assert_eq!(s.insert("a", 4), Ok(None));
+    assert_eq!(s.iter().collect::<Vec<_>>(), vec![(&"a", &4), (&"c", &3)]);
+    assert_eq!(s.len(), s.capacity());
+}
+
+#[test]
+#[should_panic(expected = "Library \"fixed\": Lending already loaned value")]
+fn array_double_lend() {
+    let mut s: LendingArray<i64, i64, 4> = LendingArray::named("fixed");
+    s.insert(1, 1).unwrap();
+    let _a = s.lend(&1);
+    let _b = s.lend(&1);
+}
+
+#[test]
+fn array_poisoned_on_panic() {
+    let mut s: LendingArray<i64, i64, 2> = LendingArray::named("fixed");
+    s.insert(1, 1).unwrap();
+    s.insert(2, 2).unwrap();
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut v = s.lend(&1).unwrap();
+        *v += 10;
+        let _w = s.lend(&2).unwrap();
+        s.remove(&2);
+        panic!("failed halfway through");
+    }));
+    assert!(s.is_poisoned(&1));
+    assert!(!s.contains_key(&2));
+    assert!(s.lend_checked(&1).unwrap().is_err());
+    assert!(s.clear_poison(&1));
+    assert_eq!(*s.lend(&1).unwrap(), 11);
+    s.insert(1, 5).unwrap();
+    assert!(!s.is_poisoned(&1));
+}
+
+#[test]
+#[should_panic(expected = "Library \"fixed\": Lending poisoned value")]
+fn array_lend_poisoned() {
+    let mut s: LendingArray<i64, i64, 2> = LendingArray::named("fixed");
+    s.insert(1, 1).unwrap();
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&1).unwrap();
+        panic!("failed halfway through");
+    }));
+    s.lend(&1);
+}
+
+#[test]
+fn bimap() {
+    let mut s: LendingBiMap<i64, String> = (0..3).map(|i| (i, format!("h{}", i))).collect();
+    assert_eq!(s.insert(0, String::from("h1")),
+               vec![(0, String::from("h0")), (1, String::from("h1"))]);
+    assert_eq!(s.len(), 2);
+    {
+        let mut e = s.lend_by_right("h2").unwrap();
+        assert!(s.is_loaned_left(&2));
+        assert_eq!(s.get_by_left(&2), None);
+        assert!(s.contains_right("h2"));
+        e.0 = 5;
+    }
+    assert!(!s.contains_left(&2));
+    assert_eq!(s.get_by_left(&5).map(String::as_str), Some("h2"));
+    {
+        let _e = s.lend_by_left(&0).unwrap();
+        assert!(s.remove_by_right("h1"));
+        assert!(!s.contains_left(&0));
+        assert_eq!(s.len(), 1);
+    }
+    assert!(!s.contains_left(&0));
+    assert!(!s.contains_right("h1"));
+    assert_eq!(s.iter().collect::<Vec<_>>(), vec![(&5, &String::from("h2"))]);
+}
+
+#[test]
+#[should_panic(expected = "Returned pair collides with an existing entry")]
+fn bimap_checkin_collision() {
+    let mut s: LendingBiMap<i64, i64> = vec![(0, 10), (1, 11)].into_iter().collect();
+    let mut e = s.lend_by_left(&0).unwrap();
+    e.1 = 11;
+}
+
+#[test]
+fn bimap_checkin_collision_keeps_pair() {
+    let mut s: LendingBiMap<i64, i64> = vec![(0, 10), (1, 11)].into_iter().collect();
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut e = s.lend_by_left(&0).unwrap();
+        e.1 = 11;
+    }));
+    assert!(r.is_err());
+    assert_eq!(s.len(), 2);
+    assert!(!s.is_loaned_left(&0));
+    assert_eq!(s.get_by_left(&0), Some(&11));
+    assert_eq!(s.get_by_right(&10), Some(&0));
+    assert_eq!(s.get_by_right(&11), Some(&1));
+    assert!(s.remove_by_left(&0));
+    assert_eq!(s.iter().collect::<Vec<_>>(), vec![(&1, &11)]);
+}
+
+#[test]
+fn multimap() {
+    let mut s: LendingMultiMap<&str, i64> = vec![("a", 1), ("a", 2), ("b", 3)].into_iter().collect();
+    assert_eq!(s.len(), 3);
+    {
+        let mut v = s.lend_one("a").unwrap();
+        *v += 10;
+        assert_eq!((s.count("a"), s.available("a")), (2, 1));
+        let rest = s.lend_all("a");
+        assert_eq!(rest.len(), 1);
+        assert!(s.lend_one("a").is_none());
+        assert!(s.lend_all("a").is_empty());
+        assert_eq!(s.remove("b"), 1);
+        assert!(!s.contains_key("b"));
+    }
+    let mut vals = s.take_available("a");
+    vals.sort();
+    assert_eq!(vals, vec![2, 11]);
+    assert!(s.is_empty());
+}
+
+#[test]
+fn multimap_remove_loaned() {
+    let mut s: LendingMultiMap<&str, i64> = LendingMultiMap::new();
+    s.insert("a", 1);
+    {
+        let _v = s.lend_one("a").unwrap();
+        assert_eq!(s.remove("a"), 1);
+        assert!(!s.contains_key("a"));
+        s.insert("a", 2);
+        assert_eq!(s.count("a"), 1);
+    }
+    assert_eq!(s.take_available("a"), vec![2]);
+    assert_eq!(s.keys().count(), 0);
+}
+
+#[cfg(feature = "indexmap")]
+#[test]
+fn indexed() {
+    use super::indexed::LendingIndexMap;
+    let mut s: LendingIndexMap<String, i64> = (0..5).map(|i| (format!("k{}", i), i)).collect();
+    assert_eq!(s.insert(String::from("k0"), 10), Some(0));
+    {
+        let mut v = s.lend("k2").unwrap();
+        *v += 100;
+        assert_eq!(s.get_index(2), None);
+        assert_eq!(s.get_index_of("k2"), Some(2));
+        assert!(s.remove("k1"));
+        assert_eq!(s.get_index_of("k2"), Some(1));
+        let w = s.lend("k3").unwrap();
+        assert!(s.swap_remove("k3"));
+        assert!(!s.contains_key("k3"));
+        assert_eq!(s.len(), 3);
+        assert_eq!(*w, 3);
+    }
+    assert!(!s.contains_key("k3"));
+    s.insert(String::from("k3"), 30);
+    let vals: Vec<_> = s.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    assert_eq!(vals, vec![("k0", 10), ("k2", 102), ("k4", 4), ("k3", 30)]);
+}
+
+#[cfg(feature = "indexmap")]
+#[test]
+#[should_panic(expected = "Cannot overwrite value awaiting drop")]
+fn indexed_insert_awaiting_drop() {
+    use super::indexed::LendingIndexMap;
+    let mut s: LendingIndexMap<i64, i64> = LendingIndexMap::new();
+    s.insert(1, 1);
+    let _v = s.lend(&1);
+    s.remove(&1);
+    s.insert(1, 2);
+}
+
+#[test]
+fn poisoning() {
+    let mut s: LendingLibrary<i64, Vec<i64>> = LendingLibrary::new();
+    s.set_normalizer(|mut v: Vec<i64>| {
+        v.sort();
+        v
+    });
+    s.insert(1, vec![]);
+    s.insert(2, vec![]);
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut v = s.lend(&1).unwrap();
+        v.push(2);
+        v.push(1);
+        panic!("borrower failed");
+    }));
+    assert!(r.is_err());
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert!(s.is_poisoned(&1));
+    assert!(!s.is_poisoned(&2));
+    {
+        let v = s.lend_checked(&1).unwrap().unwrap_err().into_inner();
+        assert_eq!(*v, vec![2, 1]);
+    }
+    assert!(s.lend_checked(&2).unwrap().is_ok());
+    assert!(s.lend_checked(&3).is_none());
+    assert!(s.clear_poison(&1));
+    assert!(!s.clear_poison(&1));
+    assert_eq!(*s.lend(&1).unwrap(), vec![2, 1]);
+
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&2).unwrap();
+        panic!("borrower failed");
+    }));
+    assert!(s.is_poisoned(&2));
+    s.insert(2, vec![3]);
+    assert!(!s.is_poisoned(&2));
+}
+
+#[test]
+#[should_panic(expected = "Lending poisoned value")]
+fn lend_poisoned() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("test"));
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&1).unwrap();
+        panic!("borrower failed");
+    }));
+    s.lend(&1);
+}
+
+#[test]
+fn poisoning_outlived_store() {
+    let r = panic::catch_unwind(|| {
+        let _v;
+        let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+        s.insert(1, String::from("test"));
+        _v = s.lend(&1).unwrap();
+        panic!("store dropped first");
+    });
+    assert!(r.is_err());
+}
+
+#[test]
+fn loan_ordering_and_hashing() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("b"));
+    s.insert(2, String::from("a"));
+    s.insert(3, String::from("b"));
+    {
+        let mut loans = vec![s.lend(&1).unwrap(), s.lend(&2).unwrap(), s.lend(&3).unwrap()];
+        loans.sort();
+        assert_eq!(*loans[0], "a");
+        assert!(loans[1] == loans[2]);
+        assert!(loans[0] < loans[1]);
+        let set: HashSet<_> = loans.into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+    assert_eq!(s.len(), 3);
+}
+
+#[test]
+fn loan_display() {
+    let mut s: LendingLibrary<i64, f64> = LendingLibrary::new();
+    s.insert(1, 1.5);
+    let v = s.lend(&1).unwrap();
+    assert_eq!(format!("{}", v), "1.5");
+    assert_eq!(format!("{:>6.2}", v), "  1.50");
+}
+
+#[test]
+fn loan_conversions() {
+    fn total<T: AsRef<Vec<i64>>>(v: T) -> i64 {
+        v.as_ref().iter().sum()
+    }
+    fn extend<T: BorrowMut<Vec<i64>>>(mut v: T) {
+        v.borrow_mut().push(3);
+    }
+
+    let mut s: LendingLibrary<i64, Vec<i64>> = LendingLibrary::new();
+    s.insert(1, vec![1, 2]);
+    extend(s.lend(&1).unwrap());
+    assert_eq!(total(s.lend(&1).unwrap()), 6);
+    {
+        let mut v = s.lend(&1).unwrap();
+        v.as_mut().clear();
+        let set: HashSet<Loan<i64, Vec<i64>>> = Some(v).into_iter().collect();
+        assert!(set.contains(&vec![]));
+    }
+    assert_eq!(s.get_mut(&1), Some(&mut vec![]));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_loans() {
+    use serde_json;
+
+    let mut s: LendingLibrary<String, Vec<i64>> = LendingLibrary::new();
+    s.insert(String::from("a"), vec![1, 2]);
+    let v = s.lend("a").unwrap();
+    assert_eq!(serde_json::to_string(&v).unwrap(), "[1,2]");
+    assert_eq!(serde_json::to_string(&Loan::with_key(&v)).unwrap(),
+               r#"{"key":"a","value":[1,2]}"#);
+    s.remove("a");
+    assert_eq!(serde_json::to_string(&Loan::with_key(&v)).unwrap(),
+               r#"{"key":"a","value":[1,2]}"#);
+}
+
+#[test]
+fn send_loans() {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    assert_send::<SendLoan<Vec<i64>>>();
+    assert_send::<LendingLibrary<i64, Vec<i64>>>();
+    assert_sync::<LendingLibrary<i64, Vec<i64>>>();
+
+    let mut s: LendingLibrary<i64, Vec<i64>> = LendingLibrary::new();
+    s.insert(1, vec![]);
+    s.insert(2, vec![]);
+    let workers: Vec<thread::JoinHandle<()>> = [1, 2]
+        .iter()
+        .map(|k| {
+            let mut v: SendLoan<Vec<i64>> = s.lend_send(k).unwrap();
+            let k = *k;
+            thread::spawn(move || v.push(k * 10))
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 2);
+    assert_eq!(s.get_mut(&1), Some(&mut vec![10]));
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(*s.lend(&2).unwrap(), vec![20]);
+
+    let v = s.lend_send(&1).unwrap();
+    assert!(thread::spawn(move || {
+        let _v = v;
+        panic!("worker failed");
+    }).join()
+        .is_err());
+    s.purge_expired();
+    assert!(s.is_poisoned(&1));
+
+    let v = s.lend_send(&2).unwrap();
+    s.remove(&2);
+    drop(v);
+}
+
+#[test]
+fn send_loans_returned_before_bulk_operations() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    *s.lend_send(&1).unwrap() += 10;
+    assert_eq!(s.clone().into_sorted_vec(), vec![(0, 0), (1, 11), (2, 2)]);
+    let mut pairs: Vec<_> = s.drain().collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![(0, 0), (1, 11), (2, 2)]);
+
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    drop(s.lend_send(&1).unwrap());
+    assert_eq!(s.into_iter().count(), 3);
+
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    drop(s.lend_send(&1).unwrap());
+    assert_eq!(HashMap::try_from(&mut s).unwrap().len(), 3);
+}
+
+#[test]
+fn split_loans() {
+    let mut s: LendingLibrary<i64, (Vec<i64>, String)> = LendingLibrary::new();
+    s.insert(1, (vec![], String::new()));
+    let (mut nums, text) = Loan::split(s.lend(&1).unwrap());
+    let mut text = MappedLoan::map(text, |t| t);
+    nums.push(1);
+    drop(nums);
+    assert_eq!(s.get_mut(&1), None);
+    text.push('a');
+    drop(text);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(s.get_mut(&1), Some(&mut (vec![1], String::from("a"))));
+}
+
+#[test]
+fn dirty_tracking() {
+    let mut s: LendingLibrary<i64, Vec<i64>> = (0..6).map(|i| (i, vec![i])).collect();
+    {
+        let v = s.lend(&0).unwrap();
+        assert_eq!(v.len(), 1);
+        assert!(!Loan::is_dirty(&v));
+    }
+    s.lend(&1).unwrap().push(1);
+    *Loan::map(s.lend(&2).unwrap(), |v| &mut v[0]) = 2;
+    {
+        let mut v = s.lend(&3).unwrap();
+        v.clear();
+        ReadLoan::upgrade(Loan::downgrade(v)).unwrap();
+    }
+    s.lend_send(&4).unwrap().push(4);
+    {
+        let mut v = s.lend(&5).unwrap();
+        v.push(5);
+        s.remove(&5);
+    }
+    let mut keys = s.drain_dirty_keys();
+    keys.sort();
+    assert_eq!(keys, vec![&1, &2, &3, &4]);
+    assert!(s.drain_dirty_keys().is_empty());
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn outstanding_report() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    let line = line!() + 1;
+    let v = s.lend(&1).unwrap();
+    let loans = s.lend_many(&[0, 2]).unwrap();
+    let report = s.outstanding_report();
+    assert_eq!(report.len(), 3);
+    let at = report.iter().find(|l| *l.key() == 1).unwrap().location();
+    assert_eq!((at.file(), at.line()), (file!(), line));
+    assert!(report.iter().all(|l| l.location().file() == file!()));
+    drop(loans);
+    drop(v);
+    assert!(s.outstanding_report().is_empty());
+}
+
+#[cfg(feature = "debug")]
+#[test]
+#[should_panic(expected = "1 value loans outlived store. Lent at: src/tests.rs:")]
+fn loans_outlived_store_location() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.insert(1, 1);
+    let v = s.lend(&1).unwrap();
+    drop(s);
+    drop(v);
+}
+
+#[test]
+fn tagged_loans() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    let a = s.lend_tagged(&0, "cache").unwrap();
+    let b = s.lend_tagged(&1, String::from("persister")).unwrap();
+    let c = s.lend(&2).unwrap();
+    let mut report: Vec<_> = s.outstanding_report()
+        .iter()
+        .map(|l| (*l.key(), l.tag()))
+        .collect();
+    report.sort();
+    assert_eq!(report, vec![(0, Some("cache")), (1, Some("persister")), (2, None)]);
+    drop((a, b, c));
+    assert!(s.outstanding_report().is_empty());
+    let _d = s.lend(&0).unwrap();
+    assert_eq!(s.outstanding_report()[0].tag(), None);
+}
+
+#[cfg(not(feature = "debug"))]
+#[test]
+#[should_panic(expected = "2 value loans outlived store. Held by: cache, persister")]
+fn loans_outlived_store_tags() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    let a = s.lend_tagged(&0, "persister").unwrap();
+    let b = s.lend_tagged(&1, "cache").unwrap();
+    drop(s);
+    drop((a, b));
+}
+
+#[test]
+fn hold_durations() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    let a = s.lend(&0).unwrap();
+    thread::sleep(Duration::from_millis(10));
+    let b = s.lend_tagged(&1, "b").unwrap();
+    thread::sleep(Duration::from_millis(10));
+    let c = s.lend_shared(&2).unwrap();
+    assert!(Loan::held_for(&a) >= Duration::from_millis(20));
+    assert!(Loan::held_for(&b) >= Duration::from_millis(10));
+    assert!(Loan::held_for(&b) < Loan::held_for(&a));
+    let held: Vec<i64> = s.longest_held(5).iter().map(|l| *l.key()).collect();
+    assert_eq!(held, vec![0, 1, 2]);
+    drop(a);
+    let held = s.longest_held(1);
+    assert_eq!((held[0].key(), held[0].tag()), (&1, Some("b")));
+    assert!(held[0].held_for() >= Duration::from_millis(10));
+    drop((b, c));
+    assert!(s.longest_held(1).is_empty());
+}
+
+#[test]
+fn loan_issuer() {
+    let mut a: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let mut b: LendingLibrary<i64, i64> = a.clone();
+    a.insert(1, 1);
+    let v = a.lend(&1).unwrap();
+    assert!(Loan::issued_by(&v, &a));
+    assert!(!Loan::issued_by(&v, &b));
+    ::std::mem::swap(&mut a, &mut b);
+    assert!(Loan::issued_by(&v, &b));
+    ::std::mem::swap(&mut a, &mut b);
+    drop(v);
+    assert_eq!(a.get_mut(&1), Some(&mut 1));
+}
+
+#[test]
+#[should_panic(expected = "Returning loan to a library that did not issue it")]
+fn loan_returned_to_other_library() {
+    let mut a: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let mut b: LendingLibrary<i64, i64> = LendingLibrary::new();
+    a.insert(1, 1);
+    b.insert(1, 2);
+    let _w = b.lend(&1).unwrap();
+    let v = a.lend(&1).unwrap();
+    ::std::mem::swap(&mut a, &mut b);
+    drop(v);
+}
+
+#[test]
+#[should_panic(expected = "Cloning read loan from a library that did not issue it")]
+fn read_loan_cloned_from_other_library() {
+    let mut a: LendingLibrary<i64, i64> = LendingLibrary::new();
+    let mut b: LendingLibrary<i64, i64> = LendingLibrary::new();
+    a.insert(1, 1);
+    b.insert(1, 2);
+    let _w = b.lend_shared(&1).unwrap();
+    let r = a.lend_shared(&1).unwrap();
+    ::std::mem::swap(&mut a, &mut b);
+    let _c = r.clone();
+}
+
+#[test]
+fn cloned_read_loans() {
+    let mut s: LendingLibrary<i64, String> = LendingLibrary::new();
+    s.insert(1, String::from("a"));
+    let r = s.lend_shared(&1).unwrap();
+    let readers: Vec<ReadLoan<i64, String>> = (0..3).map(|_| r.clone()).collect();
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 4);
+    drop(r);
+    assert!(readers.iter().all(|r| **r == "a"));
+    assert_eq!(s.get_mut(&1), None);
+    let last = readers.into_iter().last().unwrap();
+    let mut w = ReadLoan::upgrade(last).unwrap();
+    w.push('b');
+    drop(w);
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(s.get_mut(&1), Some(&mut String::from("ab")));
+}
+
+#[test]
+fn scoped_loans() {
+    let mut s: LendingLibrary<i64, Vec<i64>> = LendingLibrary::new();
+    s.insert(1, vec![]);
+    {
+        let mut v = s.lend_scoped(&1).unwrap();
+        v.push(1);
+        assert_eq!(*v, vec![1]);
+    }
+    assert!(s.lend_scoped(&2).is_none());
+    assert_eq!(s.outstanding.load(Ordering::SeqCst), 0);
+    assert_eq!(s.get_mut(&1), Some(&mut vec![1]));
+}
+
+#[test]
+fn loan_watches() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    assert!(s.watch(&0).is_returned());
+    assert!(s.watch(&5).is_returned());
+
+    let v = s.lend(&0).unwrap();
+    let early = s.watch(&0);
+    let waiter = thread::spawn(move || early.wait_timeout(Duration::from_secs(10)));
+    let late = s.watch(&0);
+    assert!(!late.wait_timeout(Duration::from_millis(1)));
+    drop(v);
+    assert!(waiter.join().unwrap());
+    assert!(late.is_returned());
+    late.wait();
+
+    let r = s.lend_shared(&1).unwrap();
+    let r2 = r.clone();
+    let watch = s.watch(&1);
+    drop(r);
+    assert!(!watch.is_returned());
+    drop(r2);
+    assert!(watch.is_returned());
+
+    let v = s.lend(&2).unwrap();
+    let watch = s.watch(&2);
+    Loan::into_inner(v);
+    assert!(watch.is_returned());
+}
+
+#[test]
+fn swapped_loans() {
+    let mut s: LendingLibrary<i64, Vec<i64>> = LendingLibrary::new();
+    s.insert(1, vec![1, 2]);
+    s.insert(2, vec![]);
+    {
+        let mut a = s.lend(&1).unwrap();
+        let mut b = s.lend(&2).unwrap();
+        Loan::swap_with(&mut a, &mut b);
+        assert!(a.is_empty());
+        assert!(Loan::is_dirty(&a) && Loan::is_dirty(&b));
+    }
+    assert_eq!(s.get_mut(&1), Some(&mut vec![]));
+    assert_eq!(s.get_mut(&2), Some(&mut vec![1, 2]));
+    let mut keys = s.drain_dirty_keys();
+    keys.sort();
+    assert_eq!(keys, vec![&1, &2]);
+}
+
+#[test]
+fn checkin_validation() {
+    let rejected = Arc::new(Mutex::new(Vec::new()));
+    let sink = rejected.clone();
+    let mut s: LendingLibrary<i64, i64> = (0..4).map(|i| (i, i)).collect();
+    s.set_validator(|_, v: &i64| if *v >= 0 { Ok(()) } else { Err("negative") },
+                    move |k, v, e| {
+                        sink.lock().unwrap().push((*k, v, e));
+                        match *k {
+                            0 => Some(0),
+                            _ => None,
+                        }
+                    });
+    *s.lend(&0).unwrap() = -5;
+    *s.lend(&1).unwrap() = -1;
+    *s.lend(&2).unwrap() = 20;
+    {
+        let mut v = s.lend(&3).unwrap();
+        *v = -3;
+        ReadLoan::upgrade(Loan::downgrade(v)).unwrap();
+    }
+    assert_eq!(*rejected.lock().unwrap(), vec![(0, -5, "negative"), (1, -1, "negative"), (3, -3, "negative")]);
+    assert_eq!(s.get_mut(&0), Some(&mut 0));
+    assert!(!s.contains_key(&1));
+    assert_eq!(s.get_mut(&2), Some(&mut 20));
+    assert!(!s.contains_key(&3));
+
+    *s.get_mut(&2).unwrap() = -2;
+    drop(s.lend(&2).unwrap());
+    assert_eq!(s.get_mut(&2), Some(&mut -2));
+    s.clear_validator();
+    *s.lend(&0).unwrap() = -1;
+    assert_eq!(s.get_mut(&0), Some(&mut -1));
+}
+
+#[test]
+fn reserved_keys() {
+    let mut s: LendingLibrary<i32, i32> = LendingLibrary::new();
+    s.insert(1, 1);
+    assert!(s.reserve_key(1).is_none());
+    let reservation = s.reserve_key(2).unwrap();
+    assert_eq!(*reservation.key(), 2);
+    assert!(s.is_reserved(&2));
+    assert!(s.reserve_key(2).is_none());
+    assert!(!s.contains_key(&2));
+    assert!(s.lend(&2).is_none());
+    assert_eq!(s.try_insert(2, 5).unwrap_err().kind(), InsertErrorKind::Reserved);
+    assert_eq!(s.insert_if_vacant(2, 5), Err((2, 5)));
+    s.insert_reserved(reservation, 2);
+    assert!(!s.is_reserved(&2));
+    assert_eq!(s.get_mut(&2), Some(&mut 2));
+
+    drop(s.reserve_key(3).unwrap());
+    assert!(!s.is_reserved(&3));
+    s.insert(3, 3);
+    assert_eq!(s.len(), 3);
+}
+
+#[test]
+#[should_panic(expected = "Cannot insert into reserved key")]
+fn insert_into_reserved_key() {
+    let mut s: LendingLibrary<i32, i32> = LendingLibrary::new();
+    let _reservation = s.reserve_key(1).unwrap();
+    s.insert(1, 1);
+}
+
+#[test]
+#[should_panic(expected = "Inserting with a reservation from a different library")]
+fn insert_reserved_into_other_library() {
+    let mut a: LendingLibrary<i32, i32> = LendingLibrary::new();
+    let mut b: LendingLibrary<i32, i32> = LendingLibrary::new();
+    let reservation = a.reserve_key(1).unwrap();
+    b.insert_reserved(reservation, 1);
+}
+
+#[test]
+fn try_lend() {
+    let mut s: LendingLibrary<i32, i32> = (0..3).map(|i| (i, i)).collect();
+    assert_eq!(s.try_lend(&5).unwrap_err().kind(), LendErrorKind::NotFound);
+    {
+        let a = s.try_lend(&0).unwrap();
+        assert_eq!(*a, 0);
+        assert_eq!(s.try_lend(&0).unwrap_err().kind(), LendErrorKind::AlreadyLoaned);
+        let _r = s.lend_shared(&1).unwrap();
+        assert_eq!(s.try_lend(&1).unwrap_err().kind(), LendErrorKind::AlreadyLoaned);
+    }
+    assert_eq!(*s.try_lend(&0).unwrap(), 0);
+
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&2).unwrap();
+        panic!("borrower failed");
+    }));
+    assert!(r.is_err());
+    assert_eq!(s.try_lend(&2).unwrap_err().kind(), LendErrorKind::Poisoned);
+    s.clear_poison(&2);
+    assert_eq!(*s.try_lend(&2).unwrap(), 2);
+}
+
+#[test]
+fn checked_operations() {
+    let mut s: LendingLibrary<i32, i32> = (0..3).map(|i| (i, i)).collect();
+    s.set_max_weight(Some(10));
+    s.set_weigher(|_, v: &i32| *v as usize);
+    let mut c = s.checked();
+    assert_eq!(c.len(), 3);
+    assert_eq!(c.get(&1), Ok(&1));
+    assert_eq!(c.get(&5).unwrap_err().kind(), CheckedErrorKind::NotFound);
+    let err = CheckedError::from(c.insert(5, 20).unwrap_err());
+    assert_eq!(err.kind(), CheckedErrorKind::OverWeight);
+    {
+        let _a = c.lend(&0).unwrap();
+        let r = c.lend_shared(&1).unwrap();
+        assert_eq!(*c.lend_shared(&1).unwrap(), *r);
+        assert_eq!(c.lend_shared(&0).unwrap_err().kind(), CheckedErrorKind::Loaned);
+        assert_eq!(c.lend(&1).unwrap_err().kind(), CheckedErrorKind::Loaned);
+        assert_eq!(c.get(&0).unwrap_err().kind(), CheckedErrorKind::Loaned);
+        assert_eq!(c.take(&0).unwrap_err().kind(), CheckedErrorKind::Loaned);
+        let err = CheckedError::from(c.iter().err().unwrap());
+        let mut keys = err.keys().to_vec();
+        keys.sort();
+        assert_eq!((err.kind(), keys), (CheckedErrorKind::Loaned, vec!["0".into(), "1".into()]));
+        assert!(err.to_string().starts_with("Value is currently loaned: ["));
+        let err = c.drain().err().unwrap();
+        assert_eq!(err.kind(), CheckedErrorKind::OutstandingLoans(2));
+        let err = CheckedError::from(c.insert(0, 0).unwrap_err());
+        assert_eq!(err.kind(), CheckedErrorKind::Loaned);
+        assert_eq!(c.remove(&0), Ok(()));
+        assert_eq!(c.lend(&0).unwrap_err().kind(), CheckedErrorKind::AwaitingDrop);
+        assert_eq!(c.lend_shared(&0).unwrap_err().kind(), CheckedErrorKind::AwaitingDrop);
+    }
+    assert_eq!(c.remove(&0).unwrap_err().kind(), CheckedErrorKind::NotFound);
+    assert_eq!(c.take(&2), Ok(2));
+    assert_eq!(c.take(&2).unwrap_err().kind(), CheckedErrorKind::NotFound);
+    assert_eq!(c.iter().unwrap().count(), 1);
+    assert_eq!(c.drain().unwrap().collect::<Vec<_>>(), vec![(1, 1)]);
+    c.insert(3, 3).unwrap();
+    c.clear();
+    assert!(c.is_empty());
+}
+
+#[test]
+fn checked_operations_do_not_panic() {
+    let mut s: LendingLibrary<i32, i32> = (0..4).map(|i| (i, i)).collect();
+    let mut other: LendingLibrary<i32, i32> = (3..6).map(|i| (i, i)).collect();
+    let _r = s.reserve_key(7).unwrap();
+    let mut c = s.checked();
+    {
+        let _a = c.lend(&0).unwrap();
+        assert_eq!(c.lend_many(&[1, 0]).err().unwrap().kind(), CheckedErrorKind::Loaned);
+        assert_eq!(c.lend_many(&[1, 1]).err().unwrap().kind(), CheckedErrorKind::Loaned);
+        assert_eq!(c.lend_many(&[1, 9]).err().unwrap().kind(), CheckedErrorKind::NotFound);
+        assert_eq!(c.entry(0).err().unwrap().kind(), CheckedErrorKind::Loaned);
+        assert_eq!(c.entry(7).err().unwrap().kind(), CheckedErrorKind::Reserved);
+        assert_eq!(c.get_mut(&0).unwrap_err().kind(), CheckedErrorKind::Loaned);
+        let err = c.get_or_insert_with(0, || unreachable!()).unwrap_err();
+        assert_eq!(err.kind(), CheckedErrorKind::Loaned);
+        let err = CheckedError::from(c.rename_key(&1, 0).unwrap_err());
+        assert_eq!(err.kind(), CheckedErrorKind::Loaned);
+        other.insert(0, 10);
+        assert_eq!(c.append(&mut other).unwrap_err().kind(), CheckedErrorKind::Loaned);
+        assert_eq!(other.len(), 4);
+        other.take(&0);
+        let loans = c.lend_many(&[1, 2]).unwrap();
+        assert_eq!(loans.len(), 2);
+    }
+    *c.get_mut(&1).unwrap() += 10;
+    *c.get_or_insert_with(8, || 8).unwrap() += 1;
+    c.entry(2).unwrap().and_modify(|v| *v += 20);
+    assert!(c.rename_key(&3, 9).unwrap());
+    c.append(&mut other).unwrap();
+    assert!(other.is_empty());
+    assert_eq!(c.get(&1), Ok(&11));
+    assert_eq!(c.get(&2), Ok(&22));
+    assert_eq!(c.get(&8), Ok(&9));
+    assert_eq!(c.get(&9), Ok(&3));
+    assert_eq!(c.len(), 8);
+}
+
+#[test]
+fn swap_keys_moves_entry_state() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    s.set_time_to_live(Some(Duration::from_secs(3600)));
+    s.insert(2, 2);
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&1).unwrap();
+        panic!("borrower failed");
+    }));
+    assert!(r.is_err());
+    s.drain_dirty_keys();
+    *s.lend(&0).unwrap() = 10;
+    assert!(s.swap_keys(&1, &2).unwrap());
+    assert!(!s.is_poisoned(&1));
+    assert!(s.is_poisoned(&2));
+    assert!(s.swap_keys(&0, &2).unwrap());
+    assert_eq!(s.drain_dirty_keys(), vec![&2]);
+    assert!(s.is_poisoned(&0));
+    s.set_time_to_live(Some(Duration::from_millis(100)));
+    thread::sleep(Duration::from_millis(60));
+    s.insert(1, 1);
+    assert!(s.swap_keys(&1, &2).unwrap());
+    thread::sleep(Duration::from_millis(60));
+    assert!(!s.contains_key(&1));
+    assert!(s.contains_key(&2));
+}
+
+#[test]
+fn rename_key_moves_entry_state() {
+    let mut s: LendingLibrary<i64, i64> = (0..3).map(|i| (i, i)).collect();
+    s.set_time_to_live(Some(Duration::from_secs(3600)));
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _v = s.lend(&1).unwrap();
+        panic!("borrower failed");
+    }));
+    assert!(r.is_err());
+    s.drain_dirty_keys();
+    *s.lend(&0).unwrap() = 10;
+    assert!(s.rename_key(&1, 1).unwrap());
+    assert!(s.is_poisoned(&1));
+    assert!(s.rename_key(&1, 5).unwrap());
+    assert!(!s.is_poisoned(&1));
+    assert!(s.is_poisoned(&5));
+    assert!(s.rename_key(&0, 0).unwrap());
+    assert_eq!(s.drain_dirty_keys(), vec![&0]);
+    let reservation = s.reserve_key(7).unwrap();
+    let err = s.rename_key(&2, 7).unwrap_err();
+    assert_eq!(err.kind(), RenameErrorKind::Reserved);
+    assert_eq!(s.get_key_value(&2), Some((&2, &2)));
+    s.insert_reserved(reservation, 7);
+    s.set_time_to_live(Some(Duration::from_millis(100)));
+    thread::sleep(Duration::from_millis(60));
+    s.insert(3, 3);
+    assert!(s.rename_key(&3, 3).unwrap());
+    thread::sleep(Duration::from_millis(60));
+    assert!(s.contains_key(&3));
+    assert!(!s.contains_key(&5));
+}
+
+#[test]
+fn bulk_removals_release_weight() {
+    let weighted = || {
+        let mut s: LendingLibrary<i32, usize> = LendingLibrary::new();
+        s.set_weigher(|_, v: &usize| *v);
+        s.set_max_weight(Some(100));
+        s.insert(1, 10);
+        s.insert(2, 5);
+        s
+    };
+    let mut s = weighted();
+    s.retain(|k, _| *k == 2);
+    assert_eq!(s.total_weight(), 5);
+    let mut s = weighted();
+    assert_eq!(s.extract_if(|k, _| *k == 1).count(), 1);
+    assert_eq!(s.total_weight(), 5);
+    let mut s = weighted();
+    s.clear();
+    assert_eq!(s.total_weight(), 0);
+    let mut s = weighted();
+    assert_eq!(s.drain().count(), 2);
+    assert_eq!(s.total_weight(), 0);
+    let mut s = weighted();
+    let other = s.split_off(|k| *k == 1).unwrap();
+    assert_eq!(s.total_weight(), 5);
+    assert_eq!(other.len(), 1);
+    let mut s = weighted();
+    let mut t = LendingLibrary::new();
+    t.set_weigher(|_, v: &usize| *v);
+    t.set_max_weight(Some(100));
+    t.append(&mut s);
+    assert_eq!(s.total_weight(), 0);
+    assert_eq!(t.total_weight(), 15);
+}
+
+#[test]
+fn reweighing_keeps_loaned_weight() {
+    let mut s: LendingLibrary<i32, usize> = LendingLibrary::new();
+    s.set_weigher(|_, v: &usize| *v);
+    s.set_max_weight(Some(100));
+    s.insert(1, 10);
+    s.insert(2, 5);
+    {
+        let _v = s.lend(&1).unwrap();
+        s.set_max_weight(Some(50));
+        assert_eq!(s.total_weight(), 15);
+        s.set_weigher(|_, v: &usize| 2 * *v);
+        assert_eq!(s.total_weight(), 20);
+    }
+    assert_eq!(s.total_weight(), 30);
+}
+
+#[test]
+fn expired_entries_hidden_from_views() {
+    let mut s: LendingLibrary<i32, i32> = LendingLibrary::new();
+    s.insert(1, 1);
+    s.set_time_to_live(Some(Duration::from_millis(5)));
+    s.insert(2, 2);
+    let _v = s.lend(&2).unwrap();
+    thread::sleep(Duration::from_millis(15));
+    assert!(!s.contains_key(&1));
+    assert_eq!(s.len(), 1);
+    assert_eq!(s.values().count(), 0);
+    assert_eq!(s.keys().collect::<Vec<_>>(), vec![&2]);
+    assert_eq!(s.key_set().len(), 1);
+    assert_eq!(format!("{:?}", s), "{2: <loaned>}");
+    drop(_v);
+    assert_eq!(s.iter().count(), 0);
+    assert_eq!(s.try_iter().unwrap().count(), 0);
+    assert!(s.is_empty());
+}
+
+#[test]
+fn projected_loans_dirty_on_write() {
+    let mut s: LendingLibrary<i64, (Vec<i64>, i64)> = LendingLibrary::new();
+    s.insert(1, (vec![], 0));
+    s.insert(2, (vec![], 0));
+    s.insert(3, (vec![], 0));
+    s.insert(4, (vec![], 0));
+    {
+        let a = Loan::map(s.lend(&1).unwrap(), |p| &mut p.0);
+        assert_eq!(a.len(), 0);
+        let _b = Loan::try_map(s.lend(&2).unwrap(), |p| Some(&mut p.1)).unwrap();
+        let (mut c, d) = Loan::split(s.lend(&3).unwrap());
+        drop(d);
+        c.push(3);
+        let (e, f) = Loan::split(s.lend(&4).unwrap());
+        let mut f = MappedLoan::map(f, |f| f);
+        *f += 1;
+        drop(f);
+        drop(e);
+    }
+    let mut keys = s.drain_dirty_keys();
+    keys.sort();
+    assert_eq!(keys, vec![&3, &4]);
+}
+
+#[test]
+fn evicted_keys_are_not_dirty() {
+    let mut s: LendingLibrary<i64, i64> = LendingLibrary::new();
+    s.set_max_entries(Some(1));
+    s.insert(1, 1);
+    *s.lend(&1).unwrap() += 1;
+    s.insert(2, 2);
+    assert!(!s.contains_key(&1));
+    s.insert(1, 1);
+    assert!(s.drain_dirty_keys().is_empty());
+}
@@ -0,0 +1,234 @@
+/* Notice
+vec.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+//! A positional, sequence variant of `LendingLibrary`.
+
+use super::_panic;
+use std::{fmt::{Debug, Error as FmtError, Formatter},
+          iter::FromIterator,
+          ops::{Deref, DerefMut},
+          sync::atomic::{AtomicUsize, Ordering},
+          thread};
+
+/// A contiguous sequence that lends temporary ownership of its elements, with the same loan
+/// semantics as [`LendingLibrary`].
+///
+/// Elements are addressed by position. While an element is loaned its position is held open, so
+/// the positions of other elements never change underneath a loan.
+///
+/// [`LendingLibrary`]: ../struct.LendingLibrary.html
+/// # Example
+/// ```
+/// use lending_library::vec::LendingVec;
+/// let mut lib: LendingVec<i32> = LendingVec::new();
+/// lib.push(1);
+/// lib.push(2);
+/// {
+///     let mut v = lib.lend(1).unwrap();
+///     *v += 10;
+///     let seen: Vec<_> = lib.iter().collect();
+///     assert_eq!(seen, vec![Some(&1), None]);
+/// }
+/// assert_eq!(*lib.lend(1).unwrap(), 12);
+/// ```
+pub struct LendingVec<V> {
+    store: Vec<Option<V>>,
+    outstanding: AtomicUsize,
+    name: Option<String>,
+}
+
+/// A smart pointer representing the loan of an element from a `LendingVec` instance.
+pub struct Loan<V> {
+    owner: *mut LendingVec<V>,
+    index: usize,
+    inner: Option<V>,
+}
+
+impl<V> LendingVec<V> {
+    /// Creates a new empty `LendingVec`.
+    /// # Example
+    /// ```
+    /// use lending_library::vec::LendingVec;
+    /// let mut lib: LendingVec<i32> = LendingVec::new();
+    /// ```
+    pub fn new() -> LendingVec<V> {
+        LendingVec::with_capacity(0)
+    }
+
+    /// Creates an empty `LendingVec` with room for at least `capacity` elements without
+    /// reallocating.
+    pub fn with_capacity(capacity: usize) -> LendingVec<V> {
+        LendingVec {
+            store: Vec::with_capacity(capacity),
+            outstanding: AtomicUsize::new(0),
+            name: None,
+        }
+    }
+
+    /// Creates a new empty `LendingVec` with a name, which is included in any panic messages it
+    /// raises.
+    pub fn named<N: Into<String>>(name: N) -> LendingVec<V> {
+        let mut lib = LendingVec::new();
+        lib.name = Some(name.into());
+        lib
+    }
+
+    /// Returns the name given to the library by [`named()`], if any.
+    ///
+    /// [`named()`]: #method.named
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the number of elements in the sequence, including those currently loaned.
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Returns true if the sequence is empty, including loaned elements.
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Appends an element to the end of the sequence, returning its position.
+    pub fn push(&mut self, val: V) -> usize {
+        self.store.push(Some(val));
+        self.store.len() - 1
+    }
+
+    /// Removes the last element from the sequence and returns it, or `None` if it is empty.
+    /// # Panics
+    /// Will panic if the last element is currently loaned.
+    pub fn pop(&mut self) -> Option<V> {
+        match self.store.pop() {
+            Some(Some(v)) => Some(v),
+            Some(None) => _panic(&self.name, "Cannot take loaned value"),
+            None => None,
+        }
+    }
+
+    /// Returns true if the element at `index` is currently loaned.
+    pub fn is_loaned(&self, index: usize) -> bool {
+        matches!(self.store.get(index), Some(None))
+    }
+
+    /// Returns a mutable reference to the element at `index`, returning `None` if the index is
+    /// out of bounds or the element is currently loaned.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut V> {
+        self.store.get_mut(index).and_then(Option::as_mut)
+    }
+
+    /// Loans the element at `index` from the sequence, returning `None` if the index is out of
+    /// bounds.
+    /// # Panics
+    /// Will panic if you try and loan an element that still has an outstanding loan.
+    pub fn lend(&mut self, index: usize) -> Option<Loan<V>> {
+        let ptr: *mut Self = self;
+        let val = match self.store.get_mut(index)?.take() {
+            Some(v) => v,
+            None => _panic(&self.name, "Lending already loaned value"),
+        };
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Some(Loan {
+            owner: ptr,
+            index,
+            inner: Some(val),
+        })
+    }
+
+    /// An iterator visiting every position in order, yielding `None` for elements that are
+    /// currently loaned.
+    pub fn iter(&self) -> impl Iterator<Item = Option<&V>> {
+        self.store.iter().map(Option::as_ref)
+    }
+
+    /// An iterator visiting every position in order with mutable references to the elements,
+    /// yielding `None` for elements that are currently loaned.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = Option<&mut V>> {
+        self.store.iter_mut().map(Option::as_mut)
+    }
+
+    fn checkin(&mut self, index: usize, val: V) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        match self.store.get_mut(index) {
+            Some(slot @ &mut None) => *slot = Some(val),
+            Some(_) => _panic(&self.name, "Returning replaced item"),
+            None => _panic(&self.name, "Returning item not from store"),
+        }
+    }
+}
+
+impl<V> Drop for LendingVec<V> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let count = self.outstanding.load(Ordering::SeqCst);
+            if count != 0 {
+                _panic(&self.name, &format!("{} value loans outlived store.", count))
+            }
+        }
+    }
+}
+
+impl<V> Default for LendingVec<V> {
+    fn default() -> Self {
+        LendingVec::new()
+    }
+}
+
+impl<V> FromIterator<V> for LendingVec<V> {
+    fn from_iter<I: IntoIterator<Item = V>>(iter: I) -> Self {
+        LendingVec {
+            store: iter.into_iter().map(Some).collect(),
+            outstanding: AtomicUsize::new(0),
+            name: None,
+        }
+    }
+}
+
+impl<V> Debug for Loan<V>
+where
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        <V as Debug>::fmt(self, f)
+    }
+}
+
+impl<V> PartialEq for Loan<V>
+where
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<V> Drop for Loan<V> {
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            unsafe {
+                (*self.owner).checkin(self.index, self.inner.take().unwrap());
+            }
+        }
+    }
+}
+
+impl<V> Deref for Loan<V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+impl<V> DerefMut for Loan<V> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.inner.as_mut().unwrap()
+    }
+}
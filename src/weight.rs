@@ -0,0 +1,85 @@
+/* Notice
+weight.rs: lending-library
+
+Copyright 2018 Thomas Bytheway <thomas.bytheway@cl.cam.ac.uk>
+
+This file is part of the lending-library open-source project: github.com/harkonenbade/lending-library;
+Its licensing is governed by the LICENSE file at the root of the project.
+*/
+
+use std::{collections::HashMap,
+          sync::Arc};
+
+/// A function giving the weight of a key/value pair.
+pub(super) type Weigher<K, V> = Arc<dyn Fn(&K, &V) -> usize + Send + Sync>;
+
+/// Tracks the weights of the entries of a library, identified by key hash, along with their total
+/// and the share of it that is currently loaned.
+#[derive(Clone, Default)]
+pub(super) struct Weights {
+    of: HashMap<u64, usize>,
+    total: usize,
+    loaned: usize,
+}
+
+impl Weights {
+    /// Records the weight of the entry `hash`, replacing any previous weight.
+    pub(super) fn set(&mut self, hash: u64, weight: usize) {
+        if let Some(old) = self.of.insert(hash, weight) {
+            self.total -= old;
+        }
+        self.total += weight;
+    }
+
+    /// Returns the recorded weight of the entry `hash`, or zero if it has none.
+    pub(super) fn get(&self, hash: u64) -> usize {
+        self.of.get(&hash).cloned().unwrap_or(0)
+    }
+
+    /// Stops tracking `hash`.
+    pub(super) fn forget(&mut self, hash: u64) {
+        if let Some(old) = self.of.remove(&hash) {
+            self.total -= old;
+        }
+    }
+
+    /// Counts the entry `hash` as loaned.
+    pub(super) fn lend(&mut self, hash: u64) {
+        self.loaned += self.get(hash);
+    }
+
+    /// Stops counting the entry `hash` as loaned.
+    pub(super) fn release(&mut self, hash: u64) {
+        self.loaned = self.loaned.saturating_sub(self.get(hash));
+    }
+
+    pub(super) fn total(&self) -> usize {
+        self.total
+    }
+
+    pub(super) fn loaned(&self) -> usize {
+        self.loaned
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.of.len()
+    }
+
+    /// Stops tracking every entry for which `f` returns false.
+    pub(super) fn retain<F: FnMut(u64) -> bool>(&mut self, mut f: F) {
+        let total = &mut self.total;
+        self.of.retain(|h, w| {
+            let keep = f(*h);
+            if !keep {
+                *total -= *w;
+            }
+            keep
+        });
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.of.clear();
+        self.total = 0;
+        self.loaned = 0;
+    }
+}